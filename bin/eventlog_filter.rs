@@ -0,0 +1,134 @@
+//! Writes a smaller eventlog with events in a time window, of selected capabilities or of
+//! selected event types
+
+use ghc_utils::eventlog::{open_eventlog, EventFilter, EventLogWriter};
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::BufWriter;
+
+use clap::{App, Arg, ArgMatches};
+
+fn main() {
+    let args = App::new("eventlog-filter")
+        .about(
+            "Keeps events in a time window, of selected capabilities, or of selected event types \
+             in a GHC eventlog. Events describing the run (program arguments, capabilities, info \
+             tables, ...) are kept outside of the time window.",
+        )
+        .arg(Arg::with_name("input").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("output")
+                .takes_value(true)
+                .required_unless("list-types"),
+        )
+        .arg(
+            Arg::with_name("from")
+                .help("Start of the time window, in seconds")
+                .long("from")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("to")
+                .help("End of the time window, in seconds")
+                .long("to")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cap")
+                .help("Capability to keep events of. Can be used multiple times.")
+                .long("cap")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("event-type")
+                .help("Id of an event type to keep, see --list-types. Can be used multiple times.")
+                .long("event-type")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("list-types")
+                .help("Print event types in the eventlog header")
+                .long("list-types"),
+        )
+        .get_matches();
+
+    let input = args.value_of("input").unwrap();
+    let mut reader = open_eventlog(input).unwrap_or_else(|err| {
+        eprintln!("Unable to read eventlog {}: {}", input, err);
+        std::process::exit(1);
+    });
+
+    if args.is_present("list-types") {
+        for ty in &reader.header().event_types {
+            println!("{}\t{}", ty.id, ty.description);
+        }
+        return;
+    }
+
+    let filter = EventFilter {
+        from: args.value_of("from").map(|from| parse_time("--from", from)),
+        to: args.value_of("to").map(|to| parse_time("--to", to)),
+        caps: parse_set(&args, "cap"),
+        event_types: parse_set(&args, "event-type"),
+    };
+
+    let output = args.value_of("output").unwrap();
+    let file = File::create(output).unwrap_or_else(|err| {
+        eprintln!("Unable to create {}: {}", output, err);
+        std::process::exit(1);
+    });
+    let mut writer =
+        EventLogWriter::new(BufWriter::new(file), reader.header()).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        });
+
+    let mut n_events = 0;
+    let mut n_kept = 0;
+    let copy_events = || -> Result<(), String> {
+        while let Some(event) = reader.next_event()? {
+            n_events += 1;
+            if filter.keep(&event) {
+                n_kept += 1;
+                // Copy the original payload, to keep fields the reader doesn't decode
+                writer.write_event_payload(&event, reader.payload())?;
+            }
+        }
+        writer.finish().map(|_| ())
+    };
+    if let Err(err) = copy_events() {
+        eprintln!("Unable to filter {}: {}", input, err);
+        std::process::exit(1);
+    }
+
+    eprintln!("Kept {} of {} events", n_kept, n_events);
+}
+
+/// Parses seconds as nanoseconds
+fn parse_time(flag: &str, s: &str) -> u64 {
+    match s.parse::<f64>() {
+        Ok(secs) if secs >= 0.0 => (secs * 1_000_000_000.0) as u64,
+        _ => {
+            eprintln!("Unable to parse {}: {}", flag, s);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn parse_set(args: &ArgMatches, arg: &str) -> Option<HashSet<u16>> {
+    args.values_of(arg).map(|values| {
+        values
+            .map(|value| {
+                value.parse::<u16>().unwrap_or_else(|err| {
+                    eprintln!("Unable to parse --{}: {}: {}", arg, value, err);
+                    std::process::exit(1);
+                })
+            })
+            .collect()
+    })
+}
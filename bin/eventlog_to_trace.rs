@@ -0,0 +1,26 @@
+//! Converts a GHC eventlog to a Chrome trace (JSON), for viewing in chrome://tracing or Perfetto
+
+use ghc_utils::eventlog::{eventlog_trace_events, open_eventlog, write_chrome_trace};
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("eventlog-to-trace")
+        .about(
+            "Converts a GHC eventlog to a Chrome trace: capabilities are tracks with threads and \
+             GCs as slices, GCs with their generations on a separate track, user messages and \
+             markers as instant events, heap size and live bytes as counters",
+        )
+        .arg(Arg::with_name("file").takes_value(true).required(true))
+        .get_matches();
+
+    let file = args.value_of("file").unwrap();
+    let events = open_eventlog(file)
+        .and_then(eventlog_trace_events)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read eventlog {}: {}", file, err);
+            std::process::exit(1);
+        });
+
+    write_chrome_trace(&events, &mut std::io::stdout().lock());
+}
@@ -1,9 +1,13 @@
-//! fs-compare <dir1> <dir2> [<file extension>]
+//! fs-compare <dir1> <dir2> [<file extension>] [-p] [--contents]
 //!
 //! Compares sizes of files with the given extension (all files if extension is not given).
+//!
+//! With `--contents`, files with the same size are also compared byte by byte, to tell files that
+//! are identical from files that were rewritten with the same size.
 
 use std::collections::HashMap;
 use std::fs;
+use std::io::Read;
 use std::path::Path;
 
 use clap::{App, Arg};
@@ -69,26 +73,93 @@ fn file_sizes(root: &Path, dir: &Path, ext: Option<&str>, files: &mut HashMap<St
     }
 }
 
-fn compare_files(f1: HashMap<String, u64>, mut f2: HashMap<String, u64>, sort_p: bool) {
-    // bool: whether the file exists in both dirs
-    let mut diffs: Vec<(String, i64, Option<f64>, bool)> =
+/// Reads until `buf` is full or the end of the file, returns the number of bytes read
+fn fill<R: Read>(reader: &mut R, buf: &mut [u8]) -> std::io::Result<usize> {
+    let mut n = 0;
+    while n < buf.len() {
+        match reader.read(&mut buf[n..])? {
+            0 => break,
+            m => n += m,
+        }
+    }
+    Ok(n)
+}
+
+/// Whether the files have the same contents
+fn same_contents(path1: &Path, path2: &Path) -> Result<bool, String> {
+    let open = |path: &Path| {
+        fs::File::open(path).map_err(|err| format!("Error when opening {:?}: {:?}", path, err))
+    };
+    let (mut file1, mut file2) = (open(path1)?, open(path2)?);
+    let mut buf1 = vec![0; 64 * 1024];
+    let mut buf2 = vec![0; 64 * 1024];
+    loop {
+        let n1 = fill(&mut file1, &mut buf1)
+            .map_err(|err| format!("Error when reading {:?}: {:?}", path1, err))?;
+        let n2 = fill(&mut file2, &mut buf2)
+            .map_err(|err| format!("Error when reading {:?}: {:?}", path2, err))?;
+        if n1 != n2 || buf1[..n1] != buf2[..n2] {
+            return Ok(false);
+        }
+        if n1 == 0 {
+            return Ok(true);
+        }
+    }
+}
+
+/// Number of files in each category, when comparing contents
+#[derive(Default)]
+struct Summary {
+    identical: usize,
+    same_size: usize,
+    size_changed: usize,
+    added: usize,
+    removed: usize,
+    /// Files with the same size that couldn't be compared
+    unreadable: usize,
+}
+
+fn compare_files(
+    f1: HashMap<String, u64>,
+    mut f2: HashMap<String, u64>,
+    sort_p: bool,
+    content_roots: Option<(&Path, &Path)>,
+) {
+    // char: '~' if the file exists in both dirs and the size changed, '=' if the size is the same
+    // but the contents changed, '+' or '-' if the file was added or removed
+    let mut diffs: Vec<(String, i64, Option<f64>, char)> =
         Vec::with_capacity(std::cmp::max(f1.len(), f2.len()));
 
     let mut total1 = 0;
     let mut total2 = 0;
+    let mut summary = Summary::default();
 
     for (k, v1) in f1.into_iter() {
         total1 += v1;
         match f2.remove(&k) {
             None => {
-                diffs.push((k, -(v1 as i64), None, false));
+                summary.removed += 1;
+                diffs.push((k, -(v1 as i64), None, '-'));
             }
             Some(v2) => {
                 total2 += v2;
                 if v1 != v2 {
+                    summary.size_changed += 1;
                     let diff = (v2 as i64) - (v1 as i64);
                     let p = ((diff as f64) / (v1 as f64)) * 100f64;
-                    diffs.push((k, diff, Some(p), true))
+                    diffs.push((k, diff, Some(p), '~'))
+                } else if let Some((root1, root2)) = content_roots {
+                    match same_contents(&root1.join(&k), &root2.join(&k)) {
+                        Ok(true) => summary.identical += 1,
+                        Ok(false) => {
+                            summary.same_size += 1;
+                            diffs.push((k, 0, Some(0f64), '='));
+                        }
+                        Err(err) => {
+                            eprintln!("{}", err);
+                            summary.unreadable += 1;
+                        }
+                    }
                 }
             }
         }
@@ -96,7 +167,8 @@ fn compare_files(f1: HashMap<String, u64>, mut f2: HashMap<String, u64>, sort_p:
 
     for (k, v2) in f2.into_iter() {
         total2 += v2;
-        diffs.push((k, v2 as i64, None, false));
+        summary.added += 1;
+        diffs.push((k, v2 as i64, None, '+'));
     }
 
     // Sort the vector based on diff size or percentage
@@ -106,15 +178,7 @@ fn compare_files(f1: HashMap<String, u64>, mut f2: HashMap<String, u64>, sort_p:
         diffs.sort_by_key(|&(_, v, _, _)| std::cmp::Reverse(v));
     }
 
-    for (path, diff, p, exists_both) in diffs.into_iter() {
-        let sign = if exists_both {
-            '~'
-        } else if diff > 0 {
-            '+'
-        } else {
-            '-'
-        };
-
+    for (path, diff, p, sign) in diffs.into_iter() {
         match p {
             None => {
                 println!("[{}] {}: {:+}", sign, path, diff);
@@ -128,11 +192,25 @@ fn compare_files(f1: HashMap<String, u64>, mut f2: HashMap<String, u64>, sort_p:
     let total_diff = (total2 as i64) - (total1 as i64);
     let total_p = ((total_diff as f64) / (total1 as f64)) * 100f64;
     println!("TOTAL: {} ({:.2}%)", total_diff, total_p);
+
+    if content_roots.is_some() {
+        println!("identical: {}", summary.identical);
+        println!("same size, different contents: {}", summary.same_size);
+        println!("size changed: {}", summary.size_changed);
+        println!("added: {}", summary.added);
+        println!("removed: {}", summary.removed);
+        if summary.unreadable != 0 {
+            println!("unreadable: {}", summary.unreadable);
+        }
+    }
 }
 
 fn main() {
     let args = App::new("fs-compare")
-        .about("Compares sizes of files with the given extension (all files if extension is not given)")
+        .about(
+            "Compares sizes of files with the given extension (all files if extension is not \
+             given). With --contents also compares contents of files with the same size.",
+        )
         .arg(Arg::with_name("dir_1").takes_value(true).required(true))
         .arg(Arg::with_name("dir_2").takes_value(true).required(true))
         .arg(Arg::with_name("ext").takes_value(true).required(false))
@@ -143,6 +221,14 @@ fn main() {
                 .required(false)
                 .short("p"),
         )
+        .arg(
+            Arg::with_name("contents")
+                .help(
+                    "Compare contents of files with the same size, and show counts of \
+                     identical, same size but different, size changed, added and removed files",
+                )
+                .long("contents"),
+        )
         .get_matches();
 
     let dir1 = args.value_of("dir_1").unwrap();
@@ -158,5 +244,34 @@ fn main() {
     let dir2_path = Path::new(dir2);
     file_sizes(dir2_path, dir2_path, ext, &mut files2);
 
-    compare_files(files1, files2, sort_p);
+    let content_roots = if args.is_present("contents") {
+        Some((dir1_path, dir2_path))
+    } else {
+        None
+    };
+    compare_files(files1, files2, sort_p, content_roots);
+}
+
+#[test]
+fn same_contents_test() {
+    let dir = std::env::temp_dir().join(format!("fs-compare-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let write = |name: &str, contents: &[u8]| {
+        let path = dir.join(name);
+        fs::write(&path, contents).unwrap();
+        path
+    };
+
+    // Bigger than the buffer, differing in the last chunk
+    let mut contents = vec![7u8; 100 * 1024];
+    let a = write("a", &contents);
+    let b = write("b", &contents);
+    contents[90 * 1024] = 8;
+    let c = write("c", &contents);
+
+    assert_eq!(same_contents(&a, &b), Ok(true));
+    assert_eq!(same_contents(&a, &c), Ok(false));
+    assert!(same_contents(&a, &dir.join("missing")).is_err());
+
+    fs::remove_dir_all(&dir).unwrap();
 }
@@ -0,0 +1,49 @@
+//! Reports GC pauses and heap sizes from a GHC eventlog (`+RTS -l`)
+
+use ghc_utils::eventlog::{open_eventlog, read_gcs, write_gc_summary, write_gcs};
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("ghc-gc-report")
+        .about(
+            "Shows GC pause percentiles, GC time per generation and the longest pauses in a GHC \
+             eventlog",
+        )
+        .arg(Arg::with_name("file").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("all")
+                .help("Print generation, pause, bytes copied, live bytes and heap size of every GC")
+                .long("all"),
+        )
+        .arg(
+            Arg::with_name("longest")
+                .help("Number of longest pauses to show")
+                .long("longest")
+                .takes_value(true)
+                .default_value("10"),
+        )
+        .get_matches();
+
+    let file = args.value_of("file").unwrap();
+    let longest = args.value_of("longest").unwrap();
+    let longest = longest.parse::<usize>().unwrap_or_else(|err| {
+        eprintln!("Unable to parse --longest: {}: {}", longest, err);
+        std::process::exit(1);
+    });
+
+    let gcs = open_eventlog(file)
+        .and_then(read_gcs)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read eventlog {}: {}", file, err);
+            std::process::exit(1);
+        });
+
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+    if args.is_present("all") {
+        write_gcs(&gcs, &mut stdout_lock);
+    } else {
+        write_gc_summary(&gcs, longest, &mut stdout_lock);
+    }
+}
@@ -0,0 +1,100 @@
+//! Shows residency of bands in a GHC heap profile (`.hp`, `+RTS -h*`), compares two heap
+//! profiles, or renders a heap profile as an SVG area chart. Heap profiles can also be read from
+//! eventlogs (`+RTS -l -h*`).
+
+use ghc_utils::eventlog::{open_eventlog, read_heap_profile};
+use ghc_utils::hp::{
+    parse_hp_file, summarize, write_hp, write_hp_svg, write_summary, write_summary_diff, HpFile,
+    HpSvgOptions,
+};
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("ghc-hp-compare")
+        .about(
+            "Shows peak and average residency of bands in a GHC heap profile (`.hp` or \
+             `.eventlog`), or compares residencies in two heap profiles",
+        )
+        .arg(Arg::with_name("file_1").takes_value(true).required(true))
+        .arg(Arg::with_name("file_2").takes_value(true).required(false))
+        .arg(
+            Arg::with_name("svg")
+                .help("Print an SVG stacked area chart of the heap profile")
+                .long("svg")
+                .conflicts_with("file_2"),
+        )
+        .arg(
+            Arg::with_name("hp")
+                .help("Print the heap profile in `.hp` format, e.g. to convert an eventlog")
+                .long("hp")
+                .conflicts_with_all(&["file_2", "svg"]),
+        )
+        .arg(
+            Arg::with_name("top")
+                .help("Number of bands to show in the chart, the rest are shown as OTHER")
+                .long("top")
+                .takes_value(true)
+                .default_value("15")
+                .requires("svg"),
+        )
+        .get_matches();
+
+    let file_1 = args.value_of("file_1").unwrap();
+    let hp1 = load_hp(file_1);
+
+    if args.is_present("hp") {
+        write_hp(&hp1, &mut std::io::stdout().lock());
+        return;
+    }
+
+    if args.is_present("svg") {
+        let top = args.value_of("top").unwrap();
+        let top = top.parse::<usize>().unwrap_or_else(|err| {
+            eprintln!("Unable to parse --top: {}: {}", top, err);
+            std::process::exit(1);
+        });
+        let opts = HpSvgOptions {
+            top,
+            title: if hp1.job.is_empty() {
+                file_1.to_owned()
+            } else {
+                hp1.job.clone()
+            },
+        };
+        write_hp_svg(&hp1, &opts, &mut std::io::stdout().lock());
+        return;
+    }
+
+    let summary1 = summarize(&hp1);
+
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+    match args.value_of("file_2") {
+        None => write_summary(&hp1, &summary1, &mut stdout_lock),
+        Some(file_2) => {
+            let hp2 = load_hp(file_2);
+            if hp1.value_unit != hp2.value_unit {
+                eprintln!(
+                    "WARNING: Heap profiles have different value units: {} and {}",
+                    hp1.value_unit, hp2.value_unit
+                );
+            }
+            write_summary_diff(&summary1, &summarize(&hp2), &mut stdout_lock);
+        }
+    }
+}
+
+/// Reads a `.hp` file, or heap profile samples in an eventlog when the file name ends with
+/// `.eventlog`
+fn load_hp(path: &str) -> HpFile {
+    if !path.ends_with(".eventlog") {
+        return parse_hp_file(path);
+    }
+    open_eventlog(path)
+        .and_then(read_heap_profile)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read heap profile in {}: {}", path, err);
+            std::process::exit(1);
+        })
+}
@@ -0,0 +1,915 @@
+//! Utilities for working on GHC's prof JSON dumps (`+RTS -pj`)
+//!
+//! Shows or compares allocations, generates folded stacks, renders flame graphs, or browses
+//! cost-centre trees interactively.
+
+mod repl;
+mod table;
+
+use ghc_utils::prof::{
+    call_graph, collapse_cost_centres, cost_centre_stats, cost_map, fold_stacks, header_warnings,
+    match_cost_centres, merge_profiles, metric_total, parse_prof_file, share_diffs,
+    write_call_graph, write_call_graph_diff, write_diff_folded, write_flamegraph, write_folded,
+    write_header, write_header_diff, write_html_report, write_share_diffs, CallGraph, CollapseMode,
+    CollapseRule, CostCentre, CostStats, FlameGraphOptions, ListingOptions, MatchKind, MergeMode,
+    Metric, ProfFile, ShareDiff,
+};
+
+use ghc_utils::utils::percent_change;
+
+use std::collections::HashMap;
+
+use table::{Cell, Format, Table};
+
+use clap::{App, Arg, ArgMatches};
+use regex::Regex;
+
+/// Cost centres in an allocation map, with their allocations
+fn cost_centres_with_allocs<'a>(
+    allocs: &HashMap<String, u64>,
+    ccs: &HashMap<String, &'a CostCentre>,
+) -> Vec<(&'a CostCentre, u64)> {
+    let mut ret: Vec<(&CostCentre, u64)> = allocs
+        .iter()
+        .map(|(name, alloc)| (*ccs.get(name).unwrap(), *alloc))
+        .collect();
+    ret.sort_by_key(|(cc, _)| cc.id);
+    ret
+}
+
+/// Profiles of one side of a comparison. Profiles of multiple runs are merged.
+struct Side {
+    /// File names, for titles
+    name: String,
+    prof: ProfFile,
+    /// Statistics of the listed metric of cost centres, when there are multiple runs
+    stats: Option<HashMap<String, CostStats>>,
+}
+
+fn load_side(files: &[&str], mode: MergeMode, rules: &[CollapseRule], metric: Metric) -> Side {
+    let profs: Vec<ProfFile> = files
+        .iter()
+        .map(|f| {
+            let mut prof = parse_prof_file(f);
+            collapse_cost_centres(&mut prof, rules);
+            prof
+        })
+        .collect();
+    let stats = if profs.len() > 1 {
+        Some(cost_centre_stats(&profs, metric))
+    } else {
+        None
+    };
+    let prof = merge_profiles(profs, mode).unwrap_or_else(|err| {
+        eprintln!("Unable to merge {}: {}", files.join(", "), err);
+        std::process::exit(1);
+    });
+    Side {
+        name: files.join(", "),
+        prof,
+        stats,
+    }
+}
+
+impl Side {
+    fn cost_stats(&self, cc: &str) -> CostStats {
+        match self.stats.as_ref().and_then(|stats| stats.get(cc)) {
+            Some(stats) => *stats,
+            None => CostStats {
+                mean: 0.0,
+                stddev: 0.0,
+                n: 1,
+            },
+        }
+    }
+}
+
+fn compare(
+    side1: &Side,
+    side2: &Side,
+    metric: Metric,
+    opts: &ListingOptions,
+    // Match renamed cost centres, pairing by source location up to this many lines apart
+    fuzzy: Option<u64>,
+    format: Option<Format>,
+) {
+    let prof1 = &side1.prof;
+    let prof2 = &side2.prof;
+    for warning in header_warnings(prof1, prof2) {
+        eprintln!("WARNING: {}", warning);
+    }
+
+    let mut costs1 = cost_map(prof1, metric);
+    let mut costs2 = cost_map(prof2, metric);
+
+    let ccs1 = prof1.cost_centres_by_name();
+    let ccs2 = prof2.cost_centres_by_name();
+    let mut ccs = ccs2.clone();
+    ccs.extend(ccs1.iter().map(|(name, cc)| (name.clone(), *cc)));
+
+    let mut diffs: Vec<(String, i64)> = vec![];
+    // Costs before and after of the rows
+    let mut values: HashMap<String, (u64, u64)> = HashMap::new();
+
+    // Noise estimates of the diffs, when there are multiple runs
+    let with_noise = side1.stats.is_some() || side2.stats.is_some();
+    let mut noise: HashMap<String, f64> = HashMap::new();
+
+    if let Some(max_line_shift) = fuzzy {
+        // Match by allocation even when listing another metric, allocation does not change
+        // between runs
+        let matching = match_cost_centres(
+            &cost_centres_with_allocs(&cost_map(prof1, Metric::Alloc), &ccs1),
+            &cost_centres_with_allocs(&cost_map(prof2, Metric::Alloc), &ccs2),
+            max_line_shift,
+        );
+        for m in matching.matches {
+            if m.kind == MatchKind::Exact {
+                continue;
+            }
+            let cost1 = costs1.remove(&m.before.name()).unwrap();
+            let cost2 = costs2.remove(&m.after.name()).unwrap();
+            let name = format!(
+                "{} => {} [fuzzy: {}]",
+                m.before.name(),
+                m.after.name(),
+                m.kind
+            );
+            ccs.insert(name.clone(), m.after);
+            if with_noise {
+                let stats1 = side1.cost_stats(&m.before.name());
+                let stats2 = side2.cost_stats(&m.after.name());
+                noise.insert(name.clone(), stats1.noise(&stats2));
+            }
+            let diff = (cost2 as i64) - (cost1 as i64);
+            if diff != 0 {
+                values.insert(name.clone(), (cost1, cost2));
+                diffs.push((name, diff));
+            }
+        }
+    }
+
+    for (cc, cost1) in costs1.into_iter() {
+        let cost2 = costs2.remove(&cc).unwrap_or(0);
+        let diff = (cost2 as i64) - (cost1 as i64);
+        if diff != 0 {
+            values.insert(cc.clone(), (cost1, cost2));
+            diffs.push((cc, diff));
+        }
+    }
+
+    for (cc, cost2) in costs2.into_iter() {
+        if cost2 != 0 {
+            values.insert(cc.clone(), (0, cost2));
+            diffs.push((cc, cost2 as i64));
+        }
+    }
+
+    diffs.sort_by_key(|&(_, v)| std::cmp::Reverse(v));
+
+    let listing = opts.select_rows(diffs, &ccs, metric_total(prof1, metric));
+
+    let mut rows = vec![];
+    for (name, _) in listing.rows {
+        let (before, after) = values.remove(&name).unwrap();
+        let noise = if with_noise {
+            Some(match noise.get(&name) {
+                Some(noise) => *noise,
+                None => side1.cost_stats(&name).noise(&side2.cost_stats(&name)),
+            })
+        } else {
+            None
+        };
+        rows.push(DiffRow {
+            name,
+            before,
+            after,
+            noise,
+        });
+    }
+
+    let other = if listing.n_other != 0 {
+        // Rows that were not listed are left in `values`
+        let (before, after) = values
+            .values()
+            .fold((0, 0), |(b, a), (before, after)| (b + before, a + after));
+        Some(DiffRow {
+            name: format!("OTHER ({} cost centres)", listing.n_other),
+            before,
+            after,
+            noise: None,
+        })
+    } else {
+        None
+    };
+
+    let (before, after) = rows
+        .iter()
+        .chain(other.iter())
+        .fold((0, 0), |(b, a), row| (b + row.before, a + row.after));
+    let total = DiffRow {
+        name: "TOTAL".to_owned(),
+        before,
+        after,
+        noise: None,
+    };
+
+    match format {
+        None => {
+            for row in &rows {
+                match row.noise {
+                    Some(noise) => {
+                        let marker = if row.within_noise(noise) {
+                            " (noise)"
+                        } else {
+                            ""
+                        };
+                        println!("{}: {} ±{:.0}{}", row.name, row.diff(), noise, marker);
+                    }
+                    None => println!("{}: {}", row.name, row.diff()),
+                }
+            }
+            if let Some(other) = &other {
+                println!("{}: {}", other.name, other.diff());
+            }
+            println!();
+            println!("TOTAL: {}", total.diff());
+        }
+        Some(format) => {
+            let cells = |row: &DiffRow| {
+                let mut cells = vec![
+                    Cell::Str(row.name.clone()),
+                    Cell::Int(row.before as i64),
+                    Cell::Int(row.after as i64),
+                    Cell::Int(row.diff()),
+                    Cell::Percent(percent_change(row.before as f64, row.after as f64)),
+                ];
+                match row.noise {
+                    Some(noise) => {
+                        cells.push(Cell::Int(noise.round() as i64));
+                        cells.push(Cell::Bool(row.within_noise(noise)));
+                    }
+                    // No noise estimates for summary rows
+                    None if with_noise => {
+                        cells.push(Cell::Str(String::new()));
+                        cells.push(Cell::Str(String::new()));
+                    }
+                    None => {}
+                }
+                cells
+            };
+
+            let mut columns = vec!["cost_centre", "before", "after", "diff", "change"];
+            if with_noise {
+                columns.extend_from_slice(&["noise", "within_noise"]);
+            }
+            let table = Table {
+                columns,
+                rows: rows.iter().map(cells).collect(),
+                summary: other.iter().chain(Some(&total)).map(cells).collect(),
+            };
+            table.write(format, &mut std::io::stdout().lock());
+        }
+    }
+}
+
+/// A row of a comparison
+struct DiffRow {
+    name: String,
+    before: u64,
+    after: u64,
+    /// Noise estimate of the difference, when there are multiple runs. Not available for summary
+    /// rows.
+    noise: Option<f64>,
+}
+
+impl DiffRow {
+    fn diff(&self) -> i64 {
+        (self.after as i64) - (self.before as i64)
+    }
+
+    /// Differences within two standard errors are likely noise
+    fn within_noise(&self, noise: f64) -> bool {
+        (self.diff().unsigned_abs() as f64) < 2.0 * noise
+    }
+}
+
+/// A row of a listing of one profile
+struct CostRow {
+    name: String,
+    cost: i64,
+    /// Standard deviation over runs, when there are multiple runs. Not available for summary rows.
+    stddev: Option<f64>,
+}
+
+fn show_costs(side: &Side, metric: Metric, opts: &ListingOptions, format: Option<Format>) {
+    let prof = &side.prof;
+    let costs = cost_map(prof, metric);
+    let mut costs = costs
+        .into_iter()
+        .filter(|&(_, v)| v != 0)
+        .map(|(k, v)| (k, v as i64))
+        .collect::<Vec<(String, i64)>>();
+    costs.sort_by_key(|&(_, v)| std::cmp::Reverse(v));
+
+    let total: i64 = costs.iter().map(|&(_, v)| v).sum();
+    let total_f: f64 = total as f64;
+
+    let listing = opts.select_rows(costs, &prof.cost_centres_by_name(), total as u64);
+
+    let rows: Vec<CostRow> = listing
+        .rows
+        .into_iter()
+        .map(|(name, cost)| {
+            let stddev = side.stats.as_ref().map(|_| side.cost_stats(&name).stddev);
+            CostRow { name, cost, stddev }
+        })
+        .collect();
+    let other = if listing.n_other != 0 {
+        Some(CostRow {
+            name: format!("OTHER ({} cost centres)", listing.n_other),
+            cost: listing.other,
+            stddev: None,
+        })
+    } else {
+        None
+    };
+    let percentage = |row: &CostRow| ((row.cost as f64) / total_f) * 100.0f64;
+
+    match format {
+        None => {
+            for row in rows.iter().chain(other.iter()) {
+                match row.stddev {
+                    None => println!("{}: {} ({:.2}%)", row.name, row.cost, percentage(row)),
+                    Some(stddev) => println!(
+                        "{}: {} ±{:.0} ({:.2}%)",
+                        row.name,
+                        row.cost,
+                        stddev,
+                        percentage(row)
+                    ),
+                }
+            }
+            println!("TOTAL: {}", total);
+        }
+        Some(format) => {
+            let cells = |row: &CostRow| {
+                let mut cells = vec![Cell::Str(row.name.clone()), Cell::Int(row.cost)];
+                if side.stats.is_some() {
+                    cells.push(Cell::Int(row.stddev.unwrap_or(0.0).round() as i64));
+                }
+                cells.push(Cell::Share(percentage(row)));
+                cells
+            };
+
+            let mut columns = vec!["cost_centre", metric_column(metric)];
+            if side.stats.is_some() {
+                columns.push("stddev");
+            }
+            columns.push("percentage");
+
+            let total = CostRow {
+                name: "TOTAL".to_owned(),
+                cost: total,
+                stddev: None,
+            };
+            let table = Table {
+                columns,
+                rows: rows.iter().map(cells).collect(),
+                summary: other.iter().chain(Some(&total)).map(cells).collect(),
+            };
+            table.write(format, &mut std::io::stdout().lock());
+        }
+    }
+}
+
+fn compare_shares(side1: &Side, side2: &Side, metric: Metric, opts: &ListingOptions) {
+    let prof1 = &side1.prof;
+    let prof2 = &side2.prof;
+    for warning in header_warnings(prof1, prof2) {
+        eprintln!("WARNING: {}", warning);
+    }
+
+    let mut ccs = prof2.cost_centres_by_name();
+    ccs.extend(prof1.cost_centres_by_name());
+
+    // Same filtering as `compare`: thresholds are on the change in the metric, hidden rows are
+    // shown as OTHER
+    let min_value = opts.min_value(metric_total(prof1, metric));
+    let (mut diffs, mut other): (Vec<ShareDiff>, Vec<ShareDiff>) =
+        share_diffs(prof1, prof2, metric)
+            .into_iter()
+            .partition(|diff| {
+                ((diff.after as i64) - (diff.before as i64)).unsigned_abs() >= min_value
+                    && opts.matches(ccs.get(&diff.name).unwrap())
+            });
+    if let Some(top) = opts.top {
+        if diffs.len() > top {
+            other.extend(diffs.drain(top..));
+        }
+    }
+
+    let stdout = std::io::stdout();
+    write_share_diffs(prof1, prof2, metric, &diffs, &other, &mut stdout.lock());
+}
+
+/// Column name of a metric in tables
+fn metric_column(metric: Metric) -> &'static str {
+    match metric {
+        Metric::Entries => "entries",
+        Metric::Alloc => "alloc",
+        Metric::Ticks => "ticks",
+    }
+}
+
+fn show_folded(side: &Side, metric: Metric) {
+    let stacks = fold_stacks(&side.prof, metric);
+    write_folded(&stacks, &mut std::io::stdout().lock());
+}
+
+fn compare_folded(side1: &Side, side2: &Side, metric: Metric) {
+    let stacks1 = fold_stacks(&side1.prof, metric);
+    let stacks2 = fold_stacks(&side2.prof, metric);
+    write_diff_folded(&stacks1, &stacks2, &mut std::io::stdout().lock());
+}
+
+fn show_html_report(side1: &Side, side2: Option<&Side>) {
+    let (before, after, title) = match side2 {
+        None => (None, &side1.prof, side1.name.clone()),
+        Some(side2) => (
+            Some(&side1.prof),
+            &side2.prof,
+            format!("{} -> {}", side1.name, side2.name),
+        ),
+    };
+    write_html_report(before, after, &title, &mut std::io::stdout().lock());
+}
+
+fn show_flamegraph(side1: &Side, side2: Option<&Side>, metric: Metric, icicle: bool) {
+    let (before, after, title) = match side2 {
+        None => (None, &side1.prof, side1.name.clone()),
+        Some(side2) => (
+            Some(&side1.prof),
+            &side2.prof,
+            format!("{} -> {}", side1.name, side2.name),
+        ),
+    };
+    let opts = FlameGraphOptions {
+        metric,
+        icicle,
+        title,
+    };
+    write_flamegraph(before, after, &opts, &mut std::io::stdout().lock());
+}
+
+fn show_call_graph(side1: &Side, side2: Option<&Side>, cc: &str, metric: Metric) {
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+
+    let graph1 = call_graph(&side1.prof, cc, metric);
+    match side2 {
+        None => match graph1 {
+            None => {
+                eprintln!("{} does not appear in {}", cc, side1.name);
+                std::process::exit(1);
+            }
+            Some(graph) => write_call_graph(cc, &graph, &mut stdout_lock),
+        },
+        Some(side2) => {
+            let graph2 = call_graph(&side2.prof, cc, metric);
+            match (graph1, graph2) {
+                (None, None) => {
+                    eprintln!("{} does not appear in {} or {}", cc, side1.name, side2.name);
+                    std::process::exit(1);
+                }
+                (graph1, graph2) => {
+                    let empty = CallGraph::empty(metric);
+                    write_call_graph_diff(
+                        cc,
+                        graph1.as_ref().unwrap_or(&empty),
+                        graph2.as_ref().unwrap_or(&empty),
+                        &mut stdout_lock,
+                    );
+                }
+            }
+        }
+    }
+}
+
+fn parse_regex(re: &str) -> Regex {
+    Regex::new(re).unwrap_or_else(|err| {
+        eprintln!("Unable to parse regex {}: {}", re, err);
+        std::process::exit(1);
+    })
+}
+
+fn listing_options(args: &ArgMatches) -> ListingOptions {
+    let regex_arg = |arg| args.value_of(arg).map(parse_regex);
+
+    let is_caf = if args.is_present("caf") {
+        Some(true)
+    } else if args.is_present("no_caf") {
+        Some(false)
+    } else {
+        None
+    };
+
+    ListingOptions {
+        module: regex_arg("module"),
+        label: regex_arg("label"),
+        is_caf,
+        top: args.value_of("top").map(|n| parse_arg("top", n)),
+        min_abs: args
+            .value_of("min")
+            .map(|n| parse_arg("min", n))
+            .unwrap_or(0),
+        min_percent: args
+            .value_of("min_percent")
+            .map(|n| parse_arg("min-percent", n))
+            .unwrap_or(0.0),
+    }
+}
+
+/// Collapse rules, collapsed cost centres first
+fn collapse_rules(args: &ArgMatches) -> Vec<CollapseRule> {
+    let mut rules = vec![];
+    for (arg, mode, by_module) in &[
+        ("collapse_module", CollapseMode::Collapse, true),
+        ("collapse_label", CollapseMode::Collapse, false),
+        ("transparent_module", CollapseMode::Transparent, true),
+        ("transparent_label", CollapseMode::Transparent, false),
+    ] {
+        for re in args.values_of(arg).into_iter().flatten() {
+            let re = Some(parse_regex(re));
+            let (module, label) = if *by_module { (re, None) } else { (None, re) };
+            rules.push(CollapseRule {
+                module,
+                label,
+                mode: *mode,
+            });
+        }
+    }
+    rules
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: &str, value: &str) -> T {
+    value.parse().unwrap_or_else(|_| {
+        eprintln!("Unable to parse --{}: {}", arg, value);
+        std::process::exit(1);
+    })
+}
+
+fn main() {
+    let args = App::new("ghc-prof-compare")
+        .about(
+            "Shows costs (allocations by default, see --metric) in a GHC prof JSON dump \
+             (`+RTS -pj`), sorted, or compares costs in two dumps",
+        )
+        .arg(
+            Arg::with_name("file_1")
+                .takes_value(true)
+                .required_unless("before"),
+        )
+        .arg(Arg::with_name("file_2").takes_value(true).required(false))
+        .arg(
+            Arg::with_name("before")
+                .help(
+                    "A profile of the first program to show or compare. Can be given multiple \
+                     times to merge profiles of multiple runs.",
+                )
+                .long("before")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .conflicts_with_all(&["file_1", "file_2"]),
+        )
+        .arg(
+            Arg::with_name("after")
+                .help(
+                    "A profile of the second program to compare. Can be given multiple times to \
+                     merge profiles of multiple runs.",
+                )
+                .long("after")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1)
+                .requires("before"),
+        )
+        .arg(
+            Arg::with_name("sum")
+                .help("Sum costs of multiple runs instead of averaging")
+                .long("sum"),
+        )
+        .arg(
+            Arg::with_name("folded")
+                .help(
+                    "Print cost-centre stacks in folded stack format (differential format when \
+                     comparing two files), weighted by the given metric",
+                )
+                .long("folded")
+                .takes_value(true)
+                .possible_values(&["entries", "alloc", "ticks"]),
+        )
+        .arg(
+            Arg::with_name("flamegraph")
+                .help(
+                    "Print an SVG flame graph, weighted by the given metric. When comparing two \
+                     files frames are coloured by the change in the metric.",
+                )
+                .long("flamegraph")
+                .takes_value(true)
+                .possible_values(&["entries", "alloc", "ticks"])
+                .conflicts_with("folded"),
+        )
+        .arg(
+            Arg::with_name("html")
+                .help(
+                    "Print a self-contained HTML report with the header, biggest cost centres (or \
+                     changes when comparing two files), a sortable table of cost centres, and \
+                     the cost-centre tree",
+                )
+                .long("html")
+                .conflicts_with_all(&["folded", "flamegraph"]),
+        )
+        .arg(
+            Arg::with_name("header")
+                .help(
+                    "Show program, arguments, RTS flags and totals. When comparing two files \
+                     warns about differences that make the comparison questionable.",
+                )
+                .long("header")
+                .conflicts_with_all(&["folded", "flamegraph"]),
+        )
+        .arg(
+            Arg::with_name("repl")
+                .help(
+                    "Browse the cost-centre tree interactively. When two files are given `use` \
+                     switches between them.",
+                )
+                .long("repl")
+                .conflicts_with_all(&["folded", "flamegraph"]),
+        )
+        .arg(
+            Arg::with_name("call_graph")
+                .help(
+                    "Show stacks, callers and callees of a cost centre (`label` or \
+                     `module.label`), with changes when comparing two files",
+                )
+                .long("call-graph")
+                .takes_value(true)
+                .conflicts_with_all(&["folded", "flamegraph", "repl"]),
+        )
+        .arg(
+            Arg::with_name("metric")
+                .help(
+                    "Metric to list, compare (with noise estimates when profiles of multiple \
+                     runs are given) and use in call graphs and normalized comparisons",
+                )
+                .long("metric")
+                .takes_value(true)
+                .possible_values(&["entries", "alloc", "ticks"])
+                .default_value("alloc"),
+        )
+        .arg(
+            Arg::with_name("normalize")
+                .help(
+                    "Compare each cost centre's share of the total of the metric (given with \
+                     --metric), ranked by change in share (percentage points)",
+                )
+                .long("normalize")
+                .conflicts_with_all(&["folded", "flamegraph", "repl", "call_graph", "header"]),
+        )
+        .arg(
+            Arg::with_name("format")
+                .help(
+                    "Output format of listings and comparisons (default: text). `markdown` \
+                     generates a Gitlab table.",
+                )
+                .long("format")
+                .takes_value(true)
+                .possible_values(&["text", "csv", "json", "markdown"])
+                // No default value: clap takes defaults as given when checking conflicts
+                .conflicts_with("normalize"),
+        )
+        .arg(
+            Arg::with_name("icicle")
+                .help("Draw the flame graph upside down, with the root at the top")
+                .long("icicle")
+                .requires("flamegraph"),
+        )
+        .arg(
+            Arg::with_name("fuzzy")
+                .help(
+                    "Match cost centres that were renamed between builds: labels with different \
+                     uniques or numbers (paired by source location), and CAFs (paired by \
+                     allocation)",
+                )
+                .long("fuzzy"),
+        )
+        .arg(
+            Arg::with_name("max_line_shift")
+                .help(
+                    "Only pair renamed cost centres by source location when they are at most \
+                     this many lines apart (default: 20)",
+                )
+                .long("max-line-shift")
+                .takes_value(true)
+                .requires("fuzzy"),
+        )
+        .arg(
+            Arg::with_name("collapse_module")
+                .help(
+                    "Remove cost centres with modules matching the regex, charging them and \
+                     everything they call to the caller. Can be given multiple times.",
+                )
+                .long("collapse-module")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("collapse_label")
+                .help("Like --collapse-module, but matches labels")
+                .long("collapse-label")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("transparent_module")
+                .help(
+                    "Remove cost centres with modules matching the regex from stacks, charging \
+                     their own costs to the caller. Callees are kept. Can be given multiple \
+                     times.",
+                )
+                .long("transparent-module")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("transparent_label")
+                .help("Like --transparent-module, but matches labels")
+                .long("transparent-label")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("module")
+                .help("Only list cost centres with modules matching the regex")
+                .long("module")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("label")
+                .help("Only list cost centres with labels matching the regex")
+                .long("label")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("caf")
+                .help("Only list CAFs")
+                .long("caf")
+                .conflicts_with("no_caf"),
+        )
+        .arg(
+            Arg::with_name("no_caf")
+                .help("Do not list CAFs")
+                .long("no-caf"),
+        )
+        .arg(
+            Arg::with_name("top")
+                .help("Only list the N cost centres with largest (absolute) values")
+                .long("top")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min")
+                .help("Hide cost centres with (absolute) values smaller than this")
+                .long("min")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("min_percent")
+                .help(
+                    "Hide cost centres with (absolute) values smaller than this percentage of \
+                     the total (total of the first file when comparing)",
+                )
+                .long("min-percent")
+                .takes_value(true),
+        )
+        .get_matches();
+
+    let merge_mode = if args.is_present("sum") {
+        MergeMode::Sum
+    } else {
+        MergeMode::Average
+    };
+
+    let rules = collapse_rules(&args);
+    let metric = args.value_of("metric").unwrap().parse::<Metric>().unwrap();
+
+    let (side1, side2) = match args.values_of("before") {
+        None => (
+            load_side(
+                &[args.value_of("file_1").unwrap()],
+                merge_mode,
+                &rules,
+                metric,
+            ),
+            args.value_of("file_2")
+                .map(|file2| load_side(&[file2], merge_mode, &rules, metric)),
+        ),
+        Some(before) => (
+            load_side(&before.collect::<Vec<_>>(), merge_mode, &rules, metric),
+            args.values_of("after")
+                .map(|after| load_side(&after.collect::<Vec<_>>(), merge_mode, &rules, metric)),
+        ),
+    };
+
+    // Text is the default, other formats are rendered as tables
+    let format = match args.value_of("format") {
+        None | Some("text") => None,
+        Some(format) => Some(format.parse::<Format>().unwrap()),
+    };
+
+    let folded = args
+        .value_of("folded")
+        .map(|metric| metric.parse::<Metric>().unwrap());
+
+    if args.is_present("header") {
+        let stdout = std::io::stdout();
+        match side2 {
+            None => write_header(&side1.prof, &mut stdout.lock()),
+            Some(side2) => write_header_diff(&side1.prof, &side2.prof, &mut stdout.lock()),
+        }
+        return;
+    }
+
+    if args.is_present("html") {
+        show_html_report(&side1, side2.as_ref());
+        return;
+    }
+
+    if args.is_present("repl") {
+        let mut profiles = vec![(side1.name, side1.prof)];
+        if let Some(side2) = side2 {
+            profiles.push((side2.name, side2.prof));
+        }
+        repl::repl(profiles);
+        return;
+    }
+
+    if let Some(cc) = args.value_of("call_graph") {
+        show_call_graph(&side1, side2.as_ref(), cc, metric);
+        return;
+    }
+
+    if args.is_present("normalize") {
+        match side2 {
+            None => {
+                eprintln!("--normalize needs two profiles to compare");
+                std::process::exit(1);
+            }
+            Some(side2) => compare_shares(&side1, &side2, metric, &listing_options(&args)),
+        }
+        return;
+    }
+
+    if let Some(metric) = args.value_of("flamegraph") {
+        let metric = metric.parse::<Metric>().unwrap();
+        show_flamegraph(&side1, side2.as_ref(), metric, args.is_present("icicle"));
+        return;
+    }
+
+    match (side2, folded) {
+        (None, None) => {
+            show_costs(&side1, metric, &listing_options(&args), format);
+        }
+        (Some(side2), None) => {
+            compare(
+                &side1,
+                &side2,
+                metric,
+                &listing_options(&args),
+                if args.is_present("fuzzy") {
+                    Some(
+                        args.value_of("max_line_shift")
+                            .map(|n| parse_arg("max-line-shift", n))
+                            .unwrap_or(20),
+                    )
+                } else {
+                    None
+                },
+                format,
+            );
+        }
+        (None, Some(metric)) => {
+            show_folded(&side1, metric);
+        }
+        (Some(side2), Some(metric)) => {
+            compare_folded(&side1, &side2, metric);
+        }
+    }
+}
@@ -0,0 +1,416 @@
+//! An interactive browser for cost-centre trees
+
+use ghc_utils::prof::{call_graph, CallCosts, CostCentre, Metric, ProfFile, Profile};
+use ghc_utils::utils::percentage;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+use ansi_term::Style;
+use regex::Regex;
+use rustyline::error::ReadlineError;
+use rustyline::Editor;
+
+static HELP: &str = "\
+Commands:
+  ls [metric]          List children of the current node, sorted by inherited metric
+  cd <n|name>          Move to a child, by index in `ls` output or by (module.)label
+  cd ..  |  up [n]     Move to the parent (n levels up)
+  cd /                 Move to the root
+  pwd                  Show the current cost-centre stack
+  top [n] [metric]     Show cost centres with the highest own cost, summed over all stacks
+  find <regex>         Show stacks of cost centres with names matching the regex
+  callers <cc>         Show callers of a cost centre, with inherited costs through each caller
+  callees <cc>         Show callees of a cost centre, with their inherited costs
+  sort <metric>        Set the default metric (entries, alloc or ticks)
+  use <before|after>   Switch to the first or second profile, keeping the current stack
+  help                 Show this message";
+
+pub fn repl(profiles: Vec<(String, ProfFile)>) {
+    let mut state = State::new(&profiles);
+
+    println!("Loaded {}", state.profile_name());
+    println!("Type `help` for a list of commands");
+
+    let mut rl = Editor::<()>::new();
+
+    loop {
+        match rl.readline(">>> ") {
+            Ok(line) if line.trim().is_empty() => {}
+            Ok(line) => {
+                rl.add_history_entry(line.as_str());
+                state.run_command(&line, &mut std::io::stdout().lock());
+            }
+            Err(ReadlineError::Interrupted | ReadlineError::Eof) => {
+                break;
+            }
+            err @ Err(_) => {
+                println!("Error while reading line: {:?}", err);
+                println!("Aborting.");
+                break;
+            }
+        }
+    }
+}
+
+struct State<'a> {
+    profiles: &'a [(String, ProfFile)],
+    cc_maps: Vec<HashMap<u64, &'a CostCentre>>,
+    /// Index of the current profile in `profiles`
+    current: usize,
+    /// Path to the current node. Elements are indices of children.
+    path: Vec<usize>,
+    /// Default metric for sorting
+    metric: Metric,
+}
+
+impl<'a> State<'a> {
+    fn new(profiles: &'a [(String, ProfFile)]) -> State<'a> {
+        State {
+            profiles,
+            cc_maps: profiles.iter().map(|(_, f)| f.cost_centre_map()).collect(),
+            current: 0,
+            path: vec![],
+            metric: Metric::Alloc,
+        }
+    }
+
+    fn profile_name(&self) -> &str {
+        &self.profiles[self.current].0
+    }
+
+    fn root(&self) -> &'a Profile {
+        &self.profiles[self.current].1.profile
+    }
+
+    fn node(&self) -> &'a Profile {
+        let mut node = self.root();
+        for idx in &self.path {
+            node = &node.children[*idx];
+        }
+        node
+    }
+
+    fn cc(&self, p: &Profile) -> &'a CostCentre {
+        self.cc_maps[self.current].get(&p.id).unwrap()
+    }
+
+    /// Indices of children of `p`, sorted by inherited cost
+    fn sorted_children(&self, p: &Profile, metric: Metric) -> Vec<(usize, u64)> {
+        let mut children: Vec<(usize, u64)> = p
+            .children
+            .iter()
+            .enumerate()
+            .map(|(idx, child)| (idx, child.inherited(metric)))
+            .collect();
+        children.sort_by_key(|&(_, cost)| std::cmp::Reverse(cost));
+        children
+    }
+
+    fn run_command<W: Write>(&mut self, line: &str, w: &mut W) {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let args = &words[1..];
+        match words[0] {
+            "help" => writeln!(w, "{}", HELP).unwrap(),
+            "ls" => match parse_metric_arg(args.first(), self.metric) {
+                Err(err) => writeln!(w, "{}", err).unwrap(),
+                Ok(metric) => self.ls(metric, w),
+            },
+            "cd" => match args.first() {
+                None | Some(&"/") => self.path.clear(),
+                Some(&"..") => {
+                    self.path.pop();
+                }
+                Some(arg) => self.cd(arg, w),
+            },
+            "up" => match args.first().map(|n| n.parse::<usize>()) {
+                None => {
+                    self.path.pop();
+                }
+                Some(Ok(n)) => {
+                    let len = self.path.len();
+                    self.path.truncate(len - std::cmp::min(n, len));
+                }
+                Some(Err(err)) => writeln!(w, "Unable to parse number: {}", err).unwrap(),
+            },
+            "pwd" => self.pwd(w),
+            "top" => {
+                let n = match args.first().map(|n| n.parse::<usize>()) {
+                    None => 10,
+                    Some(Ok(n)) => n,
+                    Some(Err(err)) => {
+                        writeln!(w, "Unable to parse number: {}", err).unwrap();
+                        return;
+                    }
+                };
+                match parse_metric_arg(args.get(1), self.metric) {
+                    Err(err) => writeln!(w, "{}", err).unwrap(),
+                    Ok(metric) => self.top(n, metric, w),
+                }
+            }
+            "find" => match args.first().map(|re| Regex::new(re)) {
+                None => writeln!(w, "USAGE: find <regex>").unwrap(),
+                Some(Err(err)) => writeln!(w, "Unable to parse regex: {}", err).unwrap(),
+                Some(Ok(re)) => self.find(&re, w),
+            },
+            "callers" => match args.first() {
+                None => writeln!(w, "USAGE: callers <cost centre>").unwrap(),
+                Some(cc) => self.callers(cc, w),
+            },
+            "callees" => match args.first() {
+                None => writeln!(w, "USAGE: callees <cost centre>").unwrap(),
+                Some(cc) => self.callees(cc, w),
+            },
+            "sort" => match args.first().map(|m| m.parse::<Metric>()) {
+                None => writeln!(w, "Sorting by {}", self.metric).unwrap(),
+                Some(Ok(metric)) => self.metric = metric,
+                Some(Err(err)) => writeln!(w, "{}", err).unwrap(),
+            },
+            "use" => match args.first() {
+                Some(&"before") => self.switch(0, w),
+                Some(&"after") => self.switch(1, w),
+                _ => writeln!(w, "USAGE: use <before|after>").unwrap(),
+            },
+            cmd => writeln!(
+                w,
+                "Unknown command: {}. Type `help` for a list of commands.",
+                cmd
+            )
+            .unwrap(),
+        }
+    }
+
+    fn ls<W: Write>(&self, metric: Metric, w: &mut W) {
+        let node = self.node();
+        let total = node.inherited(metric);
+        for (idx, cost) in self.sorted_children(node, metric) {
+            let child = &node.children[idx];
+            writeln!(
+                w,
+                "{:>4}  {}  {} {} ({:.2}%), own {}, entries {}",
+                idx,
+                self.cc(child).name(),
+                metric,
+                cost,
+                percentage(cost, total),
+                child.metric(metric),
+                child.entries,
+            )
+            .unwrap();
+        }
+    }
+
+    fn cd<W: Write>(&mut self, arg: &str, w: &mut W) {
+        let node = self.node();
+        let idx = match arg.parse::<usize>() {
+            Ok(idx) if idx < node.children.len() => Some(idx),
+            _ => node
+                .children
+                .iter()
+                .position(|child| self.cc(child).matches(arg)),
+        };
+        match idx {
+            None => writeln!(w, "No such child: {}", arg).unwrap(),
+            Some(idx) => self.path.push(idx),
+        }
+    }
+
+    fn pwd<W: Write>(&self, w: &mut W) {
+        let mut node = self.root();
+        writeln!(w, "{}", self.cc(node).name()).unwrap();
+        for (depth, idx) in self.path.iter().enumerate() {
+            node = &node.children[*idx];
+            writeln!(
+                w,
+                "{:indent$}{}",
+                "",
+                self.cc(node).name(),
+                indent = (depth + 1) * 2
+            )
+            .unwrap();
+        }
+    }
+
+    fn top<W: Write>(&self, n: usize, metric: Metric, w: &mut W) {
+        let mut costs: HashMap<String, u64> = HashMap::new();
+        self.walk(self.root(), &mut |p| {
+            *costs.entry(self.cc(p).name()).or_insert(0) += p.metric(metric);
+        });
+
+        let total: u64 = costs.values().sum();
+        let mut costs: Vec<(String, u64)> = costs.into_iter().collect();
+        costs.sort_by(|(name1, cost1), (name2, cost2)| cost2.cmp(cost1).then(name1.cmp(name2)));
+
+        for (name, cost) in costs.into_iter().take(n) {
+            writeln!(w, "{}: {} ({:.2}%)", name, cost, percentage(cost, total)).unwrap();
+        }
+    }
+
+    fn find<W: Write>(&self, re: &Regex, w: &mut W) {
+        let bold = Style::new().bold();
+        let mut stack = vec![];
+        self.find_(self.root(), re, &mut stack, &mut |stack, p| {
+            writeln!(
+                w,
+                "{} ({} {}, entries {})",
+                bold.paint(stack.join(" > ")),
+                self.metric,
+                p.inherited(self.metric),
+                p.entries
+            )
+            .unwrap();
+        });
+    }
+
+    fn find_<F: FnMut(&[String], &Profile)>(
+        &self,
+        p: &Profile,
+        re: &Regex,
+        stack: &mut Vec<String>,
+        f: &mut F,
+    ) {
+        let name = self.cc(p).name();
+        let matches = re.is_match(&name);
+        stack.push(name);
+        if matches {
+            f(stack, p);
+        }
+        for child in &p.children {
+            self.find_(child, re, stack, f);
+        }
+        stack.pop();
+    }
+
+    fn callers<W: Write>(&self, cc: &str, w: &mut W) {
+        match call_graph(&self.profiles[self.current].1, cc, self.metric) {
+            None => writeln!(w, "No such cost centre").unwrap(),
+            Some(graph) => self.print_costs(graph.callers, w),
+        }
+    }
+
+    fn callees<W: Write>(&self, cc: &str, w: &mut W) {
+        match call_graph(&self.profiles[self.current].1, cc, self.metric) {
+            None => writeln!(w, "No such cost centre").unwrap(),
+            Some(graph) => self.print_costs(graph.callees, w),
+        }
+    }
+
+    fn print_costs<W: Write>(&self, costs: BTreeMap<String, CallCosts>, w: &mut W) {
+        let total: u64 = costs.values().map(|costs| costs.inherited).sum();
+        let mut costs: Vec<(String, u64)> = costs
+            .into_iter()
+            .map(|(name, costs)| (name, costs.inherited))
+            .collect();
+        costs.sort_by(|(name1, cost1), (name2, cost2)| cost2.cmp(cost1).then(name1.cmp(name2)));
+        for (name, cost) in costs {
+            writeln!(
+                w,
+                "{}: {} {} ({:.2}%)",
+                name,
+                self.metric,
+                cost,
+                percentage(cost, total)
+            )
+            .unwrap();
+        }
+    }
+
+    fn walk<F: FnMut(&Profile)>(&self, p: &Profile, f: &mut F) {
+        f(p);
+        for child in &p.children {
+            self.walk(child, f);
+        }
+    }
+
+    /// Switch to another profile. Tries to keep the current cost-centre stack, stops at the
+    /// deepest node that exists in the new profile.
+    fn switch<W: Write>(&mut self, profile: usize, w: &mut W) {
+        if profile >= self.profiles.len() {
+            writeln!(w, "Only one profile loaded").unwrap();
+            return;
+        }
+
+        let mut names = vec![];
+        let mut node = self.root();
+        for idx in &self.path {
+            node = &node.children[*idx];
+            names.push(self.cc(node).name());
+        }
+
+        self.current = profile;
+        self.path.clear();
+
+        let mut node = self.root();
+        for name in names {
+            match node
+                .children
+                .iter()
+                .position(|child| self.cc(child).name() == name)
+            {
+                None => {
+                    writeln!(w, "{} does not exist in {}", name, self.profile_name()).unwrap();
+                    break;
+                }
+                Some(idx) => {
+                    self.path.push(idx);
+                    node = &node.children[idx];
+                }
+            }
+        }
+
+        writeln!(w, "Using {}", self.profile_name()).unwrap();
+    }
+}
+
+fn parse_metric_arg(arg: Option<&&str>, default: Metric) -> Result<Metric, String> {
+    match arg {
+        None => Ok(default),
+        Some(metric) => metric.parse(),
+    }
+}
+
+#[test]
+fn repl_test() {
+    let f: ProfFile = serde_json::from_str(
+        r#"{
+        "program": "test", "arguments": [], "rts_arguments": [], "end_time": "",
+        "initial_capabilities": 1, "total_time": 0.1, "total_ticks": 10, "tick_interval": 1000,
+        "total_alloc": 60,
+        "cost_centres": [
+            {"id": 1, "label": "MAIN", "module": "MAIN", "src_loc": "", "is_caf": false},
+            {"id": 2, "label": "main", "module": "Main", "src_loc": "", "is_caf": false},
+            {"id": 3, "label": "f", "module": "Main", "src_loc": "", "is_caf": false}
+        ],
+        "profile": {"id": 1, "entries": 0, "alloc": 0, "ticks": 0, "children": [
+            {"id": 2, "entries": 1, "alloc": 10, "ticks": 0, "children": [
+                {"id": 3, "entries": 1, "alloc": 20, "ticks": 0, "children": []}
+            ]},
+            {"id": 3, "entries": 1, "alloc": 30, "ticks": 0, "children": []}
+        ]}
+    }"#,
+    )
+    .unwrap();
+    let profiles = vec![("test".to_owned(), f)];
+    let mut state = State::new(&profiles);
+
+    let mut run = |cmd: &str| {
+        let mut out = vec![];
+        state.run_command(cmd, &mut out);
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(
+        run("ls"),
+        "   0  Main.main  alloc 30 (50.00%), own 10, entries 1\n   \
+            1  Main.f  alloc 30 (50.00%), own 30, entries 1\n"
+    );
+    assert_eq!(run("cd main"), "");
+    assert_eq!(run("pwd"), "MAIN.MAIN\n  Main.main\n");
+    assert_eq!(run("up"), "");
+    assert_eq!(run("top 1"), "Main.f: 50 (83.33%)\n");
+    assert_eq!(
+        run("callers f"),
+        "MAIN.MAIN: alloc 30 (60.00%)\nMain.main: alloc 20 (40.00%)\n"
+    );
+    assert_eq!(run("use after"), "Only one profile loaded\n");
+}
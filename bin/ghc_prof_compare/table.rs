@@ -0,0 +1,203 @@
+//! Machine-readable output of listings: CSV, JSON, and Gitlab markdown tables.
+
+use ghc_utils::markdown::{print_cols, print_sep};
+
+use std::io::Write;
+
+/// Table formats. Text output is rendered by the listings themselves.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+    Markdown,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Format, String> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "markdown" => Ok(Format::Markdown),
+            _ => Err(format!("Unknown format: {}", s)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+    /// A percentage. `None` when the percentage is undefined, e.g. change from zero.
+    Percent(Option<f64>),
+    /// A share of a total, in percent
+    Share(f64),
+}
+
+impl Cell {
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Cell::Str(s) => serde_json::Value::from(s.as_str()),
+            Cell::Int(i) => serde_json::Value::from(*i),
+            Cell::Bool(b) => serde_json::Value::from(*b),
+            Cell::Percent(None) => serde_json::Value::Null,
+            Cell::Percent(Some(p)) | Cell::Share(p) => {
+                serde_json::Value::from((p * 100.0).round() / 100.0)
+            }
+        }
+    }
+
+    fn to_csv(&self) -> String {
+        match self {
+            Cell::Str(s) => {
+                if s.contains(&[',', '"', '\n'][..]) {
+                    format!("\"{}\"", s.replace('"', "\"\""))
+                } else {
+                    s.clone()
+                }
+            }
+            Cell::Int(i) => i.to_string(),
+            Cell::Bool(b) => b.to_string(),
+            Cell::Percent(None) => String::new(),
+            Cell::Percent(Some(p)) | Cell::Share(p) => format!("{:.2}", p),
+        }
+    }
+
+    fn to_markdown(&self) -> String {
+        match self {
+            Cell::Str(s) => s.replace('|', "\\|"),
+            Cell::Int(i) => i.to_string(),
+            Cell::Bool(b) => (if *b { "yes" } else { "" }).to_owned(),
+            Cell::Percent(None) => "new".to_owned(),
+            Cell::Percent(Some(p)) => format!("{:+.2}%", p),
+            Cell::Share(p) => format!("{:.2}%", p),
+        }
+    }
+}
+
+/// A table with summary rows (OTHER, TOTAL) after the rest. Summary rows are under `summary` in
+/// JSON.
+pub struct Table {
+    pub columns: Vec<&'static str>,
+    pub rows: Vec<Vec<Cell>>,
+    pub summary: Vec<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn write<W: Write>(&self, format: Format, w: &mut W) {
+        match format {
+            Format::Csv => self.write_csv(w),
+            Format::Json => self.write_json(w),
+            Format::Markdown => self.write_markdown(w),
+        }
+    }
+
+    fn write_csv<W: Write>(&self, w: &mut W) {
+        writeln!(w, "{}", self.columns.join(",")).unwrap();
+        for row in self.rows.iter().chain(self.summary.iter()) {
+            let cells: Vec<String> = row.iter().map(Cell::to_csv).collect();
+            writeln!(w, "{}", cells.join(",")).unwrap();
+        }
+    }
+
+    fn write_json<W: Write>(&self, w: &mut W) {
+        let to_objects = |rows: &[Vec<Cell>]| -> Vec<serde_json::Value> {
+            rows.iter()
+                .map(|row| {
+                    serde_json::Value::Object(
+                        self.columns
+                            .iter()
+                            .zip(row.iter())
+                            .map(|(column, cell)| ((*column).to_owned(), cell.to_json()))
+                            .collect(),
+                    )
+                })
+                .collect()
+        };
+        let json = serde_json::json!({
+            "rows": to_objects(&self.rows),
+            "summary": to_objects(&self.summary),
+        });
+        serde_json::to_writer_pretty(&mut *w, &json).unwrap();
+        writeln!(w).unwrap();
+    }
+
+    fn write_markdown<W: Write>(&self, w: &mut W) {
+        let columns: Vec<String> = self.columns.iter().map(|c| (*c).to_owned()).collect();
+        let to_strings = |rows: &[Vec<Cell>]| -> Vec<Vec<String>> {
+            rows.iter()
+                .map(|row| row.iter().map(Cell::to_markdown).collect())
+                .collect()
+        };
+        let rows = to_strings(&self.rows);
+        let summary = to_strings(&self.summary);
+
+        // Assuming ASCII
+        let mut col_widths: Vec<usize> = columns.iter().map(|col| col.len() + 2).collect();
+        for row in rows.iter().chain(summary.iter()) {
+            for (col_idx, col) in row.iter().enumerate() {
+                col_widths[col_idx] = std::cmp::max(col_widths[col_idx], col.len() + 2);
+            }
+        }
+
+        print_cols(&columns, &col_widths, w);
+        print_sep(&col_widths, w);
+        for row in &rows {
+            print_cols(row, &col_widths, w);
+        }
+        // Markdown tables have only one separator, after the header
+        for row in &summary {
+            print_cols(row, &col_widths, w);
+        }
+    }
+}
+
+#[test]
+fn table_test() {
+    let table = Table {
+        columns: vec!["cost_centre", "before", "change"],
+        rows: vec![
+            vec![
+                Cell::Str("Main.f".to_owned()),
+                Cell::Int(300),
+                Cell::Percent(Some(16.666)),
+            ],
+            vec![
+                Cell::Str("Main.||, x".to_owned()),
+                Cell::Int(0),
+                Cell::Percent(None),
+            ],
+        ],
+        summary: vec![vec![
+            Cell::Str("TOTAL".to_owned()),
+            Cell::Int(300),
+            Cell::Percent(Some(-1.0)),
+        ]],
+    };
+
+    let render = |format| {
+        let mut out = vec![];
+        table.write(format, &mut out);
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(
+        render(Format::Csv),
+        "cost_centre,before,change\nMain.f,300,16.67\n\"Main.||, x\",0,\nTOTAL,300,-1.00\n"
+    );
+    assert_eq!(
+        render(Format::Markdown),
+        "| cost_centre  | before | change  |\n\
+         |--------------|--------|---------|\n\
+         | Main.f       | 300    | +16.67% |\n\
+         | Main.\\|\\|, x | 0      | new     |\n\
+         | TOTAL        | 300    | -1.00%  |\n"
+    );
+
+    let json: serde_json::Value = serde_json::from_str(&render(Format::Json)).unwrap();
+    assert_eq!(json["rows"][0]["change"], serde_json::json!(16.67));
+    assert_eq!(json["rows"][1]["change"], serde_json::Value::Null);
+    assert_eq!(json["summary"][0]["cost_centre"], "TOTAL");
+}
@@ -0,0 +1,38 @@
+//! Shows wall time, GC time and allocation of spans delimited by user messages or markers in a
+//! GHC eventlog
+
+use ghc_utils::eventlog::{open_eventlog, read_timeline, write_messages, write_spans};
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("ghc-spans")
+        .about(
+            "Pairs `START <name>`/`STOP <name>` (or `begin <name>`/`end <name>`) user messages and \
+             markers (traceEvent, traceMarker) in a GHC eventlog into spans, and shows wall time, \
+             GC time, number of GCs and allocation in each span",
+        )
+        .arg(Arg::with_name("file").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("messages")
+                .help("Print all user messages and markers instead")
+                .long("messages"),
+        )
+        .get_matches();
+
+    let file = args.value_of("file").unwrap();
+    let timeline = open_eventlog(file)
+        .and_then(read_timeline)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read eventlog {}: {}", file, err);
+            std::process::exit(1);
+        });
+
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+    if args.is_present("messages") {
+        write_messages(&timeline, &mut stdout_lock);
+    } else {
+        write_spans(&timeline, &mut stdout_lock);
+    }
+}
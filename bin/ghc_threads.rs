@@ -0,0 +1,63 @@
+//! Shows capability utilisation, time threads were blocked by stop reason and spark statistics
+//! of a GHC eventlog, or prints them as a Chrome trace
+
+use ghc_utils::eventlog::{
+    activity_trace_events, open_eventlog, read_activity, write_activity, write_chrome_trace,
+};
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("ghc-threads")
+        .about(
+            "Shows mutator and GC utilisation of capabilities over time, time threads waited to \
+             run again by stop reason, and spark statistics in a GHC eventlog",
+        )
+        .arg(Arg::with_name("file").takes_value(true).required(true))
+        .arg(
+            Arg::with_name("chrome")
+                .help(
+                    "Print a Chrome trace (JSON) of threads and GCs on capabilities, for \
+                     chrome://tracing or Perfetto",
+                )
+                .long("chrome"),
+        )
+        .arg(
+            Arg::with_name("buckets")
+                .help("Number of intervals to show utilisation of capabilities in")
+                .long("buckets")
+                .takes_value(true)
+                .default_value("40")
+                .conflicts_with("chrome"),
+        )
+        .get_matches();
+
+    let file = args.value_of("file").unwrap();
+    let buckets = args.value_of("buckets").unwrap();
+    let buckets = match buckets.parse::<usize>() {
+        Ok(n) if n > 0 => n,
+        Ok(_) => {
+            eprintln!("Unable to parse --buckets: {}: must be at least 1", buckets);
+            std::process::exit(1);
+        }
+        Err(err) => {
+            eprintln!("Unable to parse --buckets: {}: {}", buckets, err);
+            std::process::exit(1);
+        }
+    };
+
+    let activity = open_eventlog(file)
+        .and_then(read_activity)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read eventlog {}: {}", file, err);
+            std::process::exit(1);
+        });
+
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+    if args.is_present("chrome") {
+        write_chrome_trace(&activity_trace_events(&activity), &mut stdout_lock);
+    } else {
+        write_activity(&activity, buckets, &mut stdout_lock);
+    }
+}
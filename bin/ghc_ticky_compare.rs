@@ -0,0 +1,89 @@
+//! Shows the closures with the most entries or allocation in a ticky-ticky profile (`+RTS -r`),
+//! or compares two ticky profiles, showing the closures with the biggest changes. Ticky profiles
+//! can also be read from eventlogs (`+RTS -lT`), which have per-closure samples over time.
+
+use ghc_utils::eventlog::{open_eventlog, read_ticky};
+use ghc_utils::ticky::{
+    parse_ticky_file, write_ticky, write_ticky_diff, write_ticky_series, TickyFile, TickyMetric,
+};
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("ghc-ticky-compare")
+        .about(
+            "Shows closures with the most entries or allocation in a ticky-ticky profile (ticky \
+             report or `.eventlog`), or compares two ticky profiles. Closures are matched by \
+             name after z-decoding and stripping GHC uniques.",
+        )
+        .arg(Arg::with_name("file_1").takes_value(true).required(true))
+        .arg(Arg::with_name("file_2").takes_value(true).required(false))
+        .arg(
+            Arg::with_name("series")
+                .help(
+                    "Print the metric of the top closures in each sample of an eventlog, one \
+                     line per sample",
+                )
+                .long("series")
+                .conflicts_with("file_2"),
+        )
+        .arg(
+            Arg::with_name("metric")
+                .help("Counter to sort closures by")
+                .long("metric")
+                .takes_value(true)
+                .possible_values(&["entries", "alloc", "allocd"])
+                .default_value("alloc"),
+        )
+        .arg(
+            Arg::with_name("top")
+                .help("Number of closures to show")
+                .long("top")
+                .takes_value(true)
+                .default_value("30"),
+        )
+        .get_matches();
+
+    let metric: TickyMetric = args.value_of("metric").unwrap().parse().unwrap();
+    let top = args.value_of("top").unwrap();
+    let top = top.parse::<usize>().unwrap_or_else(|err| {
+        eprintln!("Unable to parse --top: {}: {}", top, err);
+        std::process::exit(1);
+    });
+
+    let ticky1 = load_ticky(args.value_of("file_1").unwrap());
+
+    let stdout = std::io::stdout();
+    let mut stdout_lock = stdout.lock();
+
+    if args.is_present("series") {
+        if ticky1.sample_times.is_empty() {
+            eprintln!("No ticky samples in {}", args.value_of("file_1").unwrap());
+            std::process::exit(1);
+        }
+        write_ticky_series(&ticky1, metric, top, &mut stdout_lock);
+        return;
+    }
+
+    match args.value_of("file_2") {
+        None => write_ticky(&ticky1, metric, top, &mut stdout_lock),
+        Some(file_2) => {
+            let ticky2 = load_ticky(file_2);
+            write_ticky_diff(&ticky1, &ticky2, metric, top, &mut stdout_lock);
+        }
+    }
+}
+
+/// Reads a ticky report, or ticky counters in an eventlog when the file name ends with
+/// `.eventlog`
+fn load_ticky(path: &str) -> TickyFile {
+    if !path.ends_with(".eventlog") {
+        return parse_ticky_file(path);
+    }
+    open_eventlog(path)
+        .and_then(read_ticky)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read ticky counters in {}: {}", path, err);
+            std::process::exit(1);
+        })
+}
@@ -0,0 +1,69 @@
+//! Looks up info table addresses in the IPE events of a GHC eventlog (`-finfo-table-map`)
+
+use ghc_utils::eventlog::{open_eventlog, read_ipe_index, IpeIndex};
+
+use std::io::BufRead;
+
+use clap::{App, Arg};
+
+fn main() {
+    let args = App::new("ipe-lookup")
+        .about(
+            "Shows source location, closure type and type of info tables. Addresses are read from \
+             stdin when not given as arguments.",
+        )
+        .arg(
+            Arg::with_name("eventlog")
+                .help("Eventlog of a program built with -finfo-table-map")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("addresses")
+                .help("Info table addresses, e.g. 0x4a8f30")
+                .multiple(true),
+        )
+        .get_matches();
+
+    let path = args.value_of("eventlog").unwrap();
+    let index = open_eventlog(path)
+        .and_then(read_ipe_index)
+        .unwrap_or_else(|err| {
+            eprintln!("Unable to read eventlog {}: {}", path, err);
+            std::process::exit(1);
+        });
+    if index.is_empty() {
+        eprintln!(
+            "WARNING: No IPE events in {}, was the program built with -finfo-table-map?",
+            path
+        );
+    }
+
+    match args.values_of("addresses") {
+        Some(addrs) => {
+            for addr in addrs {
+                lookup(&index, addr);
+            }
+        }
+        None => {
+            let stdin = std::io::stdin();
+            for line in stdin.lock().lines() {
+                let line = line.unwrap();
+                let addr = line.trim();
+                if !addr.is_empty() {
+                    lookup(&index, addr);
+                }
+            }
+        }
+    }
+}
+
+fn lookup(index: &IpeIndex, addr: &str) {
+    match u64::from_str_radix(addr.trim_start_matches("0x"), 16) {
+        Err(_) => println!("{}: unable to parse address", addr),
+        Ok(info) => match index.lookup(info) {
+            None => println!("{}: not found", addr),
+            Some(prov) => println!("{}: {}", addr, prov),
+        },
+    }
+}
@@ -1,8 +1,10 @@
 //! Generates a Gitlab markdown table from a NoFib analyse output
 
+use ghc_utils::markdown::{print_cols, print_sep};
+
 use clap::{App, Arg};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader};
 
 fn main() {
     let args = App::new("nofib-to-gitlab")
@@ -122,28 +124,3 @@ fn read_summary(reader: &mut BufReader<File>, cols: &mut Vec<Vec<String>>) {
 fn is_line_sep(str: &str) -> bool {
     !str.is_empty() && str.chars().all(|c| c == '-')
 }
-
-fn print_cols<W: Write>(row: &[String], widths: &[usize], w: &mut W) {
-    for (width, col) in widths.iter().zip(row.iter()) {
-        // Assuming ASCII
-        let str_w = col.len();
-
-        write!(w, "| ").unwrap();
-        write!(w, "{}", col).unwrap();
-        for _ in 0..width - str_w - 1 {
-            write!(w, " ").unwrap();
-        }
-    }
-    writeln!(w, "|").unwrap();
-}
-
-fn print_sep<W: Write>(widths: &[usize], w: &mut W) {
-    write!(w, "|").unwrap();
-    for width in widths {
-        for _ in 0..*width {
-            write!(w, "-").unwrap();
-        }
-        write!(w, "|").unwrap();
-    }
-    writeln!(w).unwrap();
-}
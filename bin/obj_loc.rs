@@ -18,6 +18,7 @@ use std::io::{BufRead, BufReader};
 
 use ansi_term::{Color, Style};
 use clap::{App, Arg};
+use ghc_utils::eventlog::{open_eventlog, read_ipe_index, IpeIndex};
 use rustyline::error::ReadlineError;
 use rustyline::Editor;
 
@@ -36,6 +37,8 @@ impl fmt::Debug for Addr {
 struct AddrSize {
     addr: Addr,
     size: u64,
+    /// Info pointer of the object, when printed by the gdb script
+    info: Option<u64>,
 }
 
 #[derive(Debug)]
@@ -85,6 +88,15 @@ fn main() {
                 .takes_value(true)
                 .required(true),
         )
+        .arg(
+            Arg::with_name("eventlog")
+                .help(
+                    "Eventlog of the same run with IPE events (-finfo-table-map), to describe \
+                     objects by their info pointers",
+                )
+                .long("eventlog")
+                .takes_value(true),
+        )
         .get_matches();
 
     let path = args.value_of("gdb-out-file").unwrap();
@@ -93,7 +105,14 @@ fn main() {
     let reader = BufReader::new(file);
 
     let gcs = parse(reader);
-    repl(&gcs);
+
+    let ipe_index = args.value_of("eventlog").map(|path| {
+        open_eventlog(path)
+            .and_then(read_ipe_index)
+            .unwrap_or_else(|err| panic!("Unable to read eventlog {}: {}", path, err))
+    });
+
+    repl(&gcs, ipe_index.as_ref());
 }
 
 fn parse<B: BufRead>(reader: B) -> Vec<GC> {
@@ -122,15 +141,35 @@ fn parse<B: BufRead>(reader: B) -> Vec<GC> {
             current_gc = Some(GC::new(major));
         } else {
             assert!(current_gc.is_some());
-            // from '->' to 'size:' size
+            // from '->' to 'size:' size ['info:' info]
             let from = Addr(parse_hex_fail(words[0]));
             let to = Addr(parse_hex_fail(words[2]));
             let size = str::parse::<u64>(words[4])
                 .unwrap_or_else(|_| panic!("Unable to parse size: {}", words[4]));
+            let info = match words.get(5) {
+                Some(&"info:") => Some(parse_hex_fail(words[6])),
+                _ => None,
+            };
             let current_gc = current_gc.as_mut().unwrap();
 
-            insert_new(&mut current_gc.moves_fwd, from, AddrSize { addr: to, size });
-            insert_new(&mut current_gc.moves_bwd, to, AddrSize { addr: from, size });
+            insert_new(
+                &mut current_gc.moves_fwd,
+                from,
+                AddrSize {
+                    addr: to,
+                    size,
+                    info,
+                },
+            );
+            insert_new(
+                &mut current_gc.moves_bwd,
+                to,
+                AddrSize {
+                    addr: from,
+                    size,
+                    info,
+                },
+            );
         }
     }
 
@@ -157,7 +196,7 @@ where
     assert!(ret.is_none());
 }
 
-fn repl(gcs: &[GC]) {
+fn repl(gcs: &[GC], ipe_index: Option<&IpeIndex>) {
     let mut last_major_gc = 0;
     for (gc_idx, gc) in gcs.iter().enumerate().rev() {
         if gc.major {
@@ -185,6 +224,7 @@ fn repl(gcs: &[GC]) {
                 Some(addr) => {
                     rl.add_history_entry(line);
                     for moves in find_moves(gcs, addr) {
+                        let info = object_info(gcs, &moves);
                         // Nth GC, 0-based
                         let mut gc_n = moves.first_move;
                         for move_ in moves.moves {
@@ -207,6 +247,12 @@ fn repl(gcs: &[GC]) {
 
                             gc_n += 1;
                         }
+                        if let Some(info) = info {
+                            match ipe_index.and_then(|index| index.lookup(info)) {
+                                None => println!("info: {:#x}", info),
+                                Some(prov) => println!("info: {:#x} {}", info, prov),
+                            }
+                        }
                         println!();
                     }
                 }
@@ -287,6 +333,15 @@ fn find_moves(gcs: &[GC], addr: u64) -> Vec<Moves> {
     ret
 }
 
+/// Info pointer of an object, from the first of its moves that has one.
+fn object_info(gcs: &[GC], moves: &Moves) -> Option<u64> {
+    moves
+        .moves
+        .iter()
+        .zip(gcs[moves.first_move..].iter())
+        .find_map(|(addr, gc)| gc.moves_fwd.get(addr).and_then(|move_| move_.info))
+}
+
 fn follow_fwd(gcs: &[GC], addr: Addr) -> Vec<Addr> {
     // println!("follow_fwd: gcs={:#?}, addr={:#?}", gcs, addr);
 
@@ -344,28 +399,32 @@ fn parse_test() {
         gcs[0].moves_fwd.get(&Addr(0x123)),
         Some(&AddrSize {
             addr: Addr(0x124),
-            size: 1
+            size: 1,
+            info: None,
         })
     );
     assert_eq!(
         gcs[0].moves_fwd.get(&Addr(0x122)),
         Some(&AddrSize {
             addr: Addr(0x123),
-            size: 2
+            size: 2,
+            info: None,
         })
     );
     assert_eq!(
         gcs[0].moves_bwd.get(&Addr(0x124)),
         Some(&AddrSize {
             addr: Addr(0x123),
-            size: 1
+            size: 1,
+            info: None,
         })
     );
     assert_eq!(
         gcs[0].moves_bwd.get(&Addr(0x123)),
         Some(&AddrSize {
             addr: Addr(0x122),
-            size: 2
+            size: 2,
+            info: None,
         })
     );
 }
@@ -436,6 +495,21 @@ fn find_moves_test() {
     );
 }
 
+#[test]
+fn object_info_test() {
+    let input = "\
+        >>> GC 1\n\
+        >>> 0x123 -> 0x124 size: 1\n\
+        >>> GC 2\n\
+        >>> 0x124 -> 0x125 size: 1 info: 0x4a8f30\n\
+    ";
+
+    let gcs = parse(input.as_bytes());
+    let moves = find_moves(&gcs, 0x123);
+    assert_eq!(moves.len(), 1);
+    assert_eq!(object_info(&gcs, &moves[0]), Some(0x4a8f30));
+}
+
 #[test]
 fn complicated_test() {
     // An interesting case that can legitemately happen in compacting GC: We move x to y, and z to
@@ -0,0 +1,1174 @@
+//! Reader and writer for GHC's eventlog binary format (`+RTS -l`)
+//!
+//! An eventlog is a header describing the event types, followed by events:
+//!
+//! ```text
+//! "hdrb" "hetb"
+//!   "etb\0" type: u16, size: i16, descr_len: u32, descr, extra_len: u32, extra, "ete\0"
+//!   ...
+//! "hete" "hdre"
+//! "datb"
+//!   type: u16, time: u64, [size: u16 for variable-size events], payload
+//!   ...
+//! 0xffff
+//! ```
+//!
+//! All numbers are big-endian. Events are written in blocks, each capability has its own buffer.
+//! A block starts with a block marker event that gives the size of the block and the capability
+//! the events in the block belong to.
+//!
+//! The reader is streaming: events are decoded one at a time, so eventlogs don't need to fit in
+//! memory. Event types we don't decode are returned as `EventKind::Unknown` with their payloads.
+//! Known events with more fields than we decode (from newer GHCs) have the extra fields ignored.
+
+mod chrome;
+mod filter;
+mod gc;
+mod heap;
+mod ipe;
+mod spans;
+mod threads;
+mod ticky;
+mod writer;
+
+pub use chrome::{eventlog_trace_events, write_chrome_trace, TraceEvent};
+pub use filter::EventFilter;
+pub use gc::{read_gcs, write_gc_summary, write_gcs, Gc, Gcs};
+pub use heap::read_heap_profile;
+pub use ipe::{read_ipe_index, InfoProv, IpeIndex};
+pub use spans::{read_timeline, write_messages, write_spans, Span, Timeline, UserMessage};
+pub use threads::{
+    activity_trace_events, read_activity, write_activity, Activity, Blocked, CapActivity,
+    SparkStats, ThreadSlice,
+};
+pub use ticky::read_ticky;
+pub use writer::EventLogWriter;
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+// Header markers
+const HEADER_BEGIN: u32 = 0x6864_7262; // "hdrb"
+const HEADER_END: u32 = 0x6864_7265; // "hdre"
+const HET_BEGIN: u32 = 0x6865_7462; // "hetb"
+const HET_END: u32 = 0x6865_7465; // "hete"
+const ET_BEGIN: u32 = 0x6574_6200; // "etb\0"
+const ET_END: u32 = 0x6574_6500; // "ete\0"
+const DATA_BEGIN: u32 = 0x6461_7462; // "datb"
+const DATA_END: u16 = 0xffff;
+
+/// Size of variable-size events in the header
+const VARIABLE_SIZE: u16 = 0xffff;
+/// Capability of blocks that don't belong to a capability
+const NO_CAP: u16 = 0xffff;
+
+// Event type ids, from GHC's `rts/include/rts/EventLogFormat.h`
+const CREATE_THREAD: u16 = 0;
+const RUN_THREAD: u16 = 1;
+const STOP_THREAD: u16 = 2;
+const THREAD_RUNNABLE: u16 = 3;
+const MIGRATE_THREAD: u16 = 4;
+const THREAD_WAKEUP: u16 = 8;
+const GC_START: u16 = 9;
+const GC_END: u16 = 10;
+const REQUEST_SEQ_GC: u16 = 11;
+const REQUEST_PAR_GC: u16 = 12;
+const CREATE_SPARK_THREAD: u16 = 15;
+const LOG_MSG: u16 = 16;
+const BLOCK_MARKER: u16 = 18;
+const USER_MSG: u16 = 19;
+const GC_IDLE: u16 = 20;
+const GC_WORK: u16 = 21;
+const GC_DONE: u16 = 22;
+const CAPSET_CREATE: u16 = 25;
+const CAPSET_DELETE: u16 = 26;
+const CAPSET_ASSIGN_CAP: u16 = 27;
+const CAPSET_REMOVE_CAP: u16 = 28;
+const RTS_IDENTIFIER: u16 = 29;
+const PROGRAM_ARGS: u16 = 30;
+const SPARK_COUNTERS: u16 = 34;
+const SPARK_CREATE: u16 = 35;
+const SPARK_DUD: u16 = 36;
+const SPARK_OVERFLOW: u16 = 37;
+const SPARK_RUN: u16 = 38;
+const SPARK_STEAL: u16 = 39;
+const SPARK_FIZZLE: u16 = 40;
+const SPARK_GC: u16 = 41;
+const WALL_CLOCK_TIME: u16 = 43;
+const THREAD_LABEL: u16 = 44;
+const CAP_CREATE: u16 = 45;
+const CAP_DELETE: u16 = 46;
+const CAP_DISABLE: u16 = 47;
+const CAP_ENABLE: u16 = 48;
+const HEAP_ALLOCATED: u16 = 49;
+const HEAP_SIZE: u16 = 50;
+const HEAP_LIVE: u16 = 51;
+const HEAP_INFO_GHC: u16 = 52;
+const GC_STATS_GHC: u16 = 53;
+const GC_GLOBAL_SYNC: u16 = 54;
+const USER_MARKER: u16 = 58;
+const BLOCKS_SIZE: u16 = 91;
+const HEAP_PROF_BEGIN: u16 = 160;
+const HEAP_PROF_COST_CENTRE: u16 = 161;
+const HEAP_PROF_SAMPLE_BEGIN: u16 = 162;
+const HEAP_PROF_SAMPLE_COST_CENTRE: u16 = 163;
+const HEAP_PROF_SAMPLE_STRING: u16 = 164;
+const HEAP_PROF_SAMPLE_END: u16 = 165;
+const HEAP_BIO_PROF_SAMPLE_BEGIN: u16 = 166;
+const PROF_SAMPLE_COST_CENTRE: u16 = 167;
+const PROF_BEGIN: u16 = 168;
+const IPE: u16 = 169;
+const TICKY_COUNTER_DEF: u16 = 210;
+const TICKY_COUNTER_SAMPLE: u16 = 211;
+const TICKY_BEGIN_SAMPLE: u16 = 212;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EventType {
+    pub id: u16,
+    /// Payload size, `None` for variable-size events
+    pub size: Option<u16>,
+    pub description: String,
+    pub extra: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Header {
+    pub event_types: Vec<EventType>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Event {
+    /// Nanoseconds since the start of the program
+    pub time: u64,
+    /// Capability of the buffer the event was written to. `None` for events outside of
+    /// capability buffers (e.g. capset events).
+    pub cap: Option<u16>,
+    pub kind: EventKind,
+}
+
+/// Decoded events. Thread ids are `u32`, capabilities `u16`, capsets `u32`, sizes are in bytes.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventKind {
+    // Threads
+    CreateThread {
+        thread: u32,
+    },
+    RunThread {
+        thread: u32,
+    },
+    StopThread {
+        thread: u32,
+        /// See `stop_status_name`
+        status: u16,
+        /// Thread the stopped thread is blocked on, 0 if none
+        blocked_on: u32,
+    },
+    ThreadRunnable {
+        thread: u32,
+    },
+    MigrateThread {
+        thread: u32,
+        new_cap: u16,
+    },
+    ThreadWakeup {
+        thread: u32,
+        other_cap: u16,
+    },
+    ThreadLabel {
+        thread: u32,
+        label: String,
+    },
+    CreateSparkThread {
+        thread: u32,
+    },
+
+    // GC
+    GcStart,
+    GcEnd,
+    RequestSeqGc,
+    RequestParGc,
+    GcIdle,
+    GcWork,
+    GcDone,
+    GcGlobalSync,
+    GcStats {
+        capset: u32,
+        generation: u16,
+        copied: u64,
+        slop: u64,
+        fragmentation: u64,
+        par_n_threads: u32,
+        par_max_copied: u64,
+        par_tot_copied: u64,
+    },
+
+    // Heap
+    HeapAllocated {
+        capset: u32,
+        allocated: u64,
+    },
+    HeapSize {
+        capset: u32,
+        size: u64,
+    },
+    HeapLive {
+        capset: u32,
+        live: u64,
+    },
+    BlocksSize {
+        capset: u32,
+        size: u64,
+    },
+    HeapInfo {
+        capset: u32,
+        generations: u16,
+        max_heap_size: u64,
+        alloc_area_size: u64,
+        mblock_size: u64,
+        block_size: u64,
+    },
+
+    // Capabilities and capsets
+    CapCreate {
+        cap: u16,
+    },
+    CapDelete {
+        cap: u16,
+    },
+    CapDisable {
+        cap: u16,
+    },
+    CapEnable {
+        cap: u16,
+    },
+    CapsetCreate {
+        capset: u32,
+        capset_type: u16,
+    },
+    CapsetDelete {
+        capset: u32,
+    },
+    CapsetAssignCap {
+        capset: u32,
+        cap: u16,
+    },
+    CapsetRemoveCap {
+        capset: u32,
+        cap: u16,
+    },
+    RtsIdentifier {
+        capset: u32,
+        identifier: String,
+    },
+    ProgramArgs {
+        capset: u32,
+        args: Vec<String>,
+    },
+    WallClockTime {
+        capset: u32,
+        sec: u64,
+        nsec: u32,
+    },
+
+    // Sparks
+    SparkCounters {
+        created: u64,
+        dud: u64,
+        overflowed: u64,
+        converted: u64,
+        gcd: u64,
+        fizzled: u64,
+        remaining: u64,
+    },
+    SparkCreate,
+    SparkDud,
+    SparkOverflow,
+    SparkRun,
+    SparkSteal {
+        victim_cap: u16,
+    },
+    SparkFizzle,
+    SparkGc,
+
+    // Messages
+    LogMsg {
+        msg: String,
+    },
+    UserMsg {
+        msg: String,
+    },
+    UserMarker {
+        marker: String,
+    },
+
+    // Profiling
+    HeapProfBegin {
+        profile: u8,
+        sampling_period: u64,
+        /// See `heap_prof_breakdown_name`
+        breakdown: u32,
+        /// Module, closure description, type description, cost centre, cost-centre stack,
+        /// retainer and biography filters
+        filters: Vec<String>,
+    },
+    HeapProfCostCentre {
+        id: u32,
+        label: String,
+        module: String,
+        src_loc: String,
+        is_caf: bool,
+    },
+    HeapProfSampleBegin {
+        era: u64,
+    },
+    HeapProfSampleEnd {
+        era: u64,
+    },
+    HeapBioProfSampleBegin {
+        era: u64,
+        time: u64,
+    },
+    HeapProfSampleCostCentre {
+        profile: u8,
+        residency: u64,
+        /// Cost-centre stack, innermost cost centre first
+        stack: Vec<u32>,
+    },
+    HeapProfSampleString {
+        profile: u8,
+        residency: u64,
+        label: String,
+    },
+    ProfSampleCostCentre {
+        cap: u32,
+        ticks: u64,
+        /// Cost-centre stack, innermost cost centre first
+        stack: Vec<u32>,
+    },
+    ProfBegin {
+        tick_interval: u64,
+    },
+    /// Info table provenance
+    Ipe {
+        info: u64,
+        table_name: String,
+        closure_desc: String,
+        ty_desc: String,
+        label: String,
+        module: String,
+        src_loc: String,
+    },
+
+    // Ticky-ticky profiling
+    TickyCounterDef {
+        id: u64,
+        arity: u16,
+        /// Kinds of the arguments, e.g. `Li`
+        kinds: String,
+        /// Name as in the ticky report, e.g. `Main.$wgo{v r1ab} (fun)`
+        name: String,
+    },
+    /// Counts since the previous sample, the RTS resets counters after sampling them
+    TickyCounterSample {
+        id: u64,
+        entries: u64,
+        alloc: u64,
+        allocd: u64,
+    },
+    TickyBeginSample,
+
+    /// Start of a capability buffer. Events in the next `size` bytes (including the marker)
+    /// belong to `block_cap`.
+    BlockMarker {
+        size: u32,
+        end_time: u64,
+        block_cap: Option<u16>,
+    },
+    /// An event we don't decode
+    Unknown {
+        id: u16,
+        payload: Vec<u8>,
+    },
+}
+
+impl EventKind {
+    /// Event type id of the event
+    pub fn type_id(&self) -> u16 {
+        match self {
+            EventKind::CreateThread { .. } => CREATE_THREAD,
+            EventKind::RunThread { .. } => RUN_THREAD,
+            EventKind::StopThread { .. } => STOP_THREAD,
+            EventKind::ThreadRunnable { .. } => THREAD_RUNNABLE,
+            EventKind::MigrateThread { .. } => MIGRATE_THREAD,
+            EventKind::ThreadWakeup { .. } => THREAD_WAKEUP,
+            EventKind::ThreadLabel { .. } => THREAD_LABEL,
+            EventKind::CreateSparkThread { .. } => CREATE_SPARK_THREAD,
+            EventKind::GcStart => GC_START,
+            EventKind::GcEnd => GC_END,
+            EventKind::RequestSeqGc => REQUEST_SEQ_GC,
+            EventKind::RequestParGc => REQUEST_PAR_GC,
+            EventKind::GcIdle => GC_IDLE,
+            EventKind::GcWork => GC_WORK,
+            EventKind::GcDone => GC_DONE,
+            EventKind::GcGlobalSync => GC_GLOBAL_SYNC,
+            EventKind::GcStats { .. } => GC_STATS_GHC,
+            EventKind::HeapAllocated { .. } => HEAP_ALLOCATED,
+            EventKind::HeapSize { .. } => HEAP_SIZE,
+            EventKind::HeapLive { .. } => HEAP_LIVE,
+            EventKind::BlocksSize { .. } => BLOCKS_SIZE,
+            EventKind::HeapInfo { .. } => HEAP_INFO_GHC,
+            EventKind::CapCreate { .. } => CAP_CREATE,
+            EventKind::CapDelete { .. } => CAP_DELETE,
+            EventKind::CapDisable { .. } => CAP_DISABLE,
+            EventKind::CapEnable { .. } => CAP_ENABLE,
+            EventKind::CapsetCreate { .. } => CAPSET_CREATE,
+            EventKind::CapsetDelete { .. } => CAPSET_DELETE,
+            EventKind::CapsetAssignCap { .. } => CAPSET_ASSIGN_CAP,
+            EventKind::CapsetRemoveCap { .. } => CAPSET_REMOVE_CAP,
+            EventKind::RtsIdentifier { .. } => RTS_IDENTIFIER,
+            EventKind::ProgramArgs { .. } => PROGRAM_ARGS,
+            EventKind::WallClockTime { .. } => WALL_CLOCK_TIME,
+            EventKind::SparkCounters { .. } => SPARK_COUNTERS,
+            EventKind::SparkCreate => SPARK_CREATE,
+            EventKind::SparkDud => SPARK_DUD,
+            EventKind::SparkOverflow => SPARK_OVERFLOW,
+            EventKind::SparkRun => SPARK_RUN,
+            EventKind::SparkSteal { .. } => SPARK_STEAL,
+            EventKind::SparkFizzle => SPARK_FIZZLE,
+            EventKind::SparkGc => SPARK_GC,
+            EventKind::LogMsg { .. } => LOG_MSG,
+            EventKind::UserMsg { .. } => USER_MSG,
+            EventKind::UserMarker { .. } => USER_MARKER,
+            EventKind::HeapProfBegin { .. } => HEAP_PROF_BEGIN,
+            EventKind::HeapProfCostCentre { .. } => HEAP_PROF_COST_CENTRE,
+            EventKind::HeapProfSampleBegin { .. } => HEAP_PROF_SAMPLE_BEGIN,
+            EventKind::HeapProfSampleEnd { .. } => HEAP_PROF_SAMPLE_END,
+            EventKind::HeapBioProfSampleBegin { .. } => HEAP_BIO_PROF_SAMPLE_BEGIN,
+            EventKind::HeapProfSampleCostCentre { .. } => HEAP_PROF_SAMPLE_COST_CENTRE,
+            EventKind::HeapProfSampleString { .. } => HEAP_PROF_SAMPLE_STRING,
+            EventKind::ProfSampleCostCentre { .. } => PROF_SAMPLE_COST_CENTRE,
+            EventKind::ProfBegin { .. } => PROF_BEGIN,
+            EventKind::Ipe { .. } => IPE,
+            EventKind::TickyCounterDef { .. } => TICKY_COUNTER_DEF,
+            EventKind::TickyCounterSample { .. } => TICKY_COUNTER_SAMPLE,
+            EventKind::TickyBeginSample => TICKY_BEGIN_SAMPLE,
+            EventKind::BlockMarker { .. } => BLOCK_MARKER,
+            EventKind::Unknown { id, .. } => *id,
+        }
+    }
+}
+
+/// Nanoseconds (event times) in milliseconds
+pub(crate) fn ms(ns: u64) -> f64 {
+    (ns as f64) / 1_000_000.0
+}
+
+/// Nanoseconds (event times) in seconds
+pub(crate) fn secs(ns: u64) -> f64 {
+    (ns as f64) / 1_000_000_000.0
+}
+
+/// Name of the stop status in `StopThread` events
+pub fn stop_status_name(status: u16) -> &'static str {
+    match status {
+        1 => "HeapOverflow",
+        2 => "StackOverflow",
+        3 => "ThreadYielding",
+        4 => "ThreadBlocked",
+        5 => "ThreadFinished",
+        6 => "ForeignCall",
+        7 => "BlockedOnMVar",
+        8 => "BlockedOnBlackHole",
+        9 => "BlockedOnRead",
+        10 => "BlockedOnWrite",
+        11 => "BlockedOnDelay",
+        12 => "BlockedOnSTM",
+        13 => "BlockedOnDoProc",
+        14 => "BlockedOnCCall",
+        15 => "BlockedOnCCall_Interruptible",
+        16 => "BlockedOnMsgThrowTo",
+        17 => "ThreadMigrating",
+        20 => "BlockedOnMVarRead",
+        _ => "Unknown",
+    }
+}
+
+/// Name of the breakdown in `HeapProfBegin` events, i.e. the `-h` flag
+pub fn heap_prof_breakdown_name(breakdown: u32) -> &'static str {
+    match breakdown {
+        1 => "cost centre (-hc)",
+        2 => "module (-hm)",
+        3 => "closure description (-hd)",
+        4 => "type description (-hy)",
+        5 => "retainer (-hr)",
+        6 => "biography (-hb)",
+        7 => "closure type (-hT)",
+        8 => "info table (-hi)",
+        _ => "unknown",
+    }
+}
+
+pub struct EventLogReader<R: Read> {
+    reader: R,
+    header: Header,
+    /// Event sizes, by event type id
+    sizes: HashMap<u16, Option<u16>>,
+    /// Bytes read after `datb`
+    offset: u64,
+    /// Capability and end offset of the current block
+    block: Option<(Option<u16>, u64)>,
+    /// Payload of the last event
+    payload: Vec<u8>,
+    done: bool,
+}
+
+/// Opens an eventlog file for reading
+pub fn open_eventlog(path: &str) -> Result<EventLogReader<BufReader<File>>, String> {
+    let file = File::open(path).map_err(|err| format!("Unable to open {}: {}", path, err))?;
+    EventLogReader::new(BufReader::new(file))
+}
+
+impl<R: Read> EventLogReader<R> {
+    /// Reads the header. `reader` should be buffered.
+    pub fn new(mut reader: R) -> Result<EventLogReader<R>, String> {
+        expect_marker(&mut reader, HEADER_BEGIN, "hdrb")?;
+        expect_marker(&mut reader, HET_BEGIN, "hetb")?;
+
+        let mut event_types = vec![];
+        loop {
+            match read_u32(&mut reader)? {
+                ET_BEGIN => {}
+                HET_END => break,
+                other => {
+                    return Err(format!(
+                        "Expected event type or end of event types, found {:#x}",
+                        other
+                    ))
+                }
+            }
+            let id = read_u16(&mut reader)?;
+            let size = read_u16(&mut reader)?;
+            let descr_len = read_u32(&mut reader)?;
+            let description =
+                String::from_utf8_lossy(&read_bytes(&mut reader, descr_len as usize)?).into_owned();
+            let extra_len = read_u32(&mut reader)?;
+            let extra = read_bytes(&mut reader, extra_len as usize)?;
+            expect_marker(&mut reader, ET_END, "ete")?;
+            event_types.push(EventType {
+                id,
+                size: if size == VARIABLE_SIZE {
+                    None
+                } else {
+                    Some(size)
+                },
+                description,
+                extra,
+            });
+        }
+
+        expect_marker(&mut reader, HEADER_END, "hdre")?;
+        expect_marker(&mut reader, DATA_BEGIN, "datb")?;
+
+        let sizes = event_types.iter().map(|ty| (ty.id, ty.size)).collect();
+        Ok(EventLogReader {
+            reader,
+            header: Header { event_types },
+            sizes,
+            offset: 0,
+            block: None,
+            payload: vec![],
+            done: false,
+        })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Payload of the last event read, as in the eventlog. Includes fields we don't decode.
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Reads the next event. Returns `None` at the end of the event data.
+    pub fn next_event(&mut self) -> Result<Option<Event>, String> {
+        if self.done {
+            return Ok(None);
+        }
+
+        let start = self.offset;
+        if let Some((_, end)) = self.block {
+            if start >= end {
+                self.block = None;
+            }
+        }
+
+        let id = self.read_u16()?;
+        if id == DATA_END {
+            self.done = true;
+            return Ok(None);
+        }
+        let time = self.read_u64()?;
+        let size = match self.sizes.get(&id) {
+            None => return Err(format!("Event type {} is not in the header", id)),
+            Some(Some(size)) => *size,
+            Some(None) => self.read_u16()?,
+        };
+        self.payload = read_bytes(&mut self.reader, size as usize)?;
+        self.offset += size as u64;
+
+        let kind = decode(id, &self.payload)
+            .map_err(|err| format!("Unable to decode event {} at offset {}: {}", id, start, err))?;
+
+        if let EventKind::BlockMarker {
+            size, block_cap, ..
+        } = &kind
+        {
+            self.block = Some((*block_cap, start + *size as u64));
+            return Ok(Some(Event {
+                time,
+                cap: None,
+                kind,
+            }));
+        }
+
+        Ok(Some(Event {
+            time,
+            cap: self.block.and_then(|(cap, _)| cap),
+            kind,
+        }))
+    }
+
+    fn read_u16(&mut self) -> Result<u16, String> {
+        self.offset += 2;
+        read_u16(&mut self.reader)
+    }
+
+    fn read_u64(&mut self) -> Result<u64, String> {
+        self.offset += 8;
+        read_u64(&mut self.reader)
+    }
+}
+
+impl<R: Read> Iterator for EventLogReader<R> {
+    type Item = Result<Event, String>;
+
+    fn next(&mut self) -> Option<Result<Event, String>> {
+        match self.next_event() {
+            Ok(event) => event.map(Ok),
+            Err(err) => {
+                // Don't try to read after an error
+                self.done = true;
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+fn read_bytes<R: Read>(reader: &mut R, n: usize) -> Result<Vec<u8>, String> {
+    let mut buf = vec![0; n];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|err| format!("Unexpected end of eventlog: {}", err))?;
+    Ok(buf)
+}
+
+fn read_u16<R: Read>(reader: &mut R) -> Result<u16, String> {
+    let mut buf = [0; 2];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|err| format!("Unexpected end of eventlog: {}", err))?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32, String> {
+    let mut buf = [0; 4];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|err| format!("Unexpected end of eventlog: {}", err))?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64, String> {
+    let mut buf = [0; 8];
+    reader
+        .read_exact(&mut buf)
+        .map_err(|err| format!("Unexpected end of eventlog: {}", err))?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+fn expect_marker<R: Read>(reader: &mut R, marker: u32, name: &str) -> Result<(), String> {
+    let value = read_u32(reader)?;
+    if value == marker {
+        Ok(())
+    } else {
+        Err(format!("Expected \"{}\", found {:#x}", name, value))
+    }
+}
+
+/// A cursor over an event payload
+struct Payload<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Payload<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], String> {
+        if self.pos + n > self.bytes.len() {
+            return Err(format!(
+                "payload too short: {} bytes, tried to read {} bytes at {}",
+                self.bytes.len(),
+                n,
+                self.pos
+            ));
+        }
+        let bytes = &self.bytes[self.pos..self.pos + n];
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, String> {
+        let mut buf = [0; 2];
+        buf.copy_from_slice(self.take(2)?);
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let mut buf = [0; 4];
+        buf.copy_from_slice(self.take(4)?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    fn u64(&mut self) -> Result<u64, String> {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(self.take(8)?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    /// A null-terminated string, or the rest of the payload if there's no null
+    fn string(&mut self) -> Result<String, String> {
+        let rest = &self.bytes[self.pos..];
+        let len = rest.iter().position(|b| *b == 0).unwrap_or(rest.len());
+        let s = String::from_utf8_lossy(&rest[..len]).into_owned();
+        self.pos = std::cmp::min(self.pos + len + 1, self.bytes.len());
+        Ok(s)
+    }
+
+    /// Rest of the payload as a string
+    fn rest_string(&mut self) -> String {
+        let rest = &self.bytes[self.pos..];
+        self.pos = self.bytes.len();
+        // Some GHCs write a null terminator
+        let rest = rest.strip_suffix(&[0]).unwrap_or(rest);
+        String::from_utf8_lossy(rest).into_owned()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.pos >= self.bytes.len()
+    }
+}
+
+fn decode(id: u16, bytes: &[u8]) -> Result<EventKind, String> {
+    let mut p = Payload { bytes, pos: 0 };
+    let p = &mut p;
+    Ok(match id {
+        CREATE_THREAD => EventKind::CreateThread { thread: p.u32()? },
+        RUN_THREAD => EventKind::RunThread { thread: p.u32()? },
+        STOP_THREAD => EventKind::StopThread {
+            thread: p.u32()?,
+            status: p.u16()?,
+            // Not in very old eventlogs
+            blocked_on: if p.is_empty() { 0 } else { p.u32()? },
+        },
+        THREAD_RUNNABLE => EventKind::ThreadRunnable { thread: p.u32()? },
+        MIGRATE_THREAD => EventKind::MigrateThread {
+            thread: p.u32()?,
+            new_cap: p.u16()?,
+        },
+        THREAD_WAKEUP => EventKind::ThreadWakeup {
+            thread: p.u32()?,
+            other_cap: p.u16()?,
+        },
+        THREAD_LABEL => EventKind::ThreadLabel {
+            thread: p.u32()?,
+            label: p.rest_string(),
+        },
+        CREATE_SPARK_THREAD => EventKind::CreateSparkThread { thread: p.u32()? },
+
+        GC_START => EventKind::GcStart,
+        GC_END => EventKind::GcEnd,
+        REQUEST_SEQ_GC => EventKind::RequestSeqGc,
+        REQUEST_PAR_GC => EventKind::RequestParGc,
+        GC_IDLE => EventKind::GcIdle,
+        GC_WORK => EventKind::GcWork,
+        GC_DONE => EventKind::GcDone,
+        GC_GLOBAL_SYNC => EventKind::GcGlobalSync,
+        GC_STATS_GHC => EventKind::GcStats {
+            capset: p.u32()?,
+            generation: p.u16()?,
+            copied: p.u64()?,
+            slop: p.u64()?,
+            fragmentation: p.u64()?,
+            par_n_threads: p.u32()?,
+            par_max_copied: p.u64()?,
+            par_tot_copied: p.u64()?,
+        },
+
+        HEAP_ALLOCATED => EventKind::HeapAllocated {
+            capset: p.u32()?,
+            allocated: p.u64()?,
+        },
+        HEAP_SIZE => EventKind::HeapSize {
+            capset: p.u32()?,
+            size: p.u64()?,
+        },
+        HEAP_LIVE => EventKind::HeapLive {
+            capset: p.u32()?,
+            live: p.u64()?,
+        },
+        BLOCKS_SIZE => EventKind::BlocksSize {
+            capset: p.u32()?,
+            size: p.u64()?,
+        },
+        HEAP_INFO_GHC => EventKind::HeapInfo {
+            capset: p.u32()?,
+            generations: p.u16()?,
+            max_heap_size: p.u64()?,
+            alloc_area_size: p.u64()?,
+            mblock_size: p.u64()?,
+            block_size: p.u64()?,
+        },
+
+        CAP_CREATE => EventKind::CapCreate { cap: p.u16()? },
+        CAP_DELETE => EventKind::CapDelete { cap: p.u16()? },
+        CAP_DISABLE => EventKind::CapDisable { cap: p.u16()? },
+        CAP_ENABLE => EventKind::CapEnable { cap: p.u16()? },
+        CAPSET_CREATE => EventKind::CapsetCreate {
+            capset: p.u32()?,
+            capset_type: p.u16()?,
+        },
+        CAPSET_DELETE => EventKind::CapsetDelete { capset: p.u32()? },
+        CAPSET_ASSIGN_CAP => EventKind::CapsetAssignCap {
+            capset: p.u32()?,
+            cap: p.u16()?,
+        },
+        CAPSET_REMOVE_CAP => EventKind::CapsetRemoveCap {
+            capset: p.u32()?,
+            cap: p.u16()?,
+        },
+        RTS_IDENTIFIER => EventKind::RtsIdentifier {
+            capset: p.u32()?,
+            identifier: p.rest_string(),
+        },
+        PROGRAM_ARGS => {
+            let capset = p.u32()?;
+            let mut args = vec![];
+            while !p.is_empty() {
+                args.push(p.string()?);
+            }
+            EventKind::ProgramArgs { capset, args }
+        }
+        WALL_CLOCK_TIME => EventKind::WallClockTime {
+            capset: p.u32()?,
+            sec: p.u64()?,
+            nsec: p.u32()?,
+        },
+
+        SPARK_COUNTERS => EventKind::SparkCounters {
+            created: p.u64()?,
+            dud: p.u64()?,
+            overflowed: p.u64()?,
+            converted: p.u64()?,
+            gcd: p.u64()?,
+            fizzled: p.u64()?,
+            remaining: p.u64()?,
+        },
+        SPARK_CREATE => EventKind::SparkCreate,
+        SPARK_DUD => EventKind::SparkDud,
+        SPARK_OVERFLOW => EventKind::SparkOverflow,
+        SPARK_RUN => EventKind::SparkRun,
+        SPARK_STEAL => EventKind::SparkSteal {
+            victim_cap: p.u16()?,
+        },
+        SPARK_FIZZLE => EventKind::SparkFizzle,
+        SPARK_GC => EventKind::SparkGc,
+
+        LOG_MSG => EventKind::LogMsg {
+            msg: p.rest_string(),
+        },
+        USER_MSG => EventKind::UserMsg {
+            msg: p.rest_string(),
+        },
+        USER_MARKER => EventKind::UserMarker {
+            marker: p.rest_string(),
+        },
+
+        HEAP_PROF_BEGIN => {
+            let profile = p.u8()?;
+            let sampling_period = p.u64()?;
+            let breakdown = p.u32()?;
+            let mut filters = vec![];
+            while !p.is_empty() {
+                filters.push(p.string()?);
+            }
+            EventKind::HeapProfBegin {
+                profile,
+                sampling_period,
+                breakdown,
+                filters,
+            }
+        }
+        HEAP_PROF_COST_CENTRE => EventKind::HeapProfCostCentre {
+            id: p.u32()?,
+            label: p.string()?,
+            module: p.string()?,
+            src_loc: p.string()?,
+            is_caf: p.u8()? & 1 != 0,
+        },
+        HEAP_PROF_SAMPLE_BEGIN => EventKind::HeapProfSampleBegin { era: p.u64()? },
+        HEAP_PROF_SAMPLE_END => EventKind::HeapProfSampleEnd { era: p.u64()? },
+        HEAP_BIO_PROF_SAMPLE_BEGIN => EventKind::HeapBioProfSampleBegin {
+            era: p.u64()?,
+            time: p.u64()?,
+        },
+        HEAP_PROF_SAMPLE_COST_CENTRE => {
+            let profile = p.u8()?;
+            let residency = p.u64()?;
+            let depth = p.u8()?;
+            let stack = (0..depth).map(|_| p.u32()).collect::<Result<_, _>>()?;
+            EventKind::HeapProfSampleCostCentre {
+                profile,
+                residency,
+                stack,
+            }
+        }
+        HEAP_PROF_SAMPLE_STRING => EventKind::HeapProfSampleString {
+            profile: p.u8()?,
+            residency: p.u64()?,
+            label: p.string()?,
+        },
+        PROF_SAMPLE_COST_CENTRE => {
+            let cap = p.u32()?;
+            let ticks = p.u64()?;
+            let depth = p.u8()?;
+            let stack = (0..depth).map(|_| p.u32()).collect::<Result<_, _>>()?;
+            EventKind::ProfSampleCostCentre { cap, ticks, stack }
+        }
+        PROF_BEGIN => EventKind::ProfBegin {
+            tick_interval: p.u64()?,
+        },
+        IPE => {
+            let info = p.u64()?;
+            let mut strings = vec![];
+            while !p.is_empty() {
+                strings.push(p.string()?);
+            }
+            // GHC 9.6 and later write source file and span separately
+            let src_loc = if strings.len() > 6 {
+                strings[5..].join(":")
+            } else {
+                strings.get(5).cloned().unwrap_or_default()
+            };
+            strings.resize(5, String::new());
+            let mut strings = strings.into_iter();
+            EventKind::Ipe {
+                info,
+                table_name: strings.next().unwrap(),
+                closure_desc: strings.next().unwrap(),
+                ty_desc: strings.next().unwrap(),
+                label: strings.next().unwrap(),
+                module: strings.next().unwrap(),
+                src_loc,
+            }
+        }
+
+        TICKY_COUNTER_DEF => EventKind::TickyCounterDef {
+            id: p.u64()?,
+            arity: p.u16()?,
+            kinds: p.string()?,
+            name: p.string()?,
+        },
+        TICKY_COUNTER_SAMPLE => EventKind::TickyCounterSample {
+            id: p.u64()?,
+            entries: p.u64()?,
+            alloc: p.u64()?,
+            allocd: p.u64()?,
+        },
+        TICKY_BEGIN_SAMPLE => EventKind::TickyBeginSample,
+
+        BLOCK_MARKER => {
+            let size = p.u32()?;
+            let end_time = p.u64()?;
+            let cap = p.u16()?;
+            EventKind::BlockMarker {
+                size,
+                end_time,
+                block_cap: if cap == NO_CAP { None } else { Some(cap) },
+            }
+        }
+
+        _ => EventKind::Unknown {
+            id,
+            payload: bytes.to_vec(),
+        },
+    })
+}
+
+/// Builds eventlogs for tests
+#[cfg(test)]
+pub(crate) struct TestLog {
+    types: Vec<u8>,
+    events: Vec<u8>,
+    sizes: HashMap<u16, Option<u16>>,
+}
+
+#[cfg(test)]
+impl TestLog {
+    pub(crate) fn new() -> TestLog {
+        TestLog {
+            types: vec![],
+            events: vec![],
+            sizes: HashMap::new(),
+        }
+    }
+
+    /// Adds an event type to the header. `size` is `None` for variable-size events.
+    pub(crate) fn event_type(&mut self, id: u16, size: Option<u16>, description: &str) {
+        self.sizes.insert(id, size);
+        self.types.extend_from_slice(&ET_BEGIN.to_be_bytes());
+        self.types.extend_from_slice(&id.to_be_bytes());
+        self.types
+            .extend_from_slice(&size.unwrap_or(VARIABLE_SIZE).to_be_bytes());
+        self.types
+            .extend_from_slice(&(description.len() as u32).to_be_bytes());
+        self.types.extend_from_slice(description.as_bytes());
+        self.types.extend_from_slice(&0u32.to_be_bytes());
+        self.types.extend_from_slice(&ET_END.to_be_bytes());
+    }
+
+    pub(crate) fn event(&mut self, id: u16, time: u64, payload: &[u8]) {
+        self.events.extend_from_slice(&id.to_be_bytes());
+        self.events.extend_from_slice(&time.to_be_bytes());
+        if self.sizes[&id].is_none() {
+            self.events
+                .extend_from_slice(&(payload.len() as u16).to_be_bytes());
+        }
+        self.events.extend_from_slice(payload);
+    }
+
+    pub(crate) fn bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&HEADER_BEGIN.to_be_bytes());
+        bytes.extend_from_slice(&HET_BEGIN.to_be_bytes());
+        bytes.extend_from_slice(&self.types);
+        bytes.extend_from_slice(&HET_END.to_be_bytes());
+        bytes.extend_from_slice(&HEADER_END.to_be_bytes());
+        bytes.extend_from_slice(&DATA_BEGIN.to_be_bytes());
+        bytes.extend_from_slice(&self.events);
+        bytes.extend_from_slice(&DATA_END.to_be_bytes());
+        bytes
+    }
+}
+
+/// An event as yielded by `EventLogReader`, for tests of the readers built on top of it
+#[cfg(test)]
+pub(crate) fn test_event(time: u64, cap: Option<u16>, kind: EventKind) -> Result<Event, String> {
+    Ok(Event { time, cap, kind })
+}
+
+#[test]
+fn read_test() {
+    let mut log = TestLog::new();
+    log.event_type(GC_START, Some(0), "Start GC");
+    log.event_type(HEAP_SIZE, Some(12), "Heap size");
+    log.event_type(BLOCK_MARKER, Some(14), "Block marker");
+    log.event_type(USER_MSG, None, "User message");
+    log.event_type(250, Some(3), "From the future");
+
+    let mut marker = vec![];
+    // Marker (10 + 14 bytes), GC start (10 bytes)
+    marker.extend_from_slice(&34u32.to_be_bytes());
+    marker.extend_from_slice(&200u64.to_be_bytes());
+    marker.extend_from_slice(&1u16.to_be_bytes());
+    log.event(BLOCK_MARKER, 100, &marker);
+    log.event(GC_START, 100, &[]);
+    log.event(USER_MSG, 150, b"hello");
+    log.event(250, 160, &[1, 2, 3]);
+    let mut heap_size = vec![];
+    heap_size.extend_from_slice(&0u32.to_be_bytes());
+    heap_size.extend_from_slice(&4096u64.to_be_bytes());
+    log.event(HEAP_SIZE, 170, &heap_size);
+
+    let bytes = log.bytes();
+    let reader = EventLogReader::new(&bytes[..]).unwrap();
+    assert_eq!(reader.header().event_types.len(), 5);
+    assert_eq!(reader.header().event_types[3].size, None);
+
+    let events: Vec<Event> = reader.collect::<Result<_, _>>().unwrap();
+    assert_eq!(events.len(), 5);
+    assert_eq!(
+        events[1],
+        Event {
+            time: 100,
+            cap: Some(1),
+            kind: EventKind::GcStart
+        }
+    );
+    // Outside of the block
+    assert_eq!(
+        events[2],
+        Event {
+            time: 150,
+            cap: None,
+            kind: EventKind::UserMsg {
+                msg: "hello".to_owned()
+            }
+        }
+    );
+    assert_eq!(
+        events[3].kind,
+        EventKind::Unknown {
+            id: 250,
+            payload: vec![1, 2, 3]
+        }
+    );
+    assert_eq!(
+        events[4].kind,
+        EventKind::HeapSize {
+            capset: 0,
+            size: 4096
+        }
+    );
+
+    // Truncated eventlog
+    let err = EventLogReader::new(&bytes[..bytes.len() - 5])
+        .unwrap()
+        .find_map(|event| event.err());
+    assert!(err.unwrap().starts_with("Unexpected end of eventlog"));
+}
+
+#[test]
+fn event_ids_test() {
+    // Ids from GHC's `EventLogFormat.h`, not the constants above, to catch typos in the constants
+    let mut blocks_size = vec![];
+    blocks_size.extend_from_slice(&0u32.to_be_bytes());
+    blocks_size.extend_from_slice(&(1u64 << 20).to_be_bytes());
+    let kind = EventKind::BlocksSize {
+        capset: 0,
+        size: 1 << 20,
+    };
+    assert_eq!(decode(91, &blocks_size).unwrap(), kind);
+    assert_eq!(kind.type_id(), 91);
+    assert_eq!(
+        decode(61, &blocks_size).unwrap(),
+        EventKind::Unknown {
+            id: 61,
+            payload: blocks_size.clone()
+        }
+    );
+}
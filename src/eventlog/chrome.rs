@@ -0,0 +1,326 @@
+//! Chrome trace event format, for viewing eventlogs in `chrome://tracing` or Perfetto
+//!
+//! See "Trace Event Format" document for the format. Times in the trace are in microseconds.
+//!
+//! In traces converted from eventlogs capabilities are trace threads with Haskell threads and GCs
+//! on them as slices. GCs with their statistics are also shown on a separate "GC" trace thread.
+//! User messages and markers are instant events, and heap size and live bytes are counters.
+
+use super::gc::{is_gc_event, read_gcs};
+use super::spans::read_timeline;
+use super::threads::{activity_trace_events, is_activity_event, read_activity};
+use super::{Event, EventKind};
+
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use std::io::Write;
+
+/// Trace thread id of the GC track, after the capabilities
+const GC_TID: u32 = 0x1_0000;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TraceEvent {
+    pub name: String,
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub cat: String,
+    /// Phase: `X` for complete events, `i` for instant events, `C` for counters, `M` for
+    /// metadata
+    pub ph: char,
+    /// In microseconds
+    pub ts: f64,
+    /// In microseconds, for complete events
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dur: Option<f64>,
+    pub pid: u32,
+    pub tid: u32,
+    /// Scope of instant events: `g`lobal, `p`rocess or `t`hread
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub s: Option<char>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    pub args: Map<String, Value>,
+}
+
+fn us(ns: u64) -> f64 {
+    (ns as f64) / 1000.0
+}
+
+impl TraceEvent {
+    /// An event with a duration. Times are in nanoseconds.
+    pub fn complete(name: String, cat: &str, start: u64, end: u64, pid: u32, tid: u32) -> Self {
+        TraceEvent {
+            name,
+            cat: cat.to_owned(),
+            ph: 'X',
+            ts: us(start),
+            dur: Some(us(end.saturating_sub(start))),
+            pid,
+            tid,
+            s: None,
+            args: Map::new(),
+        }
+    }
+
+    /// An event without a duration, shown on the thread `tid`. Time is in nanoseconds.
+    pub fn instant(name: String, cat: &str, time: u64, pid: u32, tid: u32) -> Self {
+        TraceEvent {
+            name,
+            cat: cat.to_owned(),
+            ph: 'i',
+            ts: us(time),
+            dur: None,
+            pid,
+            tid,
+            s: Some('t'),
+            args: Map::new(),
+        }
+    }
+
+    /// A counter sample, values are in `args`. Time is in nanoseconds.
+    pub fn counter(name: String, time: u64, pid: u32, args: Map<String, Value>) -> Self {
+        TraceEvent {
+            name,
+            cat: String::new(),
+            ph: 'C',
+            ts: us(time),
+            dur: None,
+            pid,
+            tid: 0,
+            s: None,
+            args,
+        }
+    }
+
+    /// Names the thread `tid` in trace viewers
+    pub fn thread_name(pid: u32, tid: u32, name: String) -> Self {
+        let mut args = Map::new();
+        args.insert("name".to_owned(), Value::String(name));
+        TraceEvent {
+            name: "thread_name".to_owned(),
+            cat: String::new(),
+            ph: 'M',
+            ts: 0.0,
+            dur: None,
+            pid,
+            tid,
+            s: None,
+            args,
+        }
+    }
+
+    /// Names the process `pid` in trace viewers
+    pub fn process_name(pid: u32, name: String) -> Self {
+        let mut args = Map::new();
+        args.insert("name".to_owned(), Value::String(name));
+        TraceEvent {
+            name: "process_name".to_owned(),
+            cat: String::new(),
+            ph: 'M',
+            ts: 0.0,
+            dur: None,
+            pid,
+            tid: 0,
+            s: None,
+            args,
+        }
+    }
+
+    pub fn with_arg<V: Into<Value>>(mut self, key: &str, value: V) -> Self {
+        self.args.insert(key.to_owned(), value.into());
+        self
+    }
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Trace<'a> {
+    trace_events: &'a [TraceEvent],
+    display_time_unit: &'static str,
+}
+
+/// Writes trace events as a JSON trace
+pub fn write_chrome_trace<W: Write>(events: &[TraceEvent], w: &mut W) {
+    let trace = Trace {
+        trace_events: events,
+        display_time_unit: "ms",
+    };
+    serde_json::to_writer(&mut *w, &trace).unwrap();
+    writeln!(w).unwrap();
+}
+
+/// Converts an eventlog to trace events
+pub fn eventlog_trace_events<I: Iterator<Item = Result<Event, String>>>(
+    events: I,
+) -> Result<Vec<TraceEvent>, String> {
+    let mut program = String::new();
+    let mut trace_events: Vec<Event> = vec![];
+    for event in events {
+        let event = event?;
+        match event.kind {
+            EventKind::ProgramArgs { ref args, .. } if program.is_empty() => {
+                program = args.first().cloned().unwrap_or_default();
+            }
+            EventKind::UserMsg { .. } | EventKind::UserMarker { .. } => trace_events.push(event),
+            ref kind if is_gc_event(kind) || is_activity_event(kind) => trace_events.push(event),
+            _ => {}
+        }
+    }
+
+    let mut events = vec![TraceEvent::process_name(
+        0,
+        if program.is_empty() {
+            "eventlog".to_owned()
+        } else {
+            program
+        },
+    )];
+
+    // Capabilities
+    let activity = read_activity(trace_events.iter().cloned().map(Ok))?;
+    events.extend(activity_trace_events(&activity));
+
+    // GCs
+    let gcs = read_gcs(trace_events.iter().cloned().map(Ok))?;
+    events.push(TraceEvent::thread_name(0, GC_TID, "GC".to_owned()));
+    for gc in &gcs.gcs {
+        let mut event = TraceEvent::complete(
+            match gc.generation {
+                None => "GC".to_owned(),
+                Some(gen) => format!("GC gen {}", gen),
+            },
+            "gc",
+            gc.start,
+            gc.end,
+            0,
+            GC_TID,
+        );
+        let stats = [
+            ("generation", gc.generation.map(u64::from)),
+            ("copied", gc.copied),
+            ("live", gc.live),
+            ("heap_size", gc.heap_size),
+            ("par_n_threads", gc.par_n_threads.map(u64::from)),
+        ];
+        for (key, value) in stats.iter() {
+            if let Some(value) = value {
+                event = event.with_arg(key, *value);
+            }
+        }
+        events.push(event);
+    }
+
+    // Heap counters
+    for event in &trace_events {
+        let (name, value) = match event.kind {
+            EventKind::HeapSize { size, .. } => ("heap size", size),
+            EventKind::HeapLive { live, .. } => ("heap live", live),
+            _ => continue,
+        };
+        let mut args = Map::new();
+        args.insert("bytes".to_owned(), value.into());
+        events.push(TraceEvent::counter(name.to_owned(), event.time, 0, args));
+    }
+
+    // User messages and markers
+    let timeline = read_timeline(trace_events.into_iter().map(Ok))?;
+    for msg in timeline.messages {
+        let cat = if msg.marker { "marker" } else { "message" };
+        let tid = msg.cap.map(u32::from).unwrap_or(0);
+        let mut event = TraceEvent::instant(msg.msg, cat, msg.time, 0, tid);
+        // Messages not posted by a capability are shown on all tracks
+        if msg.cap.is_none() {
+            event.s = Some('g');
+        }
+        events.push(event);
+    }
+
+    Ok(events)
+}
+
+#[test]
+fn write_chrome_trace_test() {
+    let events = vec![
+        TraceEvent::thread_name(0, 1, "cap 1".to_owned()),
+        TraceEvent::complete("thread 3".to_owned(), "thread", 1500, 4000, 0, 1)
+            .with_arg("stop", "HeapOverflow"),
+    ];
+    let mut out = vec![];
+    write_chrome_trace(&events, &mut out);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "{\"traceEvents\":[\
+         {\"name\":\"thread_name\",\"ph\":\"M\",\"ts\":0.0,\"pid\":0,\"tid\":1,\
+         \"args\":{\"name\":\"cap 1\"}},\
+         {\"name\":\"thread 3\",\"cat\":\"thread\",\"ph\":\"X\",\"ts\":1.5,\"dur\":2.5,\
+         \"pid\":0,\"tid\":1,\"args\":{\"stop\":\"HeapOverflow\"}}\
+         ],\"displayTimeUnit\":\"ms\"}\n"
+    );
+}
+
+#[test]
+fn eventlog_trace_events_test() {
+    use super::*;
+
+    let mut log = TestLog::new();
+    log.event_type(BLOCK_MARKER, Some(14), "Block marker");
+    log.event_type(RUN_THREAD, Some(4), "Run thread");
+    log.event_type(STOP_THREAD, Some(10), "Stop thread");
+    log.event_type(GC_START, Some(0), "Start GC");
+    log.event_type(GC_END, Some(0), "End GC");
+    log.event_type(GC_STATS_GHC, Some(50), "GC statistics");
+    log.event_type(HEAP_SIZE, Some(12), "Heap size");
+    log.event_type(USER_MARKER, None, "User marker");
+
+    // Block of cap 0: marker (24 bytes), run (14), stop (20), GC start (10), GC stats (60),
+    // GC end (10), user marker (17)
+    let mut marker = vec![];
+    marker.extend_from_slice(&155u32.to_be_bytes());
+    marker.extend_from_slice(&7000u64.to_be_bytes());
+    marker.extend_from_slice(&0u16.to_be_bytes());
+    log.event(BLOCK_MARKER, 0, &marker);
+    log.event(RUN_THREAD, 0, &1u32.to_be_bytes());
+    let mut stop = vec![];
+    stop.extend_from_slice(&1u32.to_be_bytes());
+    stop.extend_from_slice(&1u16.to_be_bytes()); // HeapOverflow
+    stop.extend_from_slice(&0u32.to_be_bytes());
+    log.event(STOP_THREAD, 4000, &stop);
+    log.event(GC_START, 4000, &[]);
+    let mut stats = vec![];
+    stats.extend_from_slice(&0u32.to_be_bytes());
+    stats.extend_from_slice(&1u16.to_be_bytes());
+    for n in &[100u64, 0, 0] {
+        stats.extend_from_slice(&n.to_be_bytes());
+    }
+    stats.extend_from_slice(&1u32.to_be_bytes());
+    stats.extend_from_slice(&[0; 16]);
+    log.event(GC_STATS_GHC, 5000, &stats);
+    log.event(GC_END, 6000, &[]);
+    log.event(USER_MARKER, 7000, b"hello");
+
+    let mut heap_size = vec![];
+    heap_size.extend_from_slice(&0u32.to_be_bytes());
+    heap_size.extend_from_slice(&4096u64.to_be_bytes());
+    log.event(HEAP_SIZE, 6000, &heap_size);
+
+    let bytes = log.bytes();
+    let events = eventlog_trace_events(EventLogReader::new(&bytes[..]).unwrap()).unwrap();
+    let find = |name: &str| events.iter().find(|event| event.name == name).unwrap();
+
+    let thread = find("thread 1");
+    assert_eq!((thread.ph, thread.tid, thread.ts), ('X', 0, 0.0));
+    assert_eq!(thread.dur, Some(4.0));
+
+    let gc = find("GC gen 1");
+    assert_eq!((gc.tid, gc.ts, gc.dur), (GC_TID, 4.0, Some(2.0)));
+    assert_eq!(gc.args["copied"], 100);
+    assert_eq!(gc.args["heap_size"], 4096);
+
+    let heap = find("heap size");
+    assert_eq!((heap.ph, heap.ts), ('C', 6.0));
+
+    let marker = find("hello");
+    assert_eq!(
+        (marker.ph, marker.cat.as_str(), marker.tid),
+        ('i', "marker", 0)
+    );
+}
@@ -0,0 +1,147 @@
+//! Filtering eventlogs by time, capability and event type
+//!
+//! Events describing the run rather than happening at a point in it (program arguments, RTS
+//! identifier, capsets and capabilities, heap info, cost centres, info tables) are kept outside
+//! of the time window, so that filtered eventlogs can still be analysed.
+
+use super::{Event, EventKind};
+
+use std::collections::HashSet;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EventFilter {
+    /// Start of the time window, in nanoseconds
+    pub from: Option<u64>,
+    /// End of the time window (exclusive), in nanoseconds
+    pub to: Option<u64>,
+    /// Capabilities to keep events of. Events not posted by a capability are always kept.
+    pub caps: Option<HashSet<u16>>,
+    /// Event type ids to keep
+    pub event_types: Option<HashSet<u16>>,
+}
+
+fn is_metadata(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::ProgramArgs { .. }
+            | EventKind::RtsIdentifier { .. }
+            | EventKind::WallClockTime { .. }
+            | EventKind::CapsetCreate { .. }
+            | EventKind::CapsetAssignCap { .. }
+            | EventKind::CapCreate { .. }
+            | EventKind::HeapInfo { .. }
+            | EventKind::HeapProfBegin { .. }
+            | EventKind::HeapProfCostCentre { .. }
+            | EventKind::ProfBegin { .. }
+            | EventKind::Ipe { .. }
+            | EventKind::TickyCounterDef { .. }
+    )
+}
+
+impl EventFilter {
+    pub fn keep(&self, event: &Event) -> bool {
+        if let Some(event_types) = &self.event_types {
+            if !event_types.contains(&event.kind.type_id()) {
+                return false;
+            }
+        }
+        if let (Some(caps), Some(cap)) = (&self.caps, event.cap) {
+            if !caps.contains(&cap) {
+                return false;
+            }
+        }
+        if is_metadata(&event.kind) {
+            return true;
+        }
+        self.from.is_none_or(|from| event.time >= from) && self.to.is_none_or(|to| event.time < to)
+    }
+}
+
+#[test]
+fn filter_test() {
+    use super::{EventLogReader, EventLogWriter};
+
+    let event = |time, cap, kind| Event { time, cap, kind };
+    let msg = |msg: &str| EventKind::UserMsg {
+        msg: msg.to_owned(),
+    };
+    let events = [
+        event(0, None, EventKind::CapCreate { cap: 1 }),
+        event(10, Some(0), msg("a")),
+        event(20, Some(1), msg("b")),
+        event(20, Some(1), EventKind::GcStart),
+        event(30, Some(0), msg("c")),
+        event(40, None, msg("d")),
+    ];
+
+    let filter = EventFilter {
+        from: Some(15),
+        to: Some(40),
+        caps: Some(vec![1].into_iter().collect()),
+        event_types: None,
+    };
+    let kept: Vec<Event> = events
+        .iter()
+        .filter(|event| filter.keep(event))
+        .cloned()
+        .collect();
+    assert_eq!(
+        kept,
+        vec![events[0].clone(), events[2].clone(), events[3].clone()]
+    );
+
+    let filter = EventFilter {
+        event_types: Some(vec![9].into_iter().collect()), // GC start
+        ..EventFilter::default()
+    };
+    assert_eq!(
+        events
+            .iter()
+            .filter(|event| filter.keep(event))
+            .collect::<Vec<_>>(),
+        vec![&events[3]]
+    );
+
+    // Filtered eventlogs are valid
+    let header = super::Header {
+        event_types: vec![
+            super::EventType {
+                id: 45,
+                size: Some(2),
+                description: "Create capability".to_owned(),
+                extra: vec![],
+            },
+            super::EventType {
+                id: 19,
+                size: None,
+                description: "User message".to_owned(),
+                extra: vec![],
+            },
+            super::EventType {
+                id: 9,
+                size: Some(0),
+                description: "Start GC".to_owned(),
+                extra: vec![],
+            },
+        ],
+    };
+    let mut writer = EventLogWriter::new(vec![], &header).unwrap();
+    for event in &kept {
+        writer.write_event(event).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+    let read: Vec<Event> = EventLogReader::new(&bytes[..])
+        .unwrap()
+        .filter(|event| {
+            !matches!(
+                event,
+                Ok(Event {
+                    kind: EventKind::BlockMarker { .. },
+                    ..
+                })
+            )
+        })
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(read, kept);
+}
@@ -0,0 +1,330 @@
+//! Reconstructing garbage collections from eventlog events.
+//!
+//! In the threaded RTS every capability taking part in a GC posts its own GC start and end events,
+//! so a GC (i.e. a pause of the mutator) lasts from the first capability starting the GC to the
+//! last one finishing it. Statistics of a GC (generation, bytes copied) and heap size and live
+//! bytes are posted around the end of the GC and are attributed to the last GC started before
+//! them.
+//!
+//! Capabilities write events to separate buffers, so events are not ordered by time in the
+//! eventlog. We only keep the GC-related events and sort them.
+
+use super::{ms, secs, Event, EventKind};
+use crate::utils::percentage;
+
+use std::collections::HashSet;
+use std::io::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gc {
+    /// Time of the first GC start event, in nanoseconds
+    pub start: u64,
+    /// Time of the last GC end event, in nanoseconds
+    pub end: u64,
+    /// Time all capabilities stopped, in nanoseconds
+    pub sync: Option<u64>,
+    /// Oldest generation collected
+    pub generation: Option<u16>,
+    pub copied: Option<u64>,
+    pub live: Option<u64>,
+    pub heap_size: Option<u64>,
+    pub par_n_threads: Option<u32>,
+}
+
+impl Gc {
+    /// Pause duration in nanoseconds
+    pub fn pause(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Gcs {
+    pub gcs: Vec<Gc>,
+    /// Time of the last event in the eventlog, in nanoseconds
+    pub end_time: u64,
+}
+
+/// Is the event used in reconstructing GCs?
+pub(super) fn is_gc_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::GcStart
+            | EventKind::GcEnd
+            | EventKind::GcGlobalSync
+            | EventKind::GcStats { .. }
+            | EventKind::HeapLive { .. }
+            | EventKind::HeapSize { .. }
+    )
+}
+
+/// Reconstructs GCs from a stream of events
+pub fn read_gcs<I: Iterator<Item = Result<Event, String>>>(events: I) -> Result<Gcs, String> {
+    let mut end_time = 0;
+    let mut gc_events: Vec<Event> = vec![];
+    for event in events {
+        let event = event?;
+        end_time = std::cmp::max(end_time, event.time);
+        if is_gc_event(&event.kind) {
+            gc_events.push(event);
+        }
+    }
+    gc_events.sort_by_key(|event| event.time);
+
+    let mut gcs: Vec<Gc> = vec![];
+    // Capabilities in the current GC. `None` is used for events outside of capability buffers,
+    // e.g. in the non-threaded RTS.
+    let mut in_gc: HashSet<Option<u16>> = HashSet::new();
+    for event in gc_events {
+        match event.kind {
+            EventKind::GcStart => {
+                if in_gc.is_empty() {
+                    gcs.push(Gc {
+                        start: event.time,
+                        end: event.time,
+                        sync: None,
+                        generation: None,
+                        copied: None,
+                        live: None,
+                        heap_size: None,
+                        par_n_threads: None,
+                    });
+                }
+                in_gc.insert(event.cap);
+            }
+            EventKind::GcEnd => {
+                in_gc.remove(&event.cap);
+                if let Some(gc) = gcs.last_mut() {
+                    gc.end = std::cmp::max(gc.end, event.time);
+                }
+            }
+            EventKind::GcGlobalSync => {
+                if let Some(gc) = gcs.last_mut() {
+                    gc.sync.get_or_insert(event.time);
+                }
+            }
+            EventKind::GcStats {
+                generation,
+                copied,
+                par_n_threads,
+                ..
+            } => {
+                if let Some(gc) = gcs.last_mut() {
+                    gc.generation = Some(generation);
+                    gc.copied = Some(copied);
+                    gc.par_n_threads = Some(par_n_threads);
+                }
+            }
+            EventKind::HeapLive { live, .. } => {
+                if let Some(gc) = gcs.last_mut() {
+                    gc.live = Some(live);
+                }
+            }
+            EventKind::HeapSize { size, .. } => {
+                if let Some(gc) = gcs.last_mut() {
+                    gc.heap_size = Some(size);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(Gcs { gcs, end_time })
+}
+
+/// Pause at the given percentile, nearest-rank method. `pauses` should be sorted.
+fn percentile(pauses: &[u64], p: f64) -> u64 {
+    if pauses.is_empty() {
+        return 0;
+    }
+    let rank = ((p / 100.0) * (pauses.len() as f64)).ceil() as usize;
+    pauses[std::cmp::max(rank, 1) - 1]
+}
+
+fn opt<T: std::fmt::Display>(v: Option<T>) -> String {
+    match v {
+        None => "-".to_owned(),
+        Some(v) => v.to_string(),
+    }
+}
+
+/// Writes one line per GC
+pub fn write_gcs<W: Write>(gcs: &Gcs, w: &mut W) {
+    writeln!(
+        w,
+        "start (s)\tgen\tpause (ms)\tsync (ms)\tcopied\tlive\theap size"
+    )
+    .unwrap();
+    for gc in &gcs.gcs {
+        writeln!(
+            w,
+            "{:.6}\t{}\t{:.3}\t{}\t{}\t{}\t{}",
+            secs(gc.start),
+            opt(gc.generation),
+            ms(gc.pause()),
+            opt(gc.sync.map(|sync| format!("{:.3}", ms(sync - gc.start)))),
+            opt(gc.copied),
+            opt(gc.live),
+            opt(gc.heap_size),
+        )
+        .unwrap();
+    }
+}
+
+/// Writes pause percentiles, GC time per generation and the `n_longest` longest pauses
+pub fn write_gc_summary<W: Write>(gcs: &Gcs, n_longest: usize, w: &mut W) {
+    let mut pauses: Vec<u64> = gcs.gcs.iter().map(Gc::pause).collect();
+    pauses.sort_unstable();
+    let total: u64 = pauses.iter().sum();
+
+    writeln!(w, "GCs:           {}", gcs.gcs.len()).unwrap();
+    writeln!(
+        w,
+        "total GC time: {:.3} ms ({:.2}% of {:.3} s)",
+        ms(total),
+        percentage(total, gcs.end_time),
+        secs(gcs.end_time)
+    )
+    .unwrap();
+    if let Some(max_live) = gcs.gcs.iter().filter_map(|gc| gc.live).max() {
+        writeln!(w, "max. live:     {} bytes", max_live).unwrap();
+    }
+    if let Some(max_heap) = gcs.gcs.iter().filter_map(|gc| gc.heap_size).max() {
+        writeln!(w, "max. heap:     {} bytes", max_heap).unwrap();
+    }
+    let copied: u64 = gcs.gcs.iter().filter_map(|gc| gc.copied).sum();
+    writeln!(w, "total copied:  {} bytes", copied).unwrap();
+
+    writeln!(w).unwrap();
+    writeln!(w, "Pauses:").unwrap();
+    for p in &[50.0, 90.0, 99.0, 100.0] {
+        writeln!(w, "  p{:<3}  {:.3} ms", p, ms(percentile(&pauses, *p))).unwrap();
+    }
+
+    writeln!(w).unwrap();
+    writeln!(w, "Generations:").unwrap();
+    let max_gen = gcs.gcs.iter().filter_map(|gc| gc.generation).max();
+    let mut gens: Vec<Option<u16>> = match max_gen {
+        None => vec![],
+        Some(max_gen) => (0..=max_gen).map(Some).collect(),
+    };
+    if gcs.gcs.iter().any(|gc| gc.generation.is_none()) {
+        gens.push(None);
+    }
+    for gen in gens {
+        let gen_pauses: Vec<u64> = gcs
+            .gcs
+            .iter()
+            .filter(|gc| gc.generation == gen)
+            .map(Gc::pause)
+            .collect();
+        if gen_pauses.is_empty() {
+            continue;
+        }
+        let gen_total: u64 = gen_pauses.iter().sum();
+        writeln!(
+            w,
+            "  gen {}: {} GCs, total {:.3} ms, mean {:.3} ms, max {:.3} ms",
+            opt(gen),
+            gen_pauses.len(),
+            ms(gen_total),
+            ms(gen_total) / (gen_pauses.len() as f64),
+            ms(*gen_pauses.iter().max().unwrap()),
+        )
+        .unwrap();
+    }
+
+    writeln!(w).unwrap();
+    writeln!(w, "Longest pauses:").unwrap();
+    let mut longest: Vec<&Gc> = gcs.gcs.iter().collect();
+    longest.sort_by_key(|gc| std::cmp::Reverse(gc.pause()));
+    for gc in longest.into_iter().take(n_longest) {
+        writeln!(
+            w,
+            "  {:.3} ms at {:.6} s, gen {}, copied {}, live {}",
+            ms(gc.pause()),
+            secs(gc.start),
+            opt(gc.generation),
+            opt(gc.copied),
+            opt(gc.live),
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn read_gcs_test() {
+    use super::test_event;
+
+    let stats = |generation, copied| EventKind::GcStats {
+        capset: 0,
+        generation,
+        copied,
+        slop: 0,
+        fragmentation: 0,
+        par_n_threads: 2,
+        par_max_copied: 0,
+        par_tot_copied: 0,
+    };
+
+    // Two capabilities, buffers written one after the other
+    let events = vec![
+        test_event(1_000_000, Some(0), EventKind::GcStart),
+        test_event(1_100_000, Some(0), EventKind::GcGlobalSync),
+        test_event(1_500_000, Some(0), stats(0, 100)),
+        test_event(1_600_000, Some(0), EventKind::GcEnd),
+        test_event(
+            1_600_000,
+            Some(0),
+            EventKind::HeapLive {
+                capset: 0,
+                live: 1000,
+            },
+        ),
+        test_event(5_000_000, Some(0), EventKind::GcStart),
+        test_event(9_000_000, Some(0), stats(1, 500)),
+        test_event(9_000_000, Some(0), EventKind::GcEnd),
+        test_event(1_050_000, Some(1), EventKind::GcStart),
+        test_event(1_700_000, Some(1), EventKind::GcEnd),
+        test_event(
+            10_000_000,
+            Some(1),
+            EventKind::UserMsg { msg: "".to_owned() },
+        ),
+    ];
+
+    let gcs = read_gcs(events.into_iter()).unwrap();
+    assert_eq!(gcs.end_time, 10_000_000);
+    assert_eq!(gcs.gcs.len(), 2);
+    assert_eq!(
+        gcs.gcs[0],
+        Gc {
+            start: 1_000_000,
+            end: 1_700_000,
+            sync: Some(1_100_000),
+            generation: Some(0),
+            copied: Some(100),
+            live: Some(1000),
+            heap_size: None,
+            par_n_threads: Some(2),
+        }
+    );
+    assert_eq!(gcs.gcs[1].pause(), 4_000_000);
+
+    let mut out = vec![];
+    write_gc_summary(&gcs, 1, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("total GC time: 4.700 ms (47.00% of 0.010 s)\n"));
+    assert!(out.contains("  gen 1: 1 GCs, total 4.000 ms, mean 4.000 ms, max 4.000 ms\n"));
+    assert!(out.contains("  4.000 ms at 0.005000 s, gen 1, copied 500, live -\n"));
+}
+
+#[test]
+fn percentile_test() {
+    let pauses = [1, 2, 3, 4, 5, 6, 7, 8, 9, 10];
+    assert_eq!(percentile(&pauses, 50.0), 5);
+    assert_eq!(percentile(&pauses, 90.0), 9);
+    assert_eq!(percentile(&pauses, 99.0), 10);
+    assert_eq!(percentile(&pauses, 100.0), 10);
+    assert_eq!(percentile(&[], 50.0), 0);
+}
@@ -0,0 +1,211 @@
+//! Extracting heap profiles from eventlogs (`+RTS -l -h*`)
+//!
+//! Heap profile samples in eventlogs are labelled either with a string (`-hT`, `-hd`, `-hy`, ...)
+//! or with a cost-centre stack (`-hc`). With `-hi` the string is the address of an info table,
+//! which we resolve to the info table's name and source location using IPE events when the
+//! program was built with `-finfo-table-map`.
+
+use super::{secs, Event, EventKind};
+use crate::hp::{HpFile, Sample};
+
+use std::collections::HashMap;
+
+enum Label {
+    String(String),
+    /// Cost-centre stack, innermost cost centre first
+    Stack(Vec<u32>),
+}
+
+/// Reads heap profile samples in an eventlog as a `.hp` file. Sample times are in seconds,
+/// residencies are in bytes.
+pub fn read_heap_profile<I: Iterator<Item = Result<Event, String>>>(
+    events: I,
+) -> Result<HpFile, String> {
+    let mut job = String::new();
+    let mut cost_centres: HashMap<u32, String> = HashMap::new();
+    let mut info_tables: HashMap<u64, String> = HashMap::new();
+    let mut samples: Vec<(f64, Vec<(Label, u64)>)> = vec![];
+    let mut in_sample = false;
+
+    for event in events {
+        let event = event?;
+        match event.kind {
+            EventKind::ProgramArgs { args, .. } => {
+                job = args.join(" ");
+            }
+            EventKind::HeapProfCostCentre {
+                id, label, module, ..
+            } => {
+                cost_centres.insert(id, format!("{}.{}", module, label));
+            }
+            EventKind::Ipe {
+                info,
+                table_name,
+                src_loc,
+                ..
+            } => {
+                info_tables.insert(
+                    info,
+                    if src_loc.is_empty() {
+                        table_name
+                    } else {
+                        format!("{} ({})", table_name, src_loc)
+                    },
+                );
+            }
+            EventKind::HeapProfSampleBegin { .. } => {
+                samples.push((secs(event.time), vec![]));
+                in_sample = true;
+            }
+            EventKind::HeapBioProfSampleBegin { time, .. } => {
+                samples.push((secs(time), vec![]));
+                in_sample = true;
+            }
+            EventKind::HeapProfSampleEnd { .. } => {
+                in_sample = false;
+            }
+            EventKind::HeapProfSampleString {
+                residency, label, ..
+            } => {
+                if !in_sample {
+                    return Err(format!(
+                        "Heap profile sample outside of a census at {}",
+                        event.time
+                    ));
+                }
+                samples
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push((Label::String(label), residency));
+            }
+            EventKind::HeapProfSampleCostCentre {
+                residency, stack, ..
+            } => {
+                if !in_sample {
+                    return Err(format!(
+                        "Heap profile sample outside of a census at {}",
+                        event.time
+                    ));
+                }
+                samples
+                    .last_mut()
+                    .unwrap()
+                    .1
+                    .push((Label::Stack(stack), residency));
+            }
+            _ => {}
+        }
+    }
+
+    // IPE events can come after the samples, so labels are resolved at the end
+    let resolve = |label: Label| -> String {
+        match label {
+            Label::String(label) => label
+                .strip_prefix("0x")
+                .and_then(|addr| u64::from_str_radix(addr, 16).ok())
+                .and_then(|addr| info_tables.get(&addr))
+                .cloned()
+                .unwrap_or(label),
+            Label::Stack(stack) => stack
+                .iter()
+                .map(|id| match cost_centres.get(id) {
+                    Some(cc) => cc.clone(),
+                    None => format!("<cc {}>", id),
+                })
+                .collect::<Vec<_>>()
+                .join("/"),
+        }
+    };
+
+    Ok(HpFile {
+        job,
+        date: String::new(),
+        sample_unit: "seconds".to_owned(),
+        value_unit: "bytes".to_owned(),
+        samples: samples
+            .into_iter()
+            .map(|(time, bands)| Sample {
+                time,
+                bands: bands
+                    .into_iter()
+                    .map(|(label, residency)| (resolve(label), residency))
+                    .collect(),
+            })
+            .collect(),
+        marks: vec![],
+    })
+}
+
+#[test]
+fn read_heap_profile_test() {
+    use super::test_event;
+
+    let event = |time, kind| test_event(time, None, kind);
+    let string = |residency, label: &str| EventKind::HeapProfSampleString {
+        profile: 0,
+        residency,
+        label: label.to_owned(),
+    };
+
+    let events = vec![
+        event(
+            0,
+            EventKind::ProgramArgs {
+                capset: 0,
+                args: vec!["./Main".to_owned(), "+RTS".to_owned(), "-hi".to_owned()],
+            },
+        ),
+        event(100_000_000, EventKind::HeapProfSampleBegin { era: 1 }),
+        event(100_000_000, string(1000, "0x4a8f30")),
+        event(100_000_000, string(200, "0x10")),
+        event(100_000_000, EventKind::HeapProfSampleEnd { era: 1 }),
+        event(
+            200_000_000,
+            EventKind::HeapProfCostCentre {
+                id: 1,
+                label: "f".to_owned(),
+                module: "Main".to_owned(),
+                src_loc: "Main.hs:3:1-10".to_owned(),
+                is_caf: false,
+            },
+        ),
+        event(200_000_000, EventKind::HeapProfSampleBegin { era: 2 }),
+        event(
+            200_000_000,
+            EventKind::HeapProfSampleCostCentre {
+                profile: 0,
+                residency: 300,
+                stack: vec![1, 2],
+            },
+        ),
+        event(200_000_000, EventKind::HeapProfSampleEnd { era: 2 }),
+        event(
+            300_000_000,
+            EventKind::Ipe {
+                info: 0x4a8f30,
+                table_name: "Main.go_info".to_owned(),
+                closure_desc: "FUN".to_owned(),
+                ty_desc: "Int -> Int".to_owned(),
+                label: "go".to_owned(),
+                module: "Main".to_owned(),
+                src_loc: "Main.hs:10:5-20".to_owned(),
+            },
+        ),
+    ];
+
+    let hp = read_heap_profile(events.into_iter()).unwrap();
+    assert_eq!(hp.job, "./Main +RTS -hi");
+    assert_eq!(hp.samples.len(), 2);
+    assert_eq!(hp.samples[0].time, 0.1);
+    assert_eq!(
+        hp.samples[0].bands,
+        vec![
+            ("Main.go_info (Main.hs:10:5-20)".to_owned(), 1000),
+            ("0x10".to_owned(), 200)
+        ]
+    );
+    assert_eq!(hp.samples[1].bands, vec![("Main.f/<cc 2>".to_owned(), 300)]);
+
+    assert!(read_heap_profile(vec![event(0, string(1, "x"))].into_iter()).is_err());
+}
@@ -0,0 +1,208 @@
+//! Info-table provenance (IPE) index
+//!
+//! Programs built with `-finfo-table-map` (GHC 9.2 and later) post an IPE event for every info
+//! table, giving its name, closure type, type and source location. The index maps info table
+//! addresses to these.
+
+use super::{Event, EventKind};
+use crate::z_decode::decode_symbol;
+
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InfoProv {
+    pub table_name: String,
+    /// Closure type. Newer GHCs post the closure type number, see `InfoProv::closure_type`.
+    pub closure_desc: String,
+    pub ty_desc: String,
+    pub label: String,
+    pub module: String,
+    pub src_loc: String,
+}
+
+impl InfoProv {
+    /// Z-decoded name of the info table
+    pub fn name(&self) -> String {
+        decode_symbol(&self.table_name)
+    }
+
+    /// Name of the closure type, e.g. `THUNK_1_0`
+    pub fn closure_type(&self) -> &str {
+        match self.closure_desc.parse::<usize>() {
+            Ok(n) if n < CLOSURE_TYPES.len() => CLOSURE_TYPES[n],
+            _ => &self.closure_desc,
+        }
+    }
+}
+
+impl fmt::Display for InfoProv {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.closure_type(), self.name())?;
+        if !self.ty_desc.is_empty() {
+            write!(f, " :: {}", self.ty_desc)?;
+        }
+        if !self.src_loc.is_empty() {
+            write!(f, " at {}", self.src_loc)?;
+        }
+        if !self.module.is_empty() {
+            write!(f, " ({})", self.module)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IpeIndex {
+    entries: HashMap<u64, InfoProv>,
+}
+
+impl IpeIndex {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Provenance of the info table at the given address
+    pub fn lookup(&self, info: u64) -> Option<&InfoProv> {
+        self.entries.get(&info)
+    }
+}
+
+/// Builds an IPE index from the IPE events in an eventlog
+pub fn read_ipe_index<I: Iterator<Item = Result<Event, String>>>(
+    events: I,
+) -> Result<IpeIndex, String> {
+    let mut index = IpeIndex::default();
+    for event in events {
+        if let EventKind::Ipe {
+            info,
+            table_name,
+            closure_desc,
+            ty_desc,
+            label,
+            module,
+            src_loc,
+        } = event?.kind
+        {
+            index.entries.insert(
+                info,
+                InfoProv {
+                    table_name,
+                    closure_desc,
+                    ty_desc,
+                    label,
+                    module,
+                    src_loc,
+                },
+            );
+        }
+    }
+    Ok(index)
+}
+
+/// Closure types, indexed by closure type number (`rts/include/rts/storage/ClosureTypes.h`)
+const CLOSURE_TYPES: [&str; 65] = [
+    "INVALID_OBJECT",
+    "CONSTR",
+    "CONSTR_1_0",
+    "CONSTR_0_1",
+    "CONSTR_2_0",
+    "CONSTR_1_1",
+    "CONSTR_0_2",
+    "CONSTR_NOCAF",
+    "FUN",
+    "FUN_1_0",
+    "FUN_0_1",
+    "FUN_2_0",
+    "FUN_1_1",
+    "FUN_0_2",
+    "FUN_STATIC",
+    "THUNK",
+    "THUNK_1_0",
+    "THUNK_0_1",
+    "THUNK_2_0",
+    "THUNK_1_1",
+    "THUNK_0_2",
+    "THUNK_STATIC",
+    "THUNK_SELECTOR",
+    "BCO",
+    "AP",
+    "PAP",
+    "AP_STACK",
+    "IND",
+    "IND_STATIC",
+    "RET_BCO",
+    "RET_SMALL",
+    "RET_BIG",
+    "RET_FUN",
+    "UPDATE_FRAME",
+    "CATCH_FRAME",
+    "UNDERFLOW_FRAME",
+    "STOP_FRAME",
+    "BLOCKING_QUEUE",
+    "BLACKHOLE",
+    "MVAR_CLEAN",
+    "MVAR_DIRTY",
+    "TVAR",
+    "ARR_WORDS",
+    "MUT_ARR_PTRS_CLEAN",
+    "MUT_ARR_PTRS_DIRTY",
+    "MUT_ARR_PTRS_FROZEN_DIRTY",
+    "MUT_ARR_PTRS_FROZEN_CLEAN",
+    "MUT_VAR_CLEAN",
+    "MUT_VAR_DIRTY",
+    "WEAK",
+    "PRIM",
+    "MUT_PRIM",
+    "TSO",
+    "STACK",
+    "TREC_CHUNK",
+    "ATOMICALLY_FRAME",
+    "CATCH_RETRY_FRAME",
+    "CATCH_STM_FRAME",
+    "WHITEHOLE",
+    "SMALL_MUT_ARR_PTRS_CLEAN",
+    "SMALL_MUT_ARR_PTRS_DIRTY",
+    "SMALL_MUT_ARR_PTRS_FROZEN_DIRTY",
+    "SMALL_MUT_ARR_PTRS_FROZEN_CLEAN",
+    "COMPACT_NFDATA",
+    "CONTINUATION",
+];
+
+#[test]
+fn ipe_index_test() {
+    use super::{EventLogReader, TestLog, IPE};
+
+    let mut log = TestLog::new();
+    log.event_type(IPE, None, "IPE");
+    let mut payload = vec![];
+    payload.extend_from_slice(&0x4a8f30u64.to_be_bytes());
+    for s in &[
+        "Main_zdwgo_info",
+        "16",
+        "Int -> Int",
+        "$wgo",
+        "Main",
+        "Main.hs:10:5-20",
+    ] {
+        payload.extend_from_slice(s.as_bytes());
+        payload.push(0);
+    }
+    log.event(IPE, 10, &payload);
+
+    let bytes = log.bytes();
+    let index = read_ipe_index(EventLogReader::new(&bytes[..]).unwrap()).unwrap();
+    assert_eq!(index.len(), 1);
+    assert_eq!(index.lookup(0x10), None);
+
+    let prov = index.lookup(0x4a8f30).unwrap();
+    assert_eq!(prov.closure_type(), "THUNK_1_0");
+    assert_eq!(
+        prov.to_string(),
+        "THUNK_1_0 Main_$wgo :: Int -> Int at Main.hs:10:5-20 (Main)"
+    );
+}
@@ -0,0 +1,329 @@
+//! User messages and markers (`traceEvent`, `traceMarker`), and spans delimited by them
+//!
+//! A span starts with a message or marker `START <name>` (or `begin <name>`) and ends with the
+//! next `STOP <name>` (or `end <name>`) with the same name. Spans with the same name can nest.
+//!
+//! GC time, number of GCs and allocation in a span are computed from GC events. Allocation is
+//! only posted at GCs, so it's the allocation between the last GCs before the span start and end.
+//! GHC posts the cumulative allocation of a capset (all capabilities are in the default heap
+//! capset) through the global buffer, so allocation events don't have a capability.
+
+use super::gc::{is_gc_event, read_gcs, Gc};
+use super::{ms, secs, Event, EventKind};
+use crate::utils::percentage;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UserMessage {
+    /// In nanoseconds
+    pub time: u64,
+    pub cap: Option<u16>,
+    pub msg: String,
+    /// Posted with `traceMarker` rather than `traceEvent`?
+    pub marker: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Span {
+    pub name: String,
+    /// In nanoseconds
+    pub start: u64,
+    /// In nanoseconds
+    pub end: u64,
+    /// Time spent in GC pauses in the span, in nanoseconds
+    pub gc_time: u64,
+    /// Number of GCs that started in the span
+    pub gcs: usize,
+    /// Bytes allocated
+    pub allocated: u64,
+}
+
+impl Span {
+    /// Wall time in nanoseconds
+    pub fn wall(&self) -> u64 {
+        self.end - self.start
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timeline {
+    /// All user messages and markers, sorted by time
+    pub messages: Vec<UserMessage>,
+    /// Spans, sorted by start time
+    pub spans: Vec<Span>,
+    /// Start and stop messages that don't have a matching stop or start
+    pub unmatched: Vec<UserMessage>,
+}
+
+/// Parses span start and stop messages. Returns whether the message starts a span, and the span
+/// name.
+fn span_marker(msg: &str) -> Option<(bool, &str)> {
+    let idx = msg.find(' ')?;
+    let (keyword, name) = (&msg[..idx], msg[idx + 1..].trim());
+    if name.is_empty() {
+        return None;
+    }
+    if keyword == "START" || keyword == "begin" {
+        Some((true, name))
+    } else if keyword == "STOP" || keyword == "end" {
+        Some((false, name))
+    } else {
+        None
+    }
+}
+
+/// Reads user messages and markers and pairs start and stop messages into spans
+pub fn read_timeline<I: Iterator<Item = Result<Event, String>>>(
+    events: I,
+) -> Result<Timeline, String> {
+    let mut messages: Vec<UserMessage> = vec![];
+    let mut gc_events: Vec<Event> = vec![];
+    // Cumulative allocation of capsets, posted at GCs
+    let mut allocs: HashMap<u32, Vec<(u64, u64)>> = HashMap::new();
+
+    for event in events {
+        let event = event?;
+        match event.kind {
+            EventKind::UserMsg { msg } => messages.push(UserMessage {
+                time: event.time,
+                cap: event.cap,
+                msg,
+                marker: false,
+            }),
+            EventKind::UserMarker { marker } => messages.push(UserMessage {
+                time: event.time,
+                cap: event.cap,
+                msg: marker,
+                marker: true,
+            }),
+            EventKind::HeapAllocated { capset, allocated } => allocs
+                .entry(capset)
+                .or_default()
+                .push((event.time, allocated)),
+            ref kind if is_gc_event(kind) => gc_events.push(event),
+            _ => {}
+        }
+    }
+
+    messages.sort_by_key(|msg| msg.time);
+    for samples in allocs.values_mut() {
+        samples.sort_unstable();
+    }
+    let gcs = read_gcs(gc_events.into_iter().map(Ok))?.gcs;
+
+    let allocated_at = |time: u64| -> u64 {
+        allocs
+            .values()
+            .map(|samples| {
+                let idx = samples.partition_point(|(t, _)| *t <= time);
+                if idx == 0 {
+                    0
+                } else {
+                    samples[idx - 1].1
+                }
+            })
+            .sum()
+    };
+
+    let mut spans: Vec<Span> = vec![];
+    let mut unmatched: Vec<UserMessage> = vec![];
+    // Start messages of open spans, by name
+    let mut open: HashMap<&str, Vec<&UserMessage>> = HashMap::new();
+    for msg in &messages {
+        match span_marker(&msg.msg) {
+            None => {}
+            Some((true, name)) => open.entry(name).or_default().push(msg),
+            Some((false, name)) => match open.get_mut(name).and_then(|starts| starts.pop()) {
+                None => unmatched.push(msg.clone()),
+                Some(start) => {
+                    spans.push(make_span(name, start.time, msg.time, &gcs, &allocated_at))
+                }
+            },
+        }
+    }
+    for starts in open.into_values() {
+        unmatched.extend(starts.into_iter().cloned());
+    }
+    spans.sort_by_key(|span| span.start);
+    unmatched.sort_by_key(|msg| msg.time);
+
+    Ok(Timeline {
+        messages,
+        spans,
+        unmatched,
+    })
+}
+
+fn make_span<F: Fn(u64) -> u64>(
+    name: &str,
+    start: u64,
+    end: u64,
+    gcs: &[Gc],
+    allocated_at: &F,
+) -> Span {
+    let mut gc_time = 0;
+    let mut n_gcs = 0;
+    for gc in gcs {
+        if gc.start >= start && gc.start < end {
+            n_gcs += 1;
+        }
+        let overlap_start = std::cmp::max(gc.start, start);
+        let overlap_end = std::cmp::min(gc.end, end);
+        if overlap_end > overlap_start {
+            gc_time += overlap_end - overlap_start;
+        }
+    }
+    Span {
+        name: name.to_owned(),
+        start,
+        end,
+        gc_time,
+        gcs: n_gcs,
+        allocated: allocated_at(end).saturating_sub(allocated_at(start)),
+    }
+}
+
+/// Writes one line per span, and start and stop messages without a match
+pub fn write_spans<W: Write>(timeline: &Timeline, w: &mut W) {
+    writeln!(
+        w,
+        "span\tstart (s)\twall (ms)\tGC (ms)\tGC %\tGCs\tallocated"
+    )
+    .unwrap();
+    for span in &timeline.spans {
+        writeln!(
+            w,
+            "{}\t{:.6}\t{:.3}\t{:.3}\t{:.2}\t{}\t{}",
+            span.name,
+            secs(span.start),
+            ms(span.wall()),
+            ms(span.gc_time),
+            percentage(span.gc_time, span.wall()),
+            span.gcs,
+            span.allocated,
+        )
+        .unwrap();
+    }
+    for msg in &timeline.unmatched {
+        writeln!(
+            w,
+            "WARNING: Unmatched message at {:.6} s: {}",
+            secs(msg.time),
+            msg.msg
+        )
+        .unwrap();
+    }
+}
+
+/// Writes all user messages and markers with their times
+pub fn write_messages<W: Write>(timeline: &Timeline, w: &mut W) {
+    for msg in &timeline.messages {
+        let cap = match msg.cap {
+            None => "-".to_owned(),
+            Some(cap) => cap.to_string(),
+        };
+        writeln!(
+            w,
+            "{:.6}\t{}\t{}\t{}",
+            secs(msg.time),
+            cap,
+            if msg.marker { "marker" } else { "message" },
+            msg.msg
+        )
+        .unwrap();
+    }
+}
+
+#[test]
+fn span_marker_test() {
+    assert_eq!(span_marker("START parse"), Some((true, "parse")));
+    assert_eq!(span_marker("end type check"), Some((false, "type check")));
+    assert_eq!(span_marker("STOP"), None);
+    assert_eq!(span_marker("STOP "), None);
+    assert_eq!(span_marker("hello world"), None);
+}
+
+#[test]
+fn read_timeline_test() {
+    use super::test_event;
+
+    let msg = |msg: &str| EventKind::UserMsg {
+        msg: msg.to_owned(),
+    };
+    let marker = |marker: &str| EventKind::UserMarker {
+        marker: marker.to_owned(),
+    };
+    let allocated = |capset, allocated| EventKind::HeapAllocated { capset, allocated };
+
+    let events = vec![
+        test_event(1_000_000, Some(0), marker("START phase 1")),
+        test_event(2_000_000, Some(0), EventKind::GcStart),
+        test_event(3_000_000, Some(0), EventKind::GcEnd),
+        test_event(3_000_000, None, allocated(0, 1000)),
+        test_event(5_000_000, Some(0), msg("STOP phase 1")),
+        test_event(5_000_000, Some(0), msg("START phase 2")),
+        test_event(6_000_000, Some(0), EventKind::GcStart),
+        test_event(8_000_000, Some(0), EventKind::GcEnd),
+        test_event(8_000_000, None, allocated(0, 3000)),
+        test_event(11_000_000, Some(0), msg("STOP phase 3")),
+        // Posted by another capability, after the GC in phase 2
+        test_event(7_000_000, Some(1), msg("STOP phase 2")),
+    ];
+
+    let timeline = read_timeline(events.into_iter()).unwrap();
+    assert_eq!(timeline.messages.len(), 5);
+    assert!(timeline.messages[0].marker);
+    assert_eq!(
+        timeline.spans,
+        vec![
+            Span {
+                name: "phase 1".to_owned(),
+                start: 1_000_000,
+                end: 5_000_000,
+                gc_time: 1_000_000,
+                gcs: 1,
+                allocated: 1000,
+            },
+            Span {
+                name: "phase 2".to_owned(),
+                start: 5_000_000,
+                end: 7_000_000,
+                gc_time: 1_000_000,
+                gcs: 1,
+                allocated: 0,
+            },
+        ]
+    );
+    assert_eq!(timeline.unmatched.len(), 1);
+    assert_eq!(timeline.unmatched[0].msg, "STOP phase 3");
+}
+
+#[test]
+fn read_timeline_capsets_test() {
+    use super::test_event;
+
+    let event = |time, kind| test_event(time, None, kind);
+    let msg = |msg: &str| EventKind::UserMsg {
+        msg: msg.to_owned(),
+    };
+    let allocated = |capset, allocated| EventKind::HeapAllocated { capset, allocated };
+
+    // Two cumulative series with interleaved samples, both from the global buffer
+    let events = vec![
+        event(1_000_000, allocated(0, 1000)),
+        event(1_000_000, allocated(1, 100)),
+        event(2_000_000, msg("START span")),
+        event(3_000_000, allocated(0, 1500)),
+        event(3_000_000, allocated(1, 400)),
+        event(4_000_000, allocated(1, 900)),
+        event(5_000_000, msg("STOP span")),
+        event(6_000_000, allocated(0, 9000)),
+    ];
+
+    let timeline = read_timeline(events.into_iter()).unwrap();
+    assert_eq!(timeline.spans.len(), 1);
+    // (1500 - 1000) + (900 - 100)
+    assert_eq!(timeline.spans[0].allocated, 1300);
+}
@@ -0,0 +1,460 @@
+//! Thread and capability activity: which threads ran on which capabilities and when, why they
+//! stopped, and how long they waited before running again
+//!
+//! Threads can migrate, and capabilities write events to separate buffers, so the thread events
+//! are sorted by time before processing.
+
+use super::chrome::TraceEvent;
+use super::{ms, stop_status_name, Event, EventKind};
+use crate::utils::percentage;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+/// `StopThread` status of finished threads
+const THREAD_FINISHED: u16 = 5;
+
+/// A thread running on a capability
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadSlice {
+    pub thread: u32,
+    /// In nanoseconds
+    pub start: u64,
+    /// In nanoseconds
+    pub end: u64,
+    /// See `stop_status_name`. `None` if the thread was still running at the end of the eventlog.
+    pub stop_status: Option<u16>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CapActivity {
+    pub cap: u16,
+    /// Threads run on the capability, in time order
+    pub slices: Vec<ThreadSlice>,
+    /// Start and end times of GCs on the capability, in nanoseconds
+    pub gcs: Vec<(u64, u64)>,
+}
+
+impl CapActivity {
+    /// Time spent running Haskell threads in the interval, in nanoseconds
+    pub fn mutator_time(&self, start: u64, end: u64) -> u64 {
+        self.slices
+            .iter()
+            .map(|slice| overlap(slice.start, slice.end, start, end))
+            .sum()
+    }
+
+    /// Time spent in GC in the interval, in nanoseconds
+    pub fn gc_time(&self, start: u64, end: u64) -> u64 {
+        self.gcs
+            .iter()
+            .map(|(gc_start, gc_end)| overlap(*gc_start, *gc_end, start, end))
+            .sum()
+    }
+}
+
+fn overlap(start1: u64, end1: u64, start2: u64, end2: u64) -> u64 {
+    std::cmp::min(end1, end2).saturating_sub(std::cmp::max(start1, start2))
+}
+
+/// Time threads waited to run again after stopping with a stop status
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Blocked {
+    pub stop_status: u16,
+    pub stops: usize,
+    /// In nanoseconds
+    pub time: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SparkStats {
+    pub created: u64,
+    pub dud: u64,
+    pub overflowed: u64,
+    pub converted: u64,
+    pub gcd: u64,
+    pub fizzled: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Activity {
+    /// Sorted by capability number
+    pub caps: Vec<CapActivity>,
+    /// Sorted by time, descending
+    pub blocked: Vec<Blocked>,
+    pub sparks: SparkStats,
+    pub threads_created: usize,
+    pub migrations: usize,
+    /// Time of the last event, in nanoseconds
+    pub end_time: u64,
+}
+
+/// Is the event used in `read_activity`?
+pub(super) fn is_activity_event(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::CreateThread { .. }
+            | EventKind::RunThread { .. }
+            | EventKind::StopThread { .. }
+            | EventKind::MigrateThread { .. }
+            | EventKind::GcStart
+            | EventKind::GcEnd
+            | EventKind::SparkCounters { .. }
+            | EventKind::SparkCreate
+            | EventKind::SparkDud
+            | EventKind::SparkOverflow
+            | EventKind::SparkRun
+            | EventKind::SparkSteal { .. }
+            | EventKind::SparkFizzle
+            | EventKind::SparkGc
+    )
+}
+
+/// Reads thread, capability and spark activity from a stream of events
+pub fn read_activity<I: Iterator<Item = Result<Event, String>>>(
+    events: I,
+) -> Result<Activity, String> {
+    let mut end_time = 0;
+    let mut activity_events: Vec<Event> = vec![];
+    for event in events {
+        let event = event?;
+        end_time = std::cmp::max(end_time, event.time);
+        if event.cap.is_some() && is_activity_event(&event.kind) {
+            activity_events.push(event);
+        }
+    }
+    activity_events.sort_by_key(|event| event.time);
+
+    let mut caps: BTreeMap<u16, CapActivity> = BTreeMap::new();
+    // Running thread and its start time, per capability
+    let mut running: HashMap<u16, (u32, u64)> = HashMap::new();
+    let mut gc_starts: HashMap<u16, u64> = HashMap::new();
+    // Stop time and status of stopped threads
+    let mut stopped: HashMap<u32, (u64, u16)> = HashMap::new();
+    let mut blocked: HashMap<u16, Blocked> = HashMap::new();
+    // Spark counters are cumulative, we keep the last one for each capability
+    let mut spark_counters: HashMap<u16, SparkStats> = HashMap::new();
+    let mut spark_events = SparkStats::default();
+    let mut threads_created = 0;
+    let mut migrations = 0;
+
+    for event in activity_events {
+        let cap = event.cap.unwrap();
+        let cap_activity = caps.entry(cap).or_insert_with(|| CapActivity {
+            cap,
+            ..CapActivity::default()
+        });
+        match event.kind {
+            EventKind::CreateThread { .. } => threads_created += 1,
+            EventKind::MigrateThread { .. } => migrations += 1,
+            EventKind::RunThread { thread } => {
+                running.insert(cap, (thread, event.time));
+                if let Some((stop_time, status)) = stopped.remove(&thread) {
+                    let entry = blocked.entry(status).or_insert(Blocked {
+                        stop_status: status,
+                        stops: 0,
+                        time: 0,
+                    });
+                    entry.stops += 1;
+                    entry.time += event.time - stop_time;
+                }
+            }
+            EventKind::StopThread { thread, status, .. } => {
+                if let Some((running_thread, start)) = running.remove(&cap) {
+                    cap_activity.slices.push(ThreadSlice {
+                        thread: running_thread,
+                        start,
+                        end: event.time,
+                        stop_status: Some(status),
+                    });
+                }
+                if status != THREAD_FINISHED {
+                    stopped.insert(thread, (event.time, status));
+                }
+            }
+            EventKind::GcStart => {
+                gc_starts.insert(cap, event.time);
+            }
+            EventKind::GcEnd => {
+                if let Some(start) = gc_starts.remove(&cap) {
+                    cap_activity.gcs.push((start, event.time));
+                }
+            }
+            EventKind::SparkCounters {
+                created,
+                dud,
+                overflowed,
+                converted,
+                gcd,
+                fizzled,
+                ..
+            } => {
+                spark_counters.insert(
+                    cap,
+                    SparkStats {
+                        created,
+                        dud,
+                        overflowed,
+                        converted,
+                        gcd,
+                        fizzled,
+                    },
+                );
+            }
+            EventKind::SparkCreate => spark_events.created += 1,
+            EventKind::SparkDud => spark_events.dud += 1,
+            EventKind::SparkOverflow => spark_events.overflowed += 1,
+            EventKind::SparkRun | EventKind::SparkSteal { .. } => spark_events.converted += 1,
+            EventKind::SparkFizzle => spark_events.fizzled += 1,
+            EventKind::SparkGc => spark_events.gcd += 1,
+            _ => {}
+        }
+    }
+
+    // Threads still running at the end
+    for (cap, (thread, start)) in running {
+        caps.get_mut(&cap).unwrap().slices.push(ThreadSlice {
+            thread,
+            start,
+            end: end_time,
+            stop_status: None,
+        });
+    }
+
+    // Spark counters are posted at GCs, individual spark events only with `+RTS -lf`. Prefer the
+    // counters when available.
+    let sparks = if spark_counters.is_empty() {
+        spark_events
+    } else {
+        spark_counters
+            .into_values()
+            .fold(SparkStats::default(), |acc, counters| SparkStats {
+                created: acc.created + counters.created,
+                dud: acc.dud + counters.dud,
+                overflowed: acc.overflowed + counters.overflowed,
+                converted: acc.converted + counters.converted,
+                gcd: acc.gcd + counters.gcd,
+                fizzled: acc.fizzled + counters.fizzled,
+            })
+    };
+
+    let mut blocked: Vec<Blocked> = blocked.into_values().collect();
+    blocked.sort_by_key(|blocked| (std::cmp::Reverse(blocked.time), blocked.stop_status));
+
+    Ok(Activity {
+        caps: caps.into_values().collect(),
+        blocked,
+        sparks,
+        threads_created,
+        migrations,
+        end_time,
+    })
+}
+
+const BARS: [char; 9] = [' ', '▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// Writes a summary of the activity, with mutator utilisation of capabilities over time in
+/// `n_buckets` intervals (at least one)
+pub fn write_activity<W: Write>(activity: &Activity, n_buckets: usize, w: &mut W) {
+    let n_buckets = std::cmp::max(n_buckets, 1);
+    let end = activity.end_time;
+    writeln!(w, "Run time:        {:.3} ms", ms(end)).unwrap();
+    writeln!(w, "Threads created: {}", activity.threads_created).unwrap();
+    writeln!(w, "Migrations:      {}", activity.migrations).unwrap();
+
+    writeln!(w).unwrap();
+    writeln!(w, "Capabilities (mutator utilisation over time):").unwrap();
+    for cap in &activity.caps {
+        let mutator = cap.mutator_time(0, end);
+        let gc = cap.gc_time(0, end);
+        let n = n_buckets as u64;
+        let bars: String = (0..n)
+            .map(|i| {
+                let (start, stop) = (i * end / n, (i + 1) * end / n);
+                let util = percentage(cap.mutator_time(start, stop), stop - start);
+                BARS[((util / 100.0) * 8.0).round() as usize]
+            })
+            .collect();
+        let mut threads: Vec<u32> = cap.slices.iter().map(|slice| slice.thread).collect();
+        threads.sort_unstable();
+        threads.dedup();
+        writeln!(
+            w,
+            "  cap {}: mutator {:.2}%, GC {:.2}%, idle {:.2}%, {} threads |{}|",
+            cap.cap,
+            percentage(mutator, end),
+            percentage(gc, end),
+            percentage(end.saturating_sub(mutator + gc), end),
+            threads.len(),
+            bars
+        )
+        .unwrap();
+    }
+
+    writeln!(w).unwrap();
+    writeln!(w, "Time until threads ran again, by stop reason:").unwrap();
+    for blocked in &activity.blocked {
+        writeln!(
+            w,
+            "  {}: {:.3} ms in {} stops",
+            stop_status_name(blocked.stop_status),
+            ms(blocked.time),
+            blocked.stops
+        )
+        .unwrap();
+    }
+
+    let sparks = &activity.sparks;
+    writeln!(w).unwrap();
+    writeln!(
+        w,
+        "Sparks: {} created, {} converted, {} overflowed, {} dud, {} GC'd, {} fizzled",
+        sparks.created, sparks.converted, sparks.overflowed, sparks.dud, sparks.gcd, sparks.fizzled
+    )
+    .unwrap();
+}
+
+/// Trace events for the activity: one trace thread per capability, with thread and GC slices
+pub fn activity_trace_events(activity: &Activity) -> Vec<TraceEvent> {
+    let mut events = vec![];
+    for cap in &activity.caps {
+        let tid = u32::from(cap.cap);
+        events.push(TraceEvent::thread_name(0, tid, format!("cap {}", cap.cap)));
+        for slice in &cap.slices {
+            let stop = match slice.stop_status {
+                None => "Running",
+                Some(status) => stop_status_name(status),
+            };
+            events.push(
+                TraceEvent::complete(
+                    format!("thread {}", slice.thread),
+                    "thread",
+                    slice.start,
+                    slice.end,
+                    0,
+                    tid,
+                )
+                .with_arg("stop", stop),
+            );
+        }
+        for (start, end) in &cap.gcs {
+            events.push(TraceEvent::complete(
+                "GC".to_owned(),
+                "gc",
+                *start,
+                *end,
+                0,
+                tid,
+            ));
+        }
+    }
+    events
+}
+
+#[test]
+fn read_activity_test() {
+    use super::test_event;
+
+    let event = |time, cap, kind| test_event(time, Some(cap), kind);
+    let stop = |thread, status| EventKind::StopThread {
+        thread,
+        status,
+        blocked_on: 0,
+    };
+
+    let events = vec![
+        // cap 0
+        event(0, 0, EventKind::CreateThread { thread: 1 }),
+        event(0, 0, EventKind::RunThread { thread: 1 }),
+        event(4_000, 0, stop(1, 7)), // BlockedOnMVar
+        event(4_000, 0, EventKind::RunThread { thread: 2 }),
+        event(6_000, 0, stop(2, 1)), // HeapOverflow
+        event(6_000, 0, EventKind::GcStart),
+        event(7_000, 0, EventKind::GcEnd),
+        event(7_000, 0, EventKind::RunThread { thread: 2 }),
+        event(10_000, 0, stop(2, THREAD_FINISHED)),
+        event(7_000, 0, EventKind::SparkCreate),
+        // cap 1, thread 1 woken up on cap 1
+        event(5_000, 1, EventKind::RunThread { thread: 1 }),
+        event(8_000, 1, EventKind::SparkRun),
+        event(
+            9_000,
+            1,
+            EventKind::MigrateThread {
+                thread: 1,
+                new_cap: 0,
+            },
+        ),
+    ];
+
+    let activity = read_activity(events.into_iter()).unwrap();
+    assert_eq!(activity.end_time, 10_000);
+    assert_eq!(activity.threads_created, 1);
+    assert_eq!(activity.migrations, 1);
+    assert_eq!(activity.caps.len(), 2);
+
+    let cap0 = &activity.caps[0];
+    assert_eq!(cap0.slices.len(), 3);
+    assert_eq!(cap0.mutator_time(0, 10_000), 9_000);
+    assert_eq!(cap0.gc_time(0, 10_000), 1_000);
+    assert_eq!(cap0.mutator_time(5_000, 7_000), 1_000);
+
+    // Still running at the end
+    assert_eq!(
+        activity.caps[1].slices,
+        vec![ThreadSlice {
+            thread: 1,
+            start: 5_000,
+            end: 10_000,
+            stop_status: None,
+        }]
+    );
+
+    assert_eq!(
+        activity.blocked,
+        vec![
+            Blocked {
+                stop_status: 1,
+                stops: 1,
+                time: 1_000,
+            },
+            Blocked {
+                stop_status: 7,
+                stops: 1,
+                time: 1_000,
+            },
+        ]
+    );
+    assert_eq!(activity.sparks.created, 1);
+    assert_eq!(activity.sparks.converted, 1);
+
+    let trace_events = activity_trace_events(&activity);
+    assert_eq!(trace_events.len(), 2 + 3 + 1 + 1);
+}
+
+#[test]
+fn write_activity_buckets_test() {
+    // 10 ns in 3 buckets: the last bucket is 6..10, covering the thread that ran at the very end
+    let activity = Activity {
+        caps: vec![CapActivity {
+            cap: 0,
+            slices: vec![ThreadSlice {
+                thread: 1,
+                start: 9,
+                end: 10,
+                stop_status: None,
+            }],
+            gcs: vec![],
+        }],
+        blocked: vec![],
+        sparks: SparkStats::default(),
+        threads_created: 1,
+        migrations: 0,
+        end_time: 10,
+    };
+
+    let mut out = Vec::new();
+    write_activity(&activity, 3, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("1 threads |  ▂|"), "{}", out);
+}
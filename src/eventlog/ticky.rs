@@ -0,0 +1,135 @@
+//! Extracting ticky-ticky profiles from eventlogs (`+RTS -lT`)
+//!
+//! Counters are defined once, then sampled periodically and at exit. Each round of samples
+//! starts with a begin sample event. The RTS resets counters after sampling them, so a sample is
+//! the counts since the previous one and totals are sums of the samples.
+
+use super::{Event, EventKind};
+use crate::ticky::{TickyClosure, TickyFile, TickySample};
+
+use std::collections::HashMap;
+
+/// Reads ticky counter definitions and samples in an eventlog
+pub fn read_ticky<I: Iterator<Item = Result<Event, String>>>(
+    events: I,
+) -> Result<TickyFile, String> {
+    let mut ticky = TickyFile {
+        counters: vec![],
+        closures: vec![],
+        sample_times: vec![],
+    };
+    // Indices of counters in `ticky.closures`
+    let mut closure_idx: HashMap<u64, usize> = HashMap::new();
+
+    let new_closure = |name: String, arity: u16, kinds: String| TickyClosure {
+        name,
+        entries: 0,
+        alloc: 0,
+        allocd: 0,
+        arity: u32::from(arity),
+        kinds,
+        samples: vec![],
+    };
+
+    for event in events {
+        let event = event?;
+        match event.kind {
+            EventKind::TickyCounterDef {
+                id,
+                arity,
+                kinds,
+                name,
+            } => {
+                closure_idx.insert(id, ticky.closures.len());
+                ticky.closures.push(new_closure(name, arity, kinds));
+            }
+            EventKind::TickyBeginSample => ticky.sample_times.push(event.time),
+            EventKind::TickyCounterSample {
+                id,
+                entries,
+                alloc,
+                allocd,
+            } => {
+                if ticky.sample_times.is_empty() {
+                    ticky.sample_times.push(event.time);
+                }
+                let time = *ticky.sample_times.last().unwrap();
+                let idx = *closure_idx.entry(id).or_insert_with(|| {
+                    let name = format!("<undefined counter {:#x}>", id);
+                    ticky.closures.push(new_closure(name, 0, String::new()));
+                    ticky.closures.len() - 1
+                });
+                let closure = &mut ticky.closures[idx];
+                closure.entries += entries;
+                closure.alloc += alloc;
+                closure.allocd += allocd;
+                if entries != 0 || alloc != 0 || allocd != 0 {
+                    closure.samples.push(TickySample {
+                        time,
+                        entries,
+                        alloc,
+                        allocd,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(ticky)
+}
+
+#[test]
+fn read_ticky_test() {
+    use super::test_event;
+    use crate::ticky::{write_ticky_series, TickyMetric};
+
+    let event = |time, kind| test_event(time, None, kind);
+    let def = |id, name: &str| EventKind::TickyCounterDef {
+        id,
+        arity: 1,
+        kinds: "L".to_owned(),
+        name: name.to_owned(),
+    };
+    let sample = |id, entries, alloc| EventKind::TickyCounterSample {
+        id,
+        entries,
+        alloc,
+        allocd: 0,
+    };
+
+    let events = vec![
+        event(0, def(1, "Main.go{v r1} (fun)")),
+        event(0, def(2, "Main.main{v r2} (fun)")),
+        event(500_000_000, EventKind::TickyBeginSample),
+        event(500_000_000, sample(1, 0, 0)),
+        event(500_000_000, sample(2, 1, 16)),
+        event(1_000_000_000, EventKind::TickyBeginSample),
+        event(1_000_000_000, sample(1, 10, 480)),
+        event(1_000_000_000, sample(2, 0, 0)),
+    ];
+
+    let ticky = read_ticky(events.into_iter()).unwrap();
+    assert_eq!(ticky.sample_times, vec![500_000_000, 1_000_000_000]);
+    assert_eq!(ticky.closures.len(), 2);
+    let go = &ticky.closures[0];
+    assert_eq!((go.entries, go.alloc, go.arity), (10, 480, 1));
+    assert_eq!(
+        go.samples,
+        vec![TickySample {
+            time: 1_000_000_000,
+            entries: 10,
+            alloc: 480,
+            allocd: 0,
+        }]
+    );
+
+    let mut out = vec![];
+    write_ticky_series(&ticky, TickyMetric::Alloc, 10, &mut out);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "time (s)\tMain.go{v r1} (fun)\tMain.main{v r2} (fun)\n\
+         0.500000\t0\t16\n\
+         1.000000\t480\t0\n"
+    );
+}
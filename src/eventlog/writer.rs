@@ -0,0 +1,743 @@
+//! Writer for GHC's eventlog binary format, the inverse of the reader
+//!
+//! Events of capabilities are written in blocks, a new block is started whenever the capability
+//! of the events changes. Block markers in the written events are ignored, as the writer
+//! generates its own. Events are encoded in the format of the newest GHC we know of. Payloads of
+//! fixed-size event types are padded with zeros or truncated to the size in the header, so that
+//! eventlogs of older and newer GHCs can be written with their original headers.
+//!
+//! Events are encoded from their decoded fields, so fields the reader doesn't decode (e.g. ones
+//! newer GHCs append to variable-size events) are lost. To copy events from an eventlog as they
+//! are, write them with their original payloads with `EventLogWriter::write_event_payload`.
+
+use super::*;
+
+use std::io::Write;
+
+/// Size of block marker payloads
+const BLOCK_MARKER_SIZE: u16 = 14;
+
+/// Encodes an event as event type id and payload
+fn encode(kind: &EventKind) -> (u16, Vec<u8>) {
+    let mut p = vec![];
+    let id = match kind {
+        EventKind::CreateThread { thread } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            CREATE_THREAD
+        }
+        EventKind::RunThread { thread } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            RUN_THREAD
+        }
+        EventKind::StopThread {
+            thread,
+            status,
+            blocked_on,
+        } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            p.extend_from_slice(&status.to_be_bytes());
+            p.extend_from_slice(&blocked_on.to_be_bytes());
+            STOP_THREAD
+        }
+        EventKind::ThreadRunnable { thread } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            THREAD_RUNNABLE
+        }
+        EventKind::MigrateThread { thread, new_cap } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            p.extend_from_slice(&new_cap.to_be_bytes());
+            MIGRATE_THREAD
+        }
+        EventKind::ThreadWakeup { thread, other_cap } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            p.extend_from_slice(&other_cap.to_be_bytes());
+            THREAD_WAKEUP
+        }
+        EventKind::ThreadLabel { thread, label } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            p.extend_from_slice(label.as_bytes());
+            THREAD_LABEL
+        }
+        EventKind::CreateSparkThread { thread } => {
+            p.extend_from_slice(&thread.to_be_bytes());
+            CREATE_SPARK_THREAD
+        }
+
+        EventKind::GcStart => GC_START,
+        EventKind::GcEnd => GC_END,
+        EventKind::RequestSeqGc => REQUEST_SEQ_GC,
+        EventKind::RequestParGc => REQUEST_PAR_GC,
+        EventKind::GcIdle => GC_IDLE,
+        EventKind::GcWork => GC_WORK,
+        EventKind::GcDone => GC_DONE,
+        EventKind::GcGlobalSync => GC_GLOBAL_SYNC,
+        EventKind::GcStats {
+            capset,
+            generation,
+            copied,
+            slop,
+            fragmentation,
+            par_n_threads,
+            par_max_copied,
+            par_tot_copied,
+        } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&generation.to_be_bytes());
+            p.extend_from_slice(&copied.to_be_bytes());
+            p.extend_from_slice(&slop.to_be_bytes());
+            p.extend_from_slice(&fragmentation.to_be_bytes());
+            p.extend_from_slice(&par_n_threads.to_be_bytes());
+            p.extend_from_slice(&par_max_copied.to_be_bytes());
+            p.extend_from_slice(&par_tot_copied.to_be_bytes());
+            GC_STATS_GHC
+        }
+
+        EventKind::HeapAllocated { capset, allocated } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&allocated.to_be_bytes());
+            HEAP_ALLOCATED
+        }
+        EventKind::HeapSize { capset, size } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&size.to_be_bytes());
+            HEAP_SIZE
+        }
+        EventKind::HeapLive { capset, live } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&live.to_be_bytes());
+            HEAP_LIVE
+        }
+        EventKind::BlocksSize { capset, size } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&size.to_be_bytes());
+            BLOCKS_SIZE
+        }
+        EventKind::HeapInfo {
+            capset,
+            generations,
+            max_heap_size,
+            alloc_area_size,
+            mblock_size,
+            block_size,
+        } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&generations.to_be_bytes());
+            p.extend_from_slice(&max_heap_size.to_be_bytes());
+            p.extend_from_slice(&alloc_area_size.to_be_bytes());
+            p.extend_from_slice(&mblock_size.to_be_bytes());
+            p.extend_from_slice(&block_size.to_be_bytes());
+            HEAP_INFO_GHC
+        }
+
+        EventKind::CapCreate { cap } => {
+            p.extend_from_slice(&cap.to_be_bytes());
+            CAP_CREATE
+        }
+        EventKind::CapDelete { cap } => {
+            p.extend_from_slice(&cap.to_be_bytes());
+            CAP_DELETE
+        }
+        EventKind::CapDisable { cap } => {
+            p.extend_from_slice(&cap.to_be_bytes());
+            CAP_DISABLE
+        }
+        EventKind::CapEnable { cap } => {
+            p.extend_from_slice(&cap.to_be_bytes());
+            CAP_ENABLE
+        }
+        EventKind::CapsetCreate {
+            capset,
+            capset_type,
+        } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&capset_type.to_be_bytes());
+            CAPSET_CREATE
+        }
+        EventKind::CapsetDelete { capset } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            CAPSET_DELETE
+        }
+        EventKind::CapsetAssignCap { capset, cap } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&cap.to_be_bytes());
+            CAPSET_ASSIGN_CAP
+        }
+        EventKind::CapsetRemoveCap { capset, cap } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&cap.to_be_bytes());
+            CAPSET_REMOVE_CAP
+        }
+        EventKind::RtsIdentifier { capset, identifier } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(identifier.as_bytes());
+            RTS_IDENTIFIER
+        }
+        EventKind::ProgramArgs { capset, args } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            for arg in args {
+                push_string(&mut p, arg);
+            }
+            PROGRAM_ARGS
+        }
+        EventKind::WallClockTime { capset, sec, nsec } => {
+            p.extend_from_slice(&capset.to_be_bytes());
+            p.extend_from_slice(&sec.to_be_bytes());
+            p.extend_from_slice(&nsec.to_be_bytes());
+            WALL_CLOCK_TIME
+        }
+
+        EventKind::SparkCounters {
+            created,
+            dud,
+            overflowed,
+            converted,
+            gcd,
+            fizzled,
+            remaining,
+        } => {
+            for n in &[created, dud, overflowed, converted, gcd, fizzled, remaining] {
+                p.extend_from_slice(&n.to_be_bytes());
+            }
+            SPARK_COUNTERS
+        }
+        EventKind::SparkCreate => SPARK_CREATE,
+        EventKind::SparkDud => SPARK_DUD,
+        EventKind::SparkOverflow => SPARK_OVERFLOW,
+        EventKind::SparkRun => SPARK_RUN,
+        EventKind::SparkSteal { victim_cap } => {
+            p.extend_from_slice(&victim_cap.to_be_bytes());
+            SPARK_STEAL
+        }
+        EventKind::SparkFizzle => SPARK_FIZZLE,
+        EventKind::SparkGc => SPARK_GC,
+
+        EventKind::LogMsg { msg } => {
+            p.extend_from_slice(msg.as_bytes());
+            LOG_MSG
+        }
+        EventKind::UserMsg { msg } => {
+            p.extend_from_slice(msg.as_bytes());
+            USER_MSG
+        }
+        EventKind::UserMarker { marker } => {
+            p.extend_from_slice(marker.as_bytes());
+            USER_MARKER
+        }
+
+        EventKind::HeapProfBegin {
+            profile,
+            sampling_period,
+            breakdown,
+            filters,
+        } => {
+            p.push(*profile);
+            p.extend_from_slice(&sampling_period.to_be_bytes());
+            p.extend_from_slice(&breakdown.to_be_bytes());
+            for filter in filters {
+                push_string(&mut p, filter);
+            }
+            HEAP_PROF_BEGIN
+        }
+        EventKind::HeapProfCostCentre {
+            id,
+            label,
+            module,
+            src_loc,
+            is_caf,
+        } => {
+            p.extend_from_slice(&id.to_be_bytes());
+            push_string(&mut p, label);
+            push_string(&mut p, module);
+            push_string(&mut p, src_loc);
+            p.push(u8::from(*is_caf));
+            HEAP_PROF_COST_CENTRE
+        }
+        EventKind::HeapProfSampleBegin { era } => {
+            p.extend_from_slice(&era.to_be_bytes());
+            HEAP_PROF_SAMPLE_BEGIN
+        }
+        EventKind::HeapProfSampleEnd { era } => {
+            p.extend_from_slice(&era.to_be_bytes());
+            HEAP_PROF_SAMPLE_END
+        }
+        EventKind::HeapBioProfSampleBegin { era, time } => {
+            p.extend_from_slice(&era.to_be_bytes());
+            p.extend_from_slice(&time.to_be_bytes());
+            HEAP_BIO_PROF_SAMPLE_BEGIN
+        }
+        EventKind::HeapProfSampleCostCentre {
+            profile,
+            residency,
+            stack,
+        } => {
+            p.push(*profile);
+            p.extend_from_slice(&residency.to_be_bytes());
+            p.push(stack.len() as u8);
+            for cc in stack {
+                p.extend_from_slice(&cc.to_be_bytes());
+            }
+            HEAP_PROF_SAMPLE_COST_CENTRE
+        }
+        EventKind::HeapProfSampleString {
+            profile,
+            residency,
+            label,
+        } => {
+            p.push(*profile);
+            p.extend_from_slice(&residency.to_be_bytes());
+            push_string(&mut p, label);
+            HEAP_PROF_SAMPLE_STRING
+        }
+        EventKind::ProfSampleCostCentre { cap, ticks, stack } => {
+            p.extend_from_slice(&cap.to_be_bytes());
+            p.extend_from_slice(&ticks.to_be_bytes());
+            p.push(stack.len() as u8);
+            for cc in stack {
+                p.extend_from_slice(&cc.to_be_bytes());
+            }
+            PROF_SAMPLE_COST_CENTRE
+        }
+        EventKind::ProfBegin { tick_interval } => {
+            p.extend_from_slice(&tick_interval.to_be_bytes());
+            PROF_BEGIN
+        }
+        EventKind::Ipe {
+            info,
+            table_name,
+            closure_desc,
+            ty_desc,
+            label,
+            module,
+            src_loc,
+        } => {
+            p.extend_from_slice(&info.to_be_bytes());
+            for s in &[table_name, closure_desc, ty_desc, label, module, src_loc] {
+                push_string(&mut p, s);
+            }
+            IPE
+        }
+
+        EventKind::TickyCounterDef {
+            id,
+            arity,
+            kinds,
+            name,
+        } => {
+            p.extend_from_slice(&id.to_be_bytes());
+            p.extend_from_slice(&arity.to_be_bytes());
+            push_string(&mut p, kinds);
+            push_string(&mut p, name);
+            TICKY_COUNTER_DEF
+        }
+        EventKind::TickyCounterSample {
+            id,
+            entries,
+            alloc,
+            allocd,
+        } => {
+            for n in &[id, entries, alloc, allocd] {
+                p.extend_from_slice(&n.to_be_bytes());
+            }
+            TICKY_COUNTER_SAMPLE
+        }
+        EventKind::TickyBeginSample => TICKY_BEGIN_SAMPLE,
+
+        EventKind::BlockMarker {
+            size,
+            end_time,
+            block_cap,
+        } => {
+            p.extend_from_slice(&size.to_be_bytes());
+            p.extend_from_slice(&end_time.to_be_bytes());
+            p.extend_from_slice(&block_cap.unwrap_or(NO_CAP).to_be_bytes());
+            BLOCK_MARKER
+        }
+
+        EventKind::Unknown { id, payload } => {
+            p.extend_from_slice(payload);
+            *id
+        }
+    };
+    (id, p)
+}
+
+/// Writes a null-terminated string
+fn push_string(p: &mut Vec<u8>, s: &str) {
+    p.extend_from_slice(s.as_bytes());
+    p.push(0);
+}
+
+/// Events of a capability, written when the capability changes
+struct Block {
+    cap: u16,
+    start_time: u64,
+    end_time: u64,
+    bytes: Vec<u8>,
+}
+
+pub struct EventLogWriter<W: Write> {
+    writer: W,
+    /// Event sizes, by event type id
+    sizes: HashMap<u16, Option<u16>>,
+    block: Option<Block>,
+}
+
+impl<W: Write> EventLogWriter<W> {
+    /// Writes the header. A block marker event type is added if the header doesn't have one.
+    pub fn new(mut writer: W, header: &Header) -> Result<EventLogWriter<W>, String> {
+        let mut event_types = header.event_types.clone();
+        if !event_types.iter().any(|ty| ty.id == BLOCK_MARKER) {
+            event_types.push(EventType {
+                id: BLOCK_MARKER,
+                size: Some(BLOCK_MARKER_SIZE),
+                description: "Block marker".to_owned(),
+                extra: vec![],
+            });
+        }
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&HEADER_BEGIN.to_be_bytes());
+        bytes.extend_from_slice(&HET_BEGIN.to_be_bytes());
+        for ty in &event_types {
+            bytes.extend_from_slice(&ET_BEGIN.to_be_bytes());
+            bytes.extend_from_slice(&ty.id.to_be_bytes());
+            bytes.extend_from_slice(&ty.size.unwrap_or(VARIABLE_SIZE).to_be_bytes());
+            bytes.extend_from_slice(&(ty.description.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(ty.description.as_bytes());
+            bytes.extend_from_slice(&(ty.extra.len() as u32).to_be_bytes());
+            bytes.extend_from_slice(&ty.extra);
+            bytes.extend_from_slice(&ET_END.to_be_bytes());
+        }
+        bytes.extend_from_slice(&HET_END.to_be_bytes());
+        bytes.extend_from_slice(&HEADER_END.to_be_bytes());
+        bytes.extend_from_slice(&DATA_BEGIN.to_be_bytes());
+        write_all(&mut writer, &bytes)?;
+
+        Ok(EventLogWriter {
+            writer,
+            sizes: event_types.iter().map(|ty| (ty.id, ty.size)).collect(),
+            block: None,
+        })
+    }
+
+    /// Writes an event, in a block of its capability. Block markers are ignored.
+    pub fn write_event(&mut self, event: &Event) -> Result<(), String> {
+        let (id, payload) = encode(&event.kind);
+        self.write(id, event, payload)
+    }
+
+    /// Writes an event with the given payload rather than encoding its fields, e.g. the payload
+    /// from `EventLogReader::payload` to copy an event as it is. Block markers are ignored.
+    pub fn write_event_payload(&mut self, event: &Event, payload: &[u8]) -> Result<(), String> {
+        self.write(event.kind.type_id(), event, payload.to_vec())
+    }
+
+    fn write(&mut self, id: u16, event: &Event, mut payload: Vec<u8>) -> Result<(), String> {
+        if let EventKind::BlockMarker { .. } = event.kind {
+            return Ok(());
+        }
+
+        let mut bytes = vec![];
+        bytes.extend_from_slice(&id.to_be_bytes());
+        bytes.extend_from_slice(&event.time.to_be_bytes());
+        match self.sizes.get(&id) {
+            None => return Err(format!("Event type {} is not in the header", id)),
+            Some(Some(size)) => payload.resize(*size as usize, 0),
+            Some(None) => {
+                if payload.len() > usize::from(VARIABLE_SIZE - 1) {
+                    return Err(format!(
+                        "Payload of event {} at {} is too large: {} bytes",
+                        id,
+                        event.time,
+                        payload.len()
+                    ));
+                }
+                bytes.extend_from_slice(&(payload.len() as u16).to_be_bytes());
+            }
+        }
+        bytes.extend_from_slice(&payload);
+
+        if self.block.as_ref().map(|block| block.cap) != event.cap {
+            self.flush_block()?;
+        }
+        match event.cap {
+            None => write_all(&mut self.writer, &bytes),
+            Some(cap) => {
+                let block = self.block.get_or_insert(Block {
+                    cap,
+                    start_time: event.time,
+                    end_time: event.time,
+                    bytes: vec![],
+                });
+                block.end_time = std::cmp::max(block.end_time, event.time);
+                block.bytes.extend_from_slice(&bytes);
+                Ok(())
+            }
+        }
+    }
+
+    fn flush_block(&mut self) -> Result<(), String> {
+        let block = match self.block.take() {
+            None => return Ok(()),
+            Some(block) => block,
+        };
+        // Marker event header (id and time) and payload are included in the block size
+        let size = 10 + u64::from(BLOCK_MARKER_SIZE) + block.bytes.len() as u64;
+        if size > u64::from(u32::MAX) {
+            return Err(format!("Block of capability {} is too large", block.cap));
+        }
+        let mut marker = vec![];
+        marker.extend_from_slice(&BLOCK_MARKER.to_be_bytes());
+        marker.extend_from_slice(&block.start_time.to_be_bytes());
+        marker.extend_from_slice(&(size as u32).to_be_bytes());
+        marker.extend_from_slice(&block.end_time.to_be_bytes());
+        marker.extend_from_slice(&block.cap.to_be_bytes());
+        write_all(&mut self.writer, &marker)?;
+        write_all(&mut self.writer, &block.bytes)
+    }
+
+    /// Writes the last block and the end of the event data, and returns the writer
+    pub fn finish(mut self) -> Result<W, String> {
+        self.flush_block()?;
+        write_all(&mut self.writer, &DATA_END.to_be_bytes())?;
+        self.writer
+            .flush()
+            .map_err(|err| format!("Unable to write eventlog: {}", err))?;
+        Ok(self.writer)
+    }
+}
+
+fn write_all<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<(), String> {
+    writer
+        .write_all(bytes)
+        .map_err(|err| format!("Unable to write eventlog: {}", err))
+}
+
+#[cfg(test)]
+fn without_block_markers(events: Vec<Event>) -> Vec<Event> {
+    events
+        .into_iter()
+        .filter(|event| !matches!(event.kind, EventKind::BlockMarker { .. }))
+        .collect()
+}
+
+#[test]
+fn round_trip_test() {
+    let event = |time, cap, kind| Event { time, cap, kind };
+    let events = vec![
+        event(
+            0,
+            None,
+            EventKind::ProgramArgs {
+                capset: 0,
+                args: vec!["./Main".to_owned(), "+RTS".to_owned(), "-l".to_owned()],
+            },
+        ),
+        event(10, Some(0), EventKind::CreateThread { thread: 1 }),
+        event(10, Some(0), EventKind::RunThread { thread: 1 }),
+        event(
+            20,
+            Some(0),
+            EventKind::StopThread {
+                thread: 1,
+                status: 7,
+                blocked_on: 0,
+            },
+        ),
+        event(15, Some(1), EventKind::GcStart),
+        event(
+            16,
+            Some(1),
+            EventKind::GcStats {
+                capset: 0,
+                generation: 1,
+                copied: 1024,
+                slop: 8,
+                fragmentation: 0,
+                par_n_threads: 2,
+                par_max_copied: 512,
+                par_tot_copied: 1024,
+            },
+        ),
+        event(
+            17,
+            Some(1),
+            EventKind::UserMarker {
+                marker: "START phase".to_owned(),
+            },
+        ),
+        event(
+            30,
+            None,
+            EventKind::HeapProfSampleCostCentre {
+                profile: 0,
+                residency: 4096,
+                stack: vec![3, 2, 1],
+            },
+        ),
+        event(
+            40,
+            Some(0),
+            EventKind::Ipe {
+                info: 0x4a8f30,
+                table_name: "Main_go_info".to_owned(),
+                closure_desc: "15".to_owned(),
+                ty_desc: "Int".to_owned(),
+                label: "go".to_owned(),
+                module: "Main".to_owned(),
+                src_loc: "Main.hs:10:5-20".to_owned(),
+            },
+        ),
+        event(
+            45,
+            Some(0),
+            EventKind::TickyCounterDef {
+                id: 0x4c1a28,
+                arity: 2,
+                kinds: "Li".to_owned(),
+                name: "Main.$wgo{v r1ab} (fun)".to_owned(),
+            },
+        ),
+        event(45, Some(0), EventKind::TickyBeginSample),
+        event(
+            45,
+            Some(0),
+            EventKind::TickyCounterSample {
+                id: 0x4c1a28,
+                entries: 10,
+                alloc: 480,
+                allocd: 0,
+            },
+        ),
+        event(
+            50,
+            Some(0),
+            EventKind::Unknown {
+                id: 250,
+                payload: vec![1, 2, 3],
+            },
+        ),
+    ];
+
+    let mut event_types: Vec<EventType> = events
+        .iter()
+        .map(|event| {
+            let id = event.kind.type_id();
+            assert_eq!(encode(&event.kind).0, id);
+            EventType {
+                id,
+                size: match id {
+                    CREATE_THREAD | RUN_THREAD => Some(4),
+                    STOP_THREAD => Some(10),
+                    GC_START => Some(0),
+                    GC_STATS_GHC => Some(50),
+                    TICKY_BEGIN_SAMPLE => Some(0),
+                    TICKY_COUNTER_SAMPLE => Some(32),
+                    250 => Some(3),
+                    _ => None,
+                },
+                description: format!("event {}", id),
+                extra: vec![],
+            }
+        })
+        .collect();
+    event_types.dedup();
+    let header = Header { event_types };
+
+    let mut writer = EventLogWriter::new(vec![], &header).unwrap();
+    for event in &events {
+        writer.write_event(event).unwrap();
+    }
+    let bytes = writer.finish().unwrap();
+
+    let reader = EventLogReader::new(&bytes[..]).unwrap();
+    assert_eq!(
+        reader.header().event_types[..header.event_types.len()],
+        header.event_types[..]
+    );
+    let read_events: Vec<Event> = reader.collect::<Result<_, _>>().unwrap();
+    assert_eq!(without_block_markers(read_events.clone()), events);
+
+    // Writing the read events gives the same eventlog
+    let reader = EventLogReader::new(&bytes[..]).unwrap();
+    let mut writer = EventLogWriter::new(vec![], reader.header()).unwrap();
+    for event in &read_events {
+        writer.write_event(event).unwrap();
+    }
+    assert_eq!(writer.finish().unwrap(), bytes);
+}
+
+#[test]
+fn fixed_size_test() {
+    // An older GHC without the `blocked_on` field in stop thread events
+    let header = Header {
+        event_types: vec![EventType {
+            id: STOP_THREAD,
+            size: Some(6),
+            description: "Stop thread".to_owned(),
+            extra: vec![],
+        }],
+    };
+    let event = Event {
+        time: 5,
+        cap: None,
+        kind: EventKind::StopThread {
+            thread: 1,
+            status: 5,
+            blocked_on: 0,
+        },
+    };
+    let mut writer = EventLogWriter::new(vec![], &header).unwrap();
+    writer.write_event(&event).unwrap();
+    let bytes = writer.finish().unwrap();
+    let events: Vec<Event> = EventLogReader::new(&bytes[..])
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(events, vec![event.clone()]);
+
+    let mut writer = EventLogWriter::new(vec![], &header).unwrap();
+    assert_eq!(
+        writer.write_event(&Event {
+            kind: EventKind::GcStart,
+            ..event
+        }),
+        Err("Event type 9 is not in the header".to_owned())
+    );
+}
+
+#[test]
+fn write_event_payload_test() {
+    // A ticky counter definition with fields after the name, as newer GHCs write them
+    let mut log = TestLog::new();
+    log.event_type(TICKY_COUNTER_DEF, None, "Ticky counter definition");
+    let mut payload = vec![];
+    payload.extend_from_slice(&1u64.to_be_bytes());
+    payload.extend_from_slice(&0u16.to_be_bytes());
+    payload.extend_from_slice(b"\0Main.go{v r1} (fun)\0");
+    payload.extend_from_slice(&0x4a8f30u64.to_be_bytes());
+    payload.extend_from_slice(b"{\"type\":\"entCntr\"}\0");
+    log.event(TICKY_COUNTER_DEF, 10, &payload);
+    let bytes = log.bytes();
+
+    let copy = |raw: bool| -> Vec<u8> {
+        let mut reader = EventLogReader::new(&bytes[..]).unwrap();
+        let mut writer = EventLogWriter::new(vec![], reader.header()).unwrap();
+        while let Some(event) = reader.next_event().unwrap() {
+            if raw {
+                writer
+                    .write_event_payload(&event, reader.payload())
+                    .unwrap();
+            } else {
+                writer.write_event(&event).unwrap();
+            }
+        }
+        let bytes = writer.finish().unwrap();
+        let mut reader = EventLogReader::new(&bytes[..]).unwrap();
+        reader.next_event().unwrap().unwrap();
+        reader.payload().to_vec()
+    };
+    assert_eq!(copy(true), payload);
+    // Encoding from the decoded fields drops the fields after the name
+    assert_eq!(copy(false), payload[..payload.len() - 27]);
+}
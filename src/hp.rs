@@ -0,0 +1,409 @@
+//! Types, parser and printer for GHC's heap profiles (`.hp` files, `+RTS -h*`)
+//!
+//! A heap profile is a header followed by samples:
+//!
+//! ```text
+//! JOB "prog +RTS -hT"
+//! DATE "Thu Jan  1 00:00 1970"
+//! SAMPLE_UNIT "seconds"
+//! VALUE_UNIT "bytes"
+//! BEGIN_SAMPLE 0.10
+//! ghc-prim:GHC.Types.:    1024
+//! MAIN    48
+//! END_SAMPLE 0.10
+//! ```
+//!
+//! Band names and sizes are separated by a tab. Band names can contain spaces.
+
+use crate::utils::change;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+mod svg;
+
+pub use svg::{write_hp_svg, HpSvgOptions};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HpFile {
+    pub job: String,
+    pub date: String,
+    pub sample_unit: String,
+    pub value_unit: String,
+    pub samples: Vec<Sample>,
+    /// Times of `MARK`s
+    pub marks: Vec<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sample {
+    pub time: f64,
+    /// Band names and sizes, in the order they appear in the sample
+    pub bands: Vec<(String, u64)>,
+}
+
+impl Sample {
+    pub fn total(&self) -> u64 {
+        self.bands.iter().map(|(_, size)| size).sum()
+    }
+}
+
+/// Parses a heap profile. Errors include the line number.
+pub fn parse_hp(s: &str) -> Result<HpFile, String> {
+    let mut hp = HpFile {
+        job: String::new(),
+        date: String::new(),
+        sample_unit: String::new(),
+        value_unit: String::new(),
+        samples: vec![],
+        marks: vec![],
+    };
+
+    let mut sample: Option<Sample> = None;
+
+    for (line_idx, line) in s.lines().enumerate() {
+        let err = |msg: String| format!("line {}: {}", line_idx + 1, msg);
+        let line = line.trim_end();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(current) = sample.as_mut() {
+            if let Some(time) = line.strip_prefix("END_SAMPLE") {
+                let time = parse_time(time).map_err(err)?;
+                if time != current.time {
+                    return Err(err(format!(
+                        "END_SAMPLE {} does not match BEGIN_SAMPLE {}",
+                        time, current.time
+                    )));
+                }
+                hp.samples.push(sample.take().unwrap());
+                continue;
+            }
+            // Band names can contain spaces, size is after the last tab or space
+            let (name, size) = match line.rfind(|c: char| c.is_ascii_whitespace()) {
+                None => return Err(err(format!("Unable to parse sample line: {}", line))),
+                Some(idx) => (line[..idx].trim_end(), &line[idx + 1..]),
+            };
+            let size = size
+                .parse::<u64>()
+                .map_err(|e| err(format!("Unable to parse band size {}: {}", size, e)))?;
+            current.bands.push((name.to_owned(), size));
+            continue;
+        }
+
+        let (key, value) = match line.find(' ') {
+            None => (line, ""),
+            Some(idx) => (&line[..idx], line[idx + 1..].trim()),
+        };
+        match key {
+            "JOB" => hp.job = unquote(value),
+            "DATE" => hp.date = unquote(value),
+            "SAMPLE_UNIT" => hp.sample_unit = unquote(value),
+            "VALUE_UNIT" => hp.value_unit = unquote(value),
+            "MARK" => hp.marks.push(parse_time(value).map_err(err)?),
+            "BEGIN_SAMPLE" => {
+                sample = Some(Sample {
+                    time: parse_time(value).map_err(err)?,
+                    bands: vec![],
+                })
+            }
+            _ => return Err(err(format!("Unexpected line: {}", line))),
+        }
+    }
+
+    if let Some(sample) = sample {
+        return Err(format!("Sample at {} is not terminated", sample.time));
+    }
+
+    Ok(hp)
+}
+
+fn parse_time(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    s.parse::<f64>()
+        .map_err(|e| format!("Unable to parse time {}: {}", s, e))
+}
+
+fn unquote(s: &str) -> String {
+    s.strip_prefix('"')
+        .and_then(|s| s.strip_suffix('"'))
+        .unwrap_or(s)
+        .to_owned()
+}
+
+/// Writes a heap profile in `.hp` format. Marks are written before the first sample after them.
+pub fn write_hp<W: Write>(hp: &HpFile, w: &mut W) {
+    writeln!(w, "JOB \"{}\"", hp.job).unwrap();
+    writeln!(w, "DATE \"{}\"", hp.date).unwrap();
+    writeln!(w, "SAMPLE_UNIT \"{}\"", hp.sample_unit).unwrap();
+    writeln!(w, "VALUE_UNIT \"{}\"", hp.value_unit).unwrap();
+
+    let mut marks = hp.marks.iter().peekable();
+    for sample in &hp.samples {
+        while let Some(mark) = marks.next_if(|mark| **mark < sample.time) {
+            writeln!(w, "MARK {}", mark).unwrap();
+        }
+        writeln!(w, "BEGIN_SAMPLE {}", sample.time).unwrap();
+        for (name, size) in &sample.bands {
+            writeln!(w, "{}\t{}", name, size).unwrap();
+        }
+        writeln!(w, "END_SAMPLE {}", sample.time).unwrap();
+    }
+    for mark in marks {
+        writeln!(w, "MARK {}", mark).unwrap();
+    }
+}
+
+pub fn parse_hp_file(path: &str) -> HpFile {
+    let contents = std::fs::read_to_string(path).unwrap();
+    match parse_hp(&contents) {
+        Ok(hp) => hp,
+        Err(err) => {
+            eprintln!("Unable to parse {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Residency of a band
+#[derive(Debug, Clone, PartialEq)]
+pub struct BandStats {
+    pub name: String,
+    /// Size at the sample with the largest total residency
+    pub at_peak: u64,
+    /// Largest size of the band
+    pub max: u64,
+    /// Average size over all samples. Samples are taken at regular intervals, so this is the
+    /// average over time.
+    pub average: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct HpSummary {
+    /// Time of the sample with the largest total residency
+    pub peak_time: f64,
+    /// Largest total residency
+    pub peak: u64,
+    /// Average total residency
+    pub average: f64,
+    /// Bands, sorted by average size
+    pub bands: Vec<BandStats>,
+}
+
+pub fn summarize(hp: &HpFile) -> HpSummary {
+    let n_samples = std::cmp::max(hp.samples.len(), 1) as f64;
+
+    let peak_sample = hp.samples.iter().max_by_key(|sample| sample.total());
+    let (peak_time, peak) = match peak_sample {
+        None => (0.0, 0),
+        Some(sample) => (sample.time, sample.total()),
+    };
+    let at_peak: HashMap<&str, u64> = match peak_sample {
+        None => HashMap::new(),
+        Some(sample) => sample
+            .bands
+            .iter()
+            .map(|(name, size)| (name.as_str(), *size))
+            .collect(),
+    };
+
+    let mut bands: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    let mut total: u64 = 0;
+    for sample in &hp.samples {
+        for (name, size) in &sample.bands {
+            let (max, sum) = bands.entry(name).or_default();
+            *max = std::cmp::max(*max, *size);
+            *sum += size;
+            total += size;
+        }
+    }
+
+    let mut bands: Vec<BandStats> = bands
+        .into_iter()
+        .map(|(name, (max, sum))| BandStats {
+            name: name.to_owned(),
+            at_peak: at_peak.get(name).copied().unwrap_or(0),
+            max,
+            average: (sum as f64) / n_samples,
+        })
+        .collect();
+    bands.sort_by(|b1, b2| b2.average.partial_cmp(&b1.average).unwrap());
+
+    HpSummary {
+        peak_time,
+        peak,
+        average: (total as f64) / n_samples,
+        bands,
+    }
+}
+
+pub fn write_summary<W: Write>(hp: &HpFile, summary: &HpSummary, w: &mut W) {
+    writeln!(w, "job:     {}", hp.job).unwrap();
+    writeln!(w, "date:    {}", hp.date).unwrap();
+    writeln!(w, "samples: {}", hp.samples.len()).unwrap();
+    writeln!(
+        w,
+        "peak:    {} {} at {} {}",
+        summary.peak, hp.value_unit, summary.peak_time, hp.sample_unit
+    )
+    .unwrap();
+    writeln!(w, "average: {:.0} {}", summary.average, hp.value_unit).unwrap();
+    writeln!(w).unwrap();
+
+    for band in &summary.bands {
+        writeln!(
+            w,
+            "{}: at peak {}, max {}, average {:.0}",
+            band.name, band.at_peak, band.max, band.average
+        )
+        .unwrap();
+    }
+}
+
+/// Shows changes in residency of bands at peak and on average, sorted by change in average
+pub fn write_summary_diff<W: Write>(s1: &HpSummary, s2: &HpSummary, w: &mut W) {
+    writeln!(
+        w,
+        "peak:    {} -> {} ({:+}, {})",
+        s1.peak,
+        s2.peak,
+        (s2.peak as i64) - (s1.peak as i64),
+        change(s1.peak as f64, s2.peak as f64)
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "average: {:.0} -> {:.0} ({:+.0}, {})",
+        s1.average,
+        s2.average,
+        s2.average - s1.average,
+        change(s1.average, s2.average)
+    )
+    .unwrap();
+    writeln!(w).unwrap();
+
+    let mut bands: BTreeMap<&str, (Option<&BandStats>, Option<&BandStats>)> = BTreeMap::new();
+    for band in &s1.bands {
+        bands.entry(&band.name).or_default().0 = Some(band);
+    }
+    for band in &s2.bands {
+        bands.entry(&band.name).or_default().1 = Some(band);
+    }
+
+    let mut rows: Vec<_> = bands
+        .into_iter()
+        .map(|(name, (band1, band2))| {
+            let stats = |band: Option<&BandStats>| match band {
+                None => (0, 0.0),
+                Some(band) => (band.at_peak, band.average),
+            };
+            (name, stats(band1), stats(band2))
+        })
+        .filter(|(_, stats1, stats2)| stats1 != stats2)
+        .collect();
+    rows.sort_by(|(_, (_, avg11), (_, avg12)), (_, (_, avg21), (_, avg22))| {
+        (avg22 - avg21)
+            .abs()
+            .partial_cmp(&(avg12 - avg11).abs())
+            .unwrap()
+    });
+
+    for (name, (peak1, avg1), (peak2, avg2)) in rows {
+        writeln!(
+            w,
+            "{}: at peak {} -> {} ({:+}), average {:.0} -> {:.0} ({:+.0}, {})",
+            name,
+            peak1,
+            peak2,
+            (peak2 as i64) - (peak1 as i64),
+            avg1,
+            avg2,
+            avg2 - avg1,
+            change(avg1, avg2)
+        )
+        .unwrap();
+    }
+}
+
+/// A small heap profile used in tests
+#[cfg(test)]
+pub(crate) const TEST_HP: &str = "JOB \"test +RTS -hT\"
+DATE \"Thu Jan  1 00:00 1970\"
+SAMPLE_UNIT \"seconds\"
+VALUE_UNIT \"bytes\"
+BEGIN_SAMPLE 0.00
+END_SAMPLE 0.00
+BEGIN_SAMPLE 0.10
+ghc-prim:GHC.Types.:\t300
+containers-0.6.2.1:Data.Map.Internal.Bin\t100
+END_SAMPLE 0.10
+MARK 0.15
+BEGIN_SAMPLE 0.20
+ghc-prim:GHC.Types.:\t200
+containers-0.6.2.1:Data.Map.Internal.Bin\t400
+ARR_WORDS\t100
+END_SAMPLE 0.20
+BEGIN_SAMPLE 0.30
+ghc-prim:GHC.Types.:\t100
+END_SAMPLE 0.30
+";
+
+#[test]
+fn parse_test() {
+    let hp = parse_hp(TEST_HP).unwrap();
+    assert_eq!(hp.job, "test +RTS -hT");
+    assert_eq!(hp.value_unit, "bytes");
+    assert_eq!(hp.samples.len(), 4);
+    assert_eq!(hp.marks, vec![0.15]);
+    assert_eq!(
+        hp.samples[1].bands[1],
+        ("containers-0.6.2.1:Data.Map.Internal.Bin".to_owned(), 100)
+    );
+
+    assert_eq!(
+        parse_hp("BEGIN_SAMPLE 0.1\nfoo\t12x\nEND_SAMPLE 0.1\n"),
+        Err("line 2: Unable to parse band size 12x: invalid digit found in string".to_owned())
+    );
+    assert!(parse_hp("BEGIN_SAMPLE 0.1\n").is_err());
+}
+
+#[test]
+fn write_hp_test() {
+    let hp = parse_hp(TEST_HP).unwrap();
+    let mut out = vec![];
+    write_hp(&hp, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("END_SAMPLE 0.1\nMARK 0.15\nBEGIN_SAMPLE 0.2\n"));
+    assert_eq!(parse_hp(&out).unwrap(), hp);
+}
+
+#[test]
+fn summary_test() {
+    let hp = parse_hp(TEST_HP).unwrap();
+    let summary = summarize(&hp);
+    assert_eq!(summary.peak, 700);
+    assert_eq!(summary.peak_time, 0.2);
+    assert_eq!(summary.average, 300.0);
+    assert_eq!(
+        summary.bands[0],
+        BandStats {
+            name: "ghc-prim:GHC.Types.:".to_owned(),
+            at_peak: 200,
+            max: 300,
+            average: 150.0,
+        }
+    );
+
+    let mut hp2 = hp.clone();
+    hp2.samples[2].bands[2].1 = 500;
+    let mut out = vec![];
+    write_summary_diff(&summary, &summarize(&hp2), &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("peak:    700 -> 1100 (+400, +57.14%)\n"));
+    assert!(out
+        .lines()
+        .nth(3)
+        .unwrap()
+        .starts_with("ARR_WORDS: at peak 100 -> 500 (+400), average 25 -> 125 (+100, +400.00%)"));
+}
@@ -0,0 +1,374 @@
+//! Renders heap profiles as SVG stacked area charts, like hp2ps but viewable in browsers and
+//! Gitlab. The largest bands (by average size) are drawn bottom to top, the rest are summed into
+//! an "OTHER" band at the top.
+
+use super::{summarize, HpFile};
+use crate::xml::escape;
+use crate::z_decode::decode_symbol;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+use regex::Regex;
+
+pub struct HpSvgOptions {
+    /// Number of bands to draw, the rest are shown as "OTHER"
+    pub top: usize,
+    pub title: String,
+}
+
+const IMAGE_WIDTH: f64 = 1200.0;
+const IMAGE_HEIGHT: f64 = 600.0;
+const FONT_SIZE: f64 = 12.0;
+const LEFT_PAD: f64 = 80.0;
+const TOP_PAD: f64 = FONT_SIZE * 3.0;
+const BOTTOM_PAD: f64 = FONT_SIZE * 4.0;
+const LEGEND_WIDTH: f64 = 320.0;
+const LEGEND_ROW_HEIGHT: f64 = FONT_SIZE * 1.5;
+/// Max. length of band names in the legend
+const MAX_NAME_LEN: usize = 40;
+const N_TICKS: f64 = 5.0;
+
+/// Colours of the bands. Colours repeat after this many bands, so this should be at least the
+/// default `--top` of ghc-hp-compare.
+const PALETTE: [&str; 18] = [
+    "rgb(31,119,180)",
+    "rgb(255,127,14)",
+    "rgb(44,160,44)",
+    "rgb(214,39,40)",
+    "rgb(148,103,189)",
+    "rgb(140,86,75)",
+    "rgb(227,119,194)",
+    "rgb(188,189,34)",
+    "rgb(23,190,207)",
+    "rgb(174,199,232)",
+    "rgb(255,187,120)",
+    "rgb(152,223,138)",
+    "rgb(255,152,150)",
+    "rgb(197,176,213)",
+    "rgb(196,156,148)",
+    "rgb(247,182,210)",
+    "rgb(219,219,141)",
+    "rgb(158,218,229)",
+];
+const OTHER_COLOUR: &str = "rgb(190,190,190)";
+
+lazy_static! {
+    /// Package versions and unit id hashes: `containers-0.6.2.1`, `text-1.2.4.0-abc123def456`
+    static ref PKG_VERSION_RE: Regex =
+        Regex::new(r"-[0-9]+(\.[0-9]+)*(-[0-9a-zA-Z]{6,})?(?P<sep>[:_.])").unwrap();
+}
+
+/// A band in the chart
+struct Band {
+    /// Full (z-decoded) name, shown in tooltips
+    name: String,
+    /// Shortened name, shown in the legend
+    short_name: String,
+    colour: &'static str,
+    /// Size at each sample
+    sizes: Vec<u64>,
+}
+
+/// Renders a stacked area chart of a heap profile
+pub fn write_hp_svg<W: Write>(hp: &HpFile, opts: &HpSvgOptions, w: &mut W) {
+    let summary = summarize(hp);
+    let n_samples = hp.samples.len();
+
+    let mut band_idx: HashMap<&str, usize> = HashMap::new();
+    let mut bands: Vec<Band> = vec![];
+    for (idx, stats) in summary.bands.iter().take(opts.top).enumerate() {
+        band_idx.insert(&stats.name, idx);
+        let name = decode_symbol(&stats.name);
+        bands.push(Band {
+            short_name: shorten(&name),
+            name,
+            colour: PALETTE[idx % PALETTE.len()],
+            sizes: vec![0; n_samples],
+        });
+    }
+    let n_other = summary.bands.len().saturating_sub(opts.top);
+    let mut other = Band {
+        name: format!("OTHER ({} bands)", n_other),
+        short_name: format!("OTHER ({} bands)", n_other),
+        colour: OTHER_COLOUR,
+        sizes: vec![0; n_samples],
+    };
+
+    for (sample_idx, sample) in hp.samples.iter().enumerate() {
+        for (name, size) in &sample.bands {
+            match band_idx.get(name.as_str()) {
+                Some(idx) => bands[*idx].sizes[sample_idx] += size,
+                None => other.sizes[sample_idx] += size,
+            }
+        }
+    }
+    if n_other != 0 {
+        bands.push(other);
+    }
+
+    let max_time = hp
+        .samples
+        .iter()
+        .map(|sample| sample.time)
+        .fold(0.0, f64::max);
+    let max_value = hp
+        .samples
+        .iter()
+        .map(|sample| sample.total())
+        .max()
+        .unwrap_or(0);
+
+    let chart_width = IMAGE_WIDTH - LEFT_PAD - LEGEND_WIDTH;
+    let chart_height = IMAGE_HEIGHT - TOP_PAD - BOTTOM_PAD;
+
+    let (value_div, value_unit) = value_unit(max_value as f64, &hp.value_unit);
+    let value_step = nice_step((max_value as f64) / value_div) * value_div;
+    let value_top = if max_value == 0 {
+        value_step
+    } else {
+        ((max_value as f64) / value_step).ceil() * value_step
+    };
+    let time_step = nice_step(max_time);
+    let time_right = if max_time == 0.0 {
+        time_step
+    } else {
+        (max_time / time_step).ceil() * time_step
+    };
+
+    let x = |time: f64| LEFT_PAD + time / time_right * chart_width;
+    let y = |value: f64| TOP_PAD + chart_height - value / value_top * chart_height;
+
+    writeln!(
+        w,
+        r#"<?xml version="1.0" standalone="no"?>
+<svg version="1.1" width="{width}" height="{height}" viewBox="0 0 {width} {height}" xmlns="http://www.w3.org/2000/svg">
+<style type="text/css">
+  text {{ font-family: monospace; font-size: {font}px; fill: rgb(0,0,0); }}
+  .band:hover {{ stroke: black; stroke-width: 0.5; }}
+  .axis {{ stroke: black; stroke-width: 1; }}
+  .grid {{ stroke: rgb(220,220,220); stroke-width: 1; }}
+</style>
+<rect x="0" y="0" width="{width}" height="{height}" fill="white"/>
+<text x="{title_x}" y="{title_y}" text-anchor="middle" style="font-size: {title_font}px">{title}</text>"#,
+        width = IMAGE_WIDTH,
+        height = IMAGE_HEIGHT,
+        font = FONT_SIZE,
+        title_x = LEFT_PAD + chart_width / 2.0,
+        title_y = FONT_SIZE * 2.0,
+        title_font = FONT_SIZE + 5.0,
+        title = escape(&opts.title),
+    )
+    .unwrap();
+
+    // Grid and y axis labels
+    let mut value = 0.0;
+    while value <= value_top + value_step / 2.0 {
+        writeln!(
+            w,
+            r#"<line class="grid" x1="{x1}" y1="{y}" x2="{x2}" y2="{y}"/>
+<text x="{text_x}" y="{text_y}" text-anchor="end">{label}</text>"#,
+            x1 = LEFT_PAD,
+            x2 = LEFT_PAD + chart_width,
+            y = y(value),
+            text_x = LEFT_PAD - 5.0,
+            text_y = y(value) + FONT_SIZE / 3.0,
+            label = format_number(value / value_div),
+        )
+        .unwrap();
+        value += value_step;
+    }
+
+    // x axis labels
+    let mut time = 0.0;
+    while time <= time_right + time_step / 2.0 {
+        writeln!(
+            w,
+            r#"<text x="{x}" y="{y}" text-anchor="middle">{label}</text>"#,
+            x = x(time),
+            y = TOP_PAD + chart_height + FONT_SIZE * 1.5,
+            label = format_number(time),
+        )
+        .unwrap();
+        time += time_step;
+    }
+
+    // Stacked bands, first band at the bottom
+    let mut lower: Vec<u64> = vec![0; n_samples];
+    for band in &bands {
+        let upper: Vec<u64> = lower
+            .iter()
+            .zip(band.sizes.iter())
+            .map(|(l, s)| l + s)
+            .collect();
+        let mut points: Vec<String> = Vec::with_capacity(n_samples * 2);
+        for (sample, value) in hp.samples.iter().zip(upper.iter()) {
+            points.push(format!("{:.2},{:.2}", x(sample.time), y(*value as f64)));
+        }
+        for (sample, value) in hp.samples.iter().zip(lower.iter()).rev() {
+            points.push(format!("{:.2},{:.2}", x(sample.time), y(*value as f64)));
+        }
+        writeln!(
+            w,
+            r#"<polygon class="band" fill="{}" points="{}"><title>{}</title></polygon>"#,
+            band.colour,
+            points.join(" "),
+            escape(&band.name),
+        )
+        .unwrap();
+        lower = upper;
+    }
+
+    // Marks
+    for mark in &hp.marks {
+        writeln!(
+            w,
+            r#"<line class="axis" x1="{x}" y1="{y1}" x2="{x}" y2="{y2}" stroke-dasharray="2,2"/>"#,
+            x = x(*mark),
+            y1 = TOP_PAD,
+            y2 = TOP_PAD + chart_height,
+        )
+        .unwrap();
+    }
+
+    // Axes and axis titles
+    writeln!(
+        w,
+        r#"<line class="axis" x1="{left}" y1="{top}" x2="{left}" y2="{bottom}"/>
+<line class="axis" x1="{left}" y1="{bottom}" x2="{right}" y2="{bottom}"/>
+<text x="{x_title_x}" y="{x_title_y}" text-anchor="middle">{x_title}</text>
+<text x="{y_title_x}" y="{y_title_y}" text-anchor="middle" transform="rotate(-90 {y_title_x} {y_title_y})">{y_title}</text>"#,
+        left = LEFT_PAD,
+        right = LEFT_PAD + chart_width,
+        top = TOP_PAD,
+        bottom = TOP_PAD + chart_height,
+        x_title_x = LEFT_PAD + chart_width / 2.0,
+        x_title_y = IMAGE_HEIGHT - FONT_SIZE,
+        x_title = escape(&hp.sample_unit),
+        y_title_x = FONT_SIZE * 1.5,
+        y_title_y = TOP_PAD + chart_height / 2.0,
+        y_title = escape(&value_unit),
+    )
+    .unwrap();
+
+    // Legend, top band first to match the chart
+    let legend_x = IMAGE_WIDTH - LEGEND_WIDTH + 20.0;
+    for (row, band) in bands.iter().rev().enumerate() {
+        let row_y = TOP_PAD + (row as f64) * LEGEND_ROW_HEIGHT;
+        writeln!(
+            w,
+            r#"<g><title>{name}</title><rect x="{x}" y="{rect_y}" width="{size}" height="{size}" fill="{colour}"/><text x="{text_x}" y="{text_y}">{short_name}</text></g>"#,
+            name = escape(&band.name),
+            x = legend_x,
+            rect_y = row_y,
+            size = FONT_SIZE,
+            colour = band.colour,
+            text_x = legend_x + FONT_SIZE * 1.5,
+            text_y = row_y + FONT_SIZE - 2.0,
+            short_name = escape(&band.short_name),
+        )
+        .unwrap();
+    }
+
+    writeln!(w, "</svg>").unwrap();
+}
+
+/// Shortens band names for the legend: removes package versions and unit ids, and truncates long
+/// names in the middle
+fn shorten(name: &str) -> String {
+    let name = PKG_VERSION_RE.replace_all(name, "$sep");
+    let n_chars = name.chars().count();
+    if n_chars <= MAX_NAME_LEN {
+        return name.into_owned();
+    }
+    let half = (MAX_NAME_LEN - 3) / 2;
+    let start: String = name.chars().take(half).collect();
+    let end: String = name.chars().skip(n_chars - half).collect();
+    format!("{}...{}", start, end)
+}
+
+/// A step of 1, 2 or 5 times a power of 10 that divides `max` into about `N_TICKS` ticks
+fn nice_step(max: f64) -> f64 {
+    if max <= 0.0 {
+        return 1.0;
+    }
+    let raw = max / N_TICKS;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let normalized = raw / magnitude;
+    let nice = if normalized <= 1.0 {
+        1.0
+    } else if normalized <= 2.0 {
+        2.0
+    } else if normalized <= 5.0 {
+        5.0
+    } else {
+        10.0
+    };
+    nice * magnitude
+}
+
+/// Divisor and name of the unit to show values in. Bytes are shown in KiB, MiB or GiB.
+fn value_unit(max: f64, unit: &str) -> (f64, String) {
+    if unit != "bytes" {
+        return (1.0, unit.to_owned());
+    }
+    let mut div = 1.0;
+    for unit in &["bytes", "KiB", "MiB", "GiB"] {
+        if max < div * 1024.0 || *unit == "GiB" {
+            return (div, (*unit).to_owned());
+        }
+        div *= 1024.0;
+    }
+    unreachable!()
+}
+
+fn format_number(n: f64) -> String {
+    let s = format!("{:.3}", n);
+    s.trim_end_matches('0').trim_end_matches('.').to_owned()
+}
+
+#[test]
+fn names_test() {
+    assert_eq!(
+        shorten("containers-0.6.2.1:Data.Map.Internal.Bin"),
+        "containers:Data.Map.Internal.Bin"
+    );
+    assert_eq!(
+        shorten("text-1.2.4.0-Cabc123def:Data.Text.Internal.Text"),
+        "text:Data.Text.Internal.Text"
+    );
+    assert_eq!(
+        shorten("(123)Main.someVeryLongFunctionName/Main.anotherLongName/Main.main"),
+        "(123)Main.someVery...LongName/Main.main"
+    );
+}
+
+#[test]
+fn units_test() {
+    assert_eq!(nice_step(700.0), 200.0);
+    assert_eq!(nice_step(0.3), 0.1);
+    assert_eq!(
+        value_unit(3_000_000.0, "bytes"),
+        (1024.0 * 1024.0, "MiB".to_owned())
+    );
+    assert_eq!(value_unit(500.0, "bytes"), (1.0, "bytes".to_owned()));
+    assert_eq!(format_number(0.30000000000000004), "0.3");
+}
+
+#[test]
+fn svg_test() {
+    let hp = super::parse_hp(super::TEST_HP).unwrap();
+    let opts = HpSvgOptions {
+        top: 2,
+        title: "<test>".to_owned(),
+    };
+    let mut out = vec![];
+    write_hp_svg(&hp, &opts, &mut out);
+    let svg = String::from_utf8(out).unwrap();
+
+    assert!(svg.contains("&lt;test&gt;"));
+    // Two bands and OTHER
+    assert_eq!(svg.matches(r#"<polygon class="band""#).count(), 3);
+    assert!(svg.contains("<title>OTHER (1 bands)</title>"));
+    assert!(svg.contains(">containers:Data.Map.Internal.Bin</text>"));
+}
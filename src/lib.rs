@@ -3,6 +3,13 @@ extern crate lazy_static;
 
 use regex::Regex;
 
+pub mod eventlog;
+pub mod hp;
+pub mod markdown;
+pub mod prof;
+pub mod ticky;
+pub mod utils;
+mod xml;
 mod z_decode;
 mod z_encode;
 
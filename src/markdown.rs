@@ -0,0 +1,30 @@
+//! Writing Gitlab markdown tables with aligned columns
+
+use std::io::Write;
+
+/// Writes a row, padding cells to `widths`. Widths include a space on each side of the cell.
+pub fn print_cols<W: Write>(row: &[String], widths: &[usize], w: &mut W) {
+    for (width, col) in widths.iter().zip(row.iter()) {
+        // Assuming ASCII
+        let str_w = col.len();
+
+        write!(w, "| ").unwrap();
+        write!(w, "{}", col).unwrap();
+        for _ in 0..width - str_w - 1 {
+            write!(w, " ").unwrap();
+        }
+    }
+    writeln!(w, "|").unwrap();
+}
+
+/// Writes the separator row after the header
+pub fn print_sep<W: Write>(widths: &[usize], w: &mut W) {
+    write!(w, "|").unwrap();
+    for width in widths {
+        for _ in 0..*width {
+            write!(w, "-").unwrap();
+        }
+        write!(w, "|").unwrap();
+    }
+    writeln!(w).unwrap();
+}
@@ -0,0 +1,269 @@
+//! Types for GHC's prof JSON dumps (`+RTS -pj`)
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+mod callgraph;
+mod collapse;
+mod filter;
+mod flamegraph;
+mod folded;
+mod header;
+mod html;
+mod matching;
+mod merge;
+mod normalize;
+
+pub use callgraph::{call_graph, write_call_graph, write_call_graph_diff, CallCosts, CallGraph};
+pub use collapse::{collapse_cost_centres, CollapseMode, CollapseRule};
+pub use filter::{Listing, ListingOptions};
+pub use flamegraph::{write_flamegraph, FlameGraphOptions};
+pub use folded::{fold_stacks, write_diff_folded, write_folded};
+pub use header::{header_warnings, write_header, write_header_diff};
+pub use html::write_html_report;
+pub(crate) use matching::{is_unique, strip_unique};
+pub use matching::{match_cost_centres, CcMatch, MatchKind, Matching};
+pub use merge::{cost_centre_stats, merge_profiles, CostStats, MergeMode};
+pub use normalize::{metric_total, share_diffs, write_share_diffs, ShareDiff};
+
+#[derive(Debug, Deserialize)]
+pub struct ProfFile {
+    pub program: String,
+    pub arguments: Vec<String>,
+    pub rts_arguments: Vec<String>,
+    pub end_time: String,
+    pub initial_capabilities: u8,
+    pub total_time: f64,
+    pub total_ticks: u64,
+    pub tick_interval: u64,
+    pub total_alloc: u64,
+    pub cost_centres: Vec<CostCentre>,
+    pub profile: Profile,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct CostCentre {
+    pub id: u64,
+    pub label: String,
+    pub module: String,
+    pub src_loc: String,
+    pub is_caf: bool,
+}
+
+impl CostCentre {
+    /// `module.label`, the name we use to identify a cost centre across profiles.
+    pub fn name(&self) -> String {
+        format!("{}.{}", self.module, self.label)
+    }
+
+    /// Does the cost centre have the given `label` or `module.label`?
+    pub fn matches(&self, name: &str) -> bool {
+        self.label == name || self.name() == name
+    }
+}
+
+/// A node in the cost-centre stack tree. `entries`, `alloc` and `ticks` are the costs of this node
+/// alone, costs of the children are not included.
+#[derive(Debug, Default, Deserialize)]
+pub struct Profile {
+    pub id: u64,
+    pub entries: u64,
+    pub alloc: u64,
+    pub ticks: u64,
+    pub children: Vec<Profile>,
+}
+
+impl Profile {
+    pub fn metric(&self, metric: Metric) -> u64 {
+        match metric {
+            Metric::Entries => self.entries,
+            Metric::Alloc => self.alloc,
+            Metric::Ticks => self.ticks,
+        }
+    }
+
+    /// Cost of this node and all of its children
+    pub fn inherited(&self, metric: Metric) -> u64 {
+        self.metric(metric)
+            + self
+                .children
+                .iter()
+                .map(|child| child.inherited(metric))
+                .sum::<u64>()
+    }
+}
+
+/// Costs of all metrics, for reports that show more than one
+#[derive(Debug, Default, Clone, Copy)]
+pub(crate) struct Costs {
+    pub(crate) entries: u64,
+    pub(crate) alloc: u64,
+    pub(crate) ticks: u64,
+}
+
+impl Costs {
+    /// Own costs of a node
+    pub(crate) fn own(p: &Profile) -> Costs {
+        Costs {
+            entries: p.entries,
+            alloc: p.alloc,
+            ticks: p.ticks,
+        }
+    }
+
+    pub(crate) fn add(&mut self, other: Costs) {
+        self.entries += other.entries;
+        self.alloc += other.alloc;
+        self.ticks += other.ticks;
+    }
+
+    pub(crate) fn metric(&self, metric: Metric) -> u64 {
+        match metric {
+            Metric::Entries => self.entries,
+            Metric::Alloc => self.alloc,
+            Metric::Ticks => self.ticks,
+        }
+    }
+}
+
+/// A cost we can attribute to a cost-centre stack.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    Entries,
+    Alloc,
+    Ticks,
+}
+
+impl FromStr for Metric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Metric, String> {
+        match s {
+            "entries" => Ok(Metric::Entries),
+            "alloc" => Ok(Metric::Alloc),
+            "ticks" => Ok(Metric::Ticks),
+            _ => Err(format!("Unknown metric: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Metric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Metric::Entries => "entries",
+            Metric::Alloc => "alloc",
+            Metric::Ticks => "ticks",
+        })
+    }
+}
+
+/// Maps cost centre names (`module.label`) to their own costs. A cost centre can appear in
+/// multiple stacks, costs are summed over the stacks.
+pub fn cost_map(f: &ProfFile, metric: Metric) -> HashMap<String, u64> {
+    let cc_map = f.cost_centre_map();
+    let mut costs = HashMap::new();
+    add_costs(&f.profile, &cc_map, metric, &mut costs);
+    costs
+}
+
+fn add_costs(
+    p: &Profile,
+    cc_map: &HashMap<u64, &CostCentre>,
+    metric: Metric,
+    costs: &mut HashMap<String, u64>,
+) {
+    *costs.entry(cc_map.get(&p.id).unwrap().name()).or_insert(0) += p.metric(metric);
+    for child in &p.children {
+        add_costs(child, cc_map, metric, costs);
+    }
+}
+
+pub fn parse_prof_file(path: &str) -> ProfFile {
+    let file = std::fs::File::open(path).unwrap();
+    let reader = std::io::BufReader::new(file);
+    serde_json::from_reader(reader).unwrap()
+}
+
+impl ProfFile {
+    /// Maps cost centre ids to cost centres
+    pub fn cost_centre_map(&self) -> HashMap<u64, &CostCentre> {
+        let mut map = HashMap::with_capacity(self.cost_centres.len());
+        for cc in &self.cost_centres {
+            map.insert(cc.id, cc);
+        }
+        map
+    }
+
+    /// Maps cost centre names (`module.label`) to cost centres
+    pub fn cost_centres_by_name(&self) -> HashMap<String, &CostCentre> {
+        let mut map = HashMap::with_capacity(self.cost_centres.len());
+        for cc in &self.cost_centres {
+            map.insert(cc.name(), cc);
+        }
+        map
+    }
+}
+
+/// A small profile used in tests:
+///
+/// ```text
+/// MAIN.MAIN
+///   Main.main
+///     Main.f
+///     Data.Map.insert
+///   Main.CAF
+/// ```
+#[cfg(test)]
+pub(crate) const TEST_PROFILE: &str = r#"{
+    "program": "test",
+    "arguments": [],
+    "rts_arguments": ["-pj"],
+    "end_time": "Thu Jan 1 00:00 1970",
+    "initial_capabilities": 1,
+    "total_time": 0.1,
+    "total_ticks": 100,
+    "tick_interval": 1000,
+    "total_alloc": 1000,
+    "cost_centres": [
+        {"id": 1, "label": "MAIN", "module": "MAIN", "src_loc": "<built-in>", "is_caf": false},
+        {"id": 2, "label": "main", "module": "Main", "src_loc": "Main.hs:3:1-20", "is_caf": false},
+        {"id": 3, "label": "f", "module": "Main", "src_loc": "Main.hs:6:1-10", "is_caf": false},
+        {"id": 4, "label": "insert", "module": "Data.Map", "src_loc": "Map.hs:1:1", "is_caf": false},
+        {"id": 5, "label": "CAF", "module": "Main", "src_loc": "<entire-module>", "is_caf": true}
+    ],
+    "profile": {"id": 1, "entries": 0, "alloc": 100, "ticks": 10, "children": [
+        {"id": 2, "entries": 1, "alloc": 200, "ticks": 20, "children": [
+            {"id": 3, "entries": 10, "alloc": 300, "ticks": 30, "children": []},
+            {"id": 4, "entries": 5, "alloc": 400, "ticks": 40, "children": []}
+        ]},
+        {"id": 5, "entries": 0, "alloc": 0, "ticks": 0, "children": []}
+    ]}
+}"#;
+
+#[test]
+fn inherited_test() {
+    let f: ProfFile = serde_json::from_str(TEST_PROFILE).unwrap();
+    assert_eq!(f.profile.inherited(Metric::Alloc), 1000);
+    assert_eq!(f.profile.inherited(Metric::Entries), 16);
+    assert_eq!(f.profile.children[0].inherited(Metric::Ticks), 90);
+}
+
+#[test]
+fn cost_map_test() {
+    let mut f: ProfFile = serde_json::from_str(TEST_PROFILE).unwrap();
+    // `Main.f` in two stacks: MAIN.MAIN/Main.main/Main.f and MAIN.MAIN/Main.CAF/Main.f
+    f.profile.children[1].children.push(Profile {
+        id: 3,
+        entries: 1,
+        alloc: 50,
+        ticks: 5,
+        children: vec![],
+    });
+
+    let allocs = cost_map(&f, Metric::Alloc);
+    assert_eq!(allocs["Main.f"], 350);
+    assert_eq!(allocs["Main.main"], 200);
+    assert_eq!(cost_map(&f, Metric::Entries)["Main.f"], 11);
+}
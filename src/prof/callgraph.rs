@@ -0,0 +1,260 @@
+//! gprof-style caller/callee reports for a cost centre: every stack the cost centre appears in, its
+//! callers with the costs attributed through each caller, and its callees with their costs.
+
+use super::{CostCentre, Metric, ProfFile, Profile};
+use crate::utils::{change, delta, percentage};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CallCosts {
+    pub entries: u64,
+    /// Own cost of the metric
+    pub own: u64,
+    /// Inherited cost of the metric
+    pub inherited: u64,
+}
+
+impl CallCosts {
+    fn add(&mut self, p: &Profile, metric: Metric) {
+        self.entries += p.entries;
+        self.own += p.metric(metric);
+        self.inherited += p.inherited(metric);
+    }
+}
+
+#[derive(Debug)]
+pub struct CallGraph {
+    pub metric: Metric,
+    /// Costs of the cost centre, summed over all stacks. Inherited costs of recursive calls are
+    /// only counted once.
+    pub costs: CallCosts,
+    /// Cost-centre stacks ending with the cost centre
+    pub stacks: BTreeMap<String, CallCosts>,
+    /// Costs of the cost centre attributed to each caller
+    pub callers: BTreeMap<String, CallCosts>,
+    /// Costs of the callees when called from the cost centre
+    pub callees: BTreeMap<String, CallCosts>,
+}
+
+impl CallGraph {
+    /// Call graph of a cost centre that does not appear in a profile
+    pub fn empty(metric: Metric) -> CallGraph {
+        CallGraph {
+            metric,
+            costs: CallCosts::default(),
+            stacks: BTreeMap::new(),
+            callers: BTreeMap::new(),
+            callees: BTreeMap::new(),
+        }
+    }
+}
+
+/// Builds call graph of a cost centre, given as `label` or `module.label`. Returns `None` if the
+/// cost centre does not appear in the profile.
+pub fn call_graph(f: &ProfFile, cc: &str, metric: Metric) -> Option<CallGraph> {
+    let mut graph = CallGraph::empty(metric);
+
+    let cc_map = f.cost_centre_map();
+    let mut stack = vec![];
+    let mut found = false;
+    add_profile(
+        &f.profile, cc, &cc_map, &mut stack, false, &mut found, &mut graph,
+    );
+
+    if found {
+        Some(graph)
+    } else {
+        None
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn add_profile(
+    p: &Profile,
+    name: &str,
+    cc_map: &HashMap<u64, &CostCentre>,
+    stack: &mut Vec<String>,
+    // Are we already in a call to the cost centre?
+    in_cc: bool,
+    found: &mut bool,
+    graph: &mut CallGraph,
+) {
+    let cc = cc_map.get(&p.id).unwrap();
+    let matches = cc.matches(name);
+    stack.push(cc.name());
+
+    if matches {
+        *found = true;
+
+        if in_cc {
+            graph.costs.entries += p.entries;
+            graph.costs.own += p.metric(graph.metric);
+        } else {
+            graph.costs.add(p, graph.metric);
+        }
+
+        graph
+            .stacks
+            .entry(stack.join(" > "))
+            .or_default()
+            .add(p, graph.metric);
+
+        let caller = if stack.len() > 1 {
+            stack[stack.len() - 2].clone()
+        } else {
+            "<spontaneous>".to_owned()
+        };
+        graph
+            .callers
+            .entry(caller)
+            .or_default()
+            .add(p, graph.metric);
+
+        for child in &p.children {
+            graph
+                .callees
+                .entry(cc_map.get(&child.id).unwrap().name())
+                .or_default()
+                .add(child, graph.metric);
+        }
+    }
+
+    for child in &p.children {
+        add_profile(child, name, cc_map, stack, in_cc || matches, found, graph);
+    }
+
+    stack.pop();
+}
+
+pub fn write_call_graph<W: Write>(name: &str, graph: &CallGraph, w: &mut W) {
+    let metric = graph.metric;
+    writeln!(
+        w,
+        "{}: entries {}, own {} {}, inherited {} {}",
+        name, graph.costs.entries, metric, graph.costs.own, metric, graph.costs.inherited
+    )
+    .unwrap();
+
+    for (title, costs) in &[
+        ("Stacks", &graph.stacks),
+        ("Callers", &graph.callers),
+        ("Callees", &graph.callees),
+    ] {
+        writeln!(w).unwrap();
+        writeln!(w, "{}:", title).unwrap();
+        if costs.is_empty() {
+            writeln!(w, "  (none)").unwrap();
+        }
+
+        let mut costs: Vec<(&String, &CallCosts)> = costs.iter().collect();
+        costs.sort_by_key(|(_, costs)| std::cmp::Reverse(costs.inherited));
+
+        for (name, costs) in costs {
+            writeln!(
+                w,
+                "  {}: entries {}, {} {} ({:.2}%)",
+                name,
+                costs.entries,
+                metric,
+                costs.inherited,
+                percentage(costs.inherited, graph.costs.inherited),
+            )
+            .unwrap();
+        }
+    }
+}
+
+/// Like `write_call_graph`, but shows changes from `before` to `after`. Rows are sorted by change
+/// in inherited cost.
+pub fn write_call_graph_diff<W: Write>(
+    name: &str,
+    before: &CallGraph,
+    after: &CallGraph,
+    w: &mut W,
+) {
+    let metric = after.metric;
+    writeln!(
+        w,
+        "{}: entries {} -> {}, own {} {} -> {} ({:+}), inherited {} {} -> {} ({:+})",
+        name,
+        before.costs.entries,
+        after.costs.entries,
+        metric,
+        before.costs.own,
+        after.costs.own,
+        delta(before.costs.own, after.costs.own),
+        metric,
+        before.costs.inherited,
+        after.costs.inherited,
+        delta(before.costs.inherited, after.costs.inherited),
+    )
+    .unwrap();
+
+    for (title, costs1, costs2) in &[
+        ("Stacks", &before.stacks, &after.stacks),
+        ("Callers", &before.callers, &after.callers),
+        ("Callees", &before.callees, &after.callees),
+    ] {
+        writeln!(w).unwrap();
+        writeln!(w, "{}:", title).unwrap();
+
+        let mut rows: BTreeMap<&String, (CallCosts, CallCosts)> = BTreeMap::new();
+        for (name, costs) in costs1.iter() {
+            rows.entry(name).or_default().0 = *costs;
+        }
+        for (name, costs) in costs2.iter() {
+            rows.entry(name).or_default().1 = *costs;
+        }
+
+        if rows.is_empty() {
+            writeln!(w, "  (none)").unwrap();
+        }
+
+        let mut rows: Vec<(&String, (CallCosts, CallCosts))> = rows.into_iter().collect();
+        rows.sort_by_key(|(_, (costs1, costs2))| {
+            std::cmp::Reverse(delta(costs1.inherited, costs2.inherited))
+        });
+
+        for (name, (costs1, costs2)) in rows {
+            writeln!(
+                w,
+                "  {}: entries {} -> {}, {} {} -> {} ({:+}, {})",
+                name,
+                costs1.entries,
+                costs2.entries,
+                metric,
+                costs1.inherited,
+                costs2.inherited,
+                delta(costs1.inherited, costs2.inherited),
+                change(costs1.inherited as f64, costs2.inherited as f64),
+            )
+            .unwrap();
+        }
+    }
+}
+
+#[test]
+fn call_graph_test() {
+    let f: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    assert!(call_graph(&f, "foo", Metric::Alloc).is_none());
+
+    let graph = call_graph(&f, "Main.main", Metric::Alloc).unwrap();
+    assert_eq!(
+        graph.costs,
+        CallCosts {
+            entries: 1,
+            own: 200,
+            inherited: 900
+        }
+    );
+    assert_eq!(graph.callers.keys().collect::<Vec<_>>(), vec!["MAIN.MAIN"]);
+    assert_eq!(graph.callees.len(), 2);
+    assert_eq!(graph.callees["Main.f"].inherited, 300);
+
+    let mut out = vec![];
+    write_call_graph_diff("Main.main", &graph, &graph, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("  Main.f: entries 10 -> 10, alloc 300 -> 300 (+0, +0.00%)\n"));
+}
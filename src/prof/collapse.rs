@@ -0,0 +1,199 @@
+//! Simplifying cost-centre trees by removing uninteresting cost centres, like plumbing in
+//! `GHC.Utils.Monad` or `Data.Map.Internal`. The tree is rewritten before anything else is done
+//! with the profile, so listings, diffs and flame graphs all see the simplified tree.
+
+use super::{CostCentre, Metric, ProfFile, Profile};
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CollapseMode {
+    /// Remove the cost centre and everything it calls, charging all of the costs to the caller
+    Collapse,
+    /// Remove the cost centre from stacks, charging its own costs to the caller. Callees become
+    /// callees of the caller.
+    Transparent,
+}
+
+#[derive(Debug)]
+pub struct CollapseRule {
+    /// Cost centres with modules matching this regex. `None` matches all modules.
+    pub module: Option<Regex>,
+    /// Cost centres with labels matching this regex. `None` matches all labels.
+    pub label: Option<Regex>,
+    pub mode: CollapseMode,
+}
+
+impl CollapseRule {
+    pub fn matches(&self, cc: &CostCentre) -> bool {
+        self.module
+            .as_ref()
+            .map(|re| re.is_match(&cc.module))
+            .unwrap_or(true)
+            && self
+                .label
+                .as_ref()
+                .map(|re| re.is_match(&cc.label))
+                .unwrap_or(true)
+    }
+}
+
+/// Rewrites the cost-centre tree of a profile using the first matching rule of each cost centre.
+/// The root is never removed. Children that end up with the same cost centre under one parent are
+/// merged. Entries of removed cost centres are dropped, allocations and ticks are preserved.
+pub fn collapse_cost_centres(f: &mut ProfFile, rules: &[CollapseRule]) {
+    if rules.is_empty() {
+        return;
+    }
+
+    let modes: HashMap<u64, CollapseMode> = f
+        .cost_centres
+        .iter()
+        .filter_map(|cc| {
+            rules
+                .iter()
+                .find(|rule| rule.matches(cc))
+                .map(|rule| (cc.id, rule.mode))
+        })
+        .collect();
+
+    let root = std::mem::take(&mut f.profile);
+    f.profile = rewrite(root, &modes);
+}
+
+fn rewrite(mut p: Profile, modes: &HashMap<u64, CollapseMode>) -> Profile {
+    let children = std::mem::take(&mut p.children);
+    let mut rewritten = Vec::with_capacity(children.len());
+    for child in children {
+        absorb(&mut p, &mut rewritten, child, modes);
+    }
+    p.children = merge_children(rewritten);
+    p
+}
+
+/// Adds a (not yet rewritten) child to a parent. Rewritten children are added to `children`, costs
+/// of removed cost centres to the parent.
+fn absorb(
+    parent: &mut Profile,
+    children: &mut Vec<Profile>,
+    child: Profile,
+    modes: &HashMap<u64, CollapseMode>,
+) {
+    match modes.get(&child.id) {
+        None => children.push(rewrite(child, modes)),
+        Some(CollapseMode::Collapse) => {
+            parent.alloc += child.inherited(Metric::Alloc);
+            parent.ticks += child.inherited(Metric::Ticks);
+        }
+        Some(CollapseMode::Transparent) => {
+            parent.alloc += child.alloc;
+            parent.ticks += child.ticks;
+            for grandchild in child.children {
+                absorb(parent, children, grandchild, modes);
+            }
+        }
+    }
+}
+
+/// Merges children of the same cost centre, in the order of their first appearance. Children of
+/// each node must already be merged.
+fn merge_children(children: Vec<Profile>) -> Vec<Profile> {
+    let mut merged: Vec<Profile> = Vec::with_capacity(children.len());
+    // Maps cost centre ids to indices in `merged`
+    let mut idxs: HashMap<u64, usize> = HashMap::with_capacity(children.len());
+    // Whether the child at the index had other children merged into it
+    let mut has_merges: Vec<bool> = Vec::with_capacity(children.len());
+    for child in children {
+        match idxs.get(&child.id) {
+            None => {
+                idxs.insert(child.id, merged.len());
+                merged.push(child);
+                has_merges.push(false);
+            }
+            Some(idx) => {
+                let existing = &mut merged[*idx];
+                existing.entries += child.entries;
+                existing.alloc += child.alloc;
+                existing.ticks += child.ticks;
+                existing.children.extend(child.children);
+                has_merges[*idx] = true;
+            }
+        }
+    }
+    for (p, has_merges) in merged.iter_mut().zip(has_merges) {
+        if has_merges {
+            p.children = merge_children(std::mem::take(&mut p.children));
+        }
+    }
+    merged
+}
+
+#[test]
+fn collapse_test() {
+    let rule = |module: &str, mode| CollapseRule {
+        module: Some(Regex::new(module).unwrap()),
+        label: None,
+        mode,
+    };
+
+    let mut f: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    collapse_cost_centres(&mut f, &[rule("^Data\\.Map", CollapseMode::Collapse)]);
+    let main = &f.profile.children[0];
+    assert_eq!(main.alloc, 600);
+    assert_eq!(main.children.len(), 1);
+    assert_eq!(f.profile.inherited(Metric::Alloc), 1000);
+
+    // Makes `Main.main` and `Main.CAF` transparent, `Main.f` moves to the root
+    let mut f: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    collapse_cost_centres(
+        &mut f,
+        &[CollapseRule {
+            module: Some(Regex::new("^Main$").unwrap()),
+            label: Some(Regex::new("^(main|CAF)$").unwrap()),
+            mode: CollapseMode::Transparent,
+        }],
+    );
+    let cc_map = f.cost_centre_map();
+    let children: Vec<String> = f
+        .profile
+        .children
+        .iter()
+        .map(|p| cc_map.get(&p.id).unwrap().name())
+        .collect();
+    assert_eq!(children, vec!["Main.f", "Data.Map.insert"]);
+    assert_eq!(f.profile.alloc, 300);
+    assert_eq!(f.profile.inherited(Metric::Alloc), 1000);
+
+    // `Main.f` under both `Main.main` and `Main.CAF` is merged into one child of the root, with
+    // the `Data.Map.insert` children of both merged too
+    let mut f: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let node = |id, alloc, children| Profile {
+        id,
+        alloc,
+        children,
+        ..Default::default()
+    };
+    f.profile.children[0].children[0]
+        .children
+        .push(node(4, 1, vec![]));
+    f.profile.children[1]
+        .children
+        .push(node(3, 50, vec![node(4, 7, vec![])]));
+    collapse_cost_centres(
+        &mut f,
+        &[CollapseRule {
+            module: Some(Regex::new("^Main$").unwrap()),
+            label: Some(Regex::new("^(main|CAF)$").unwrap()),
+            mode: CollapseMode::Transparent,
+        }],
+    );
+    let ids: Vec<u64> = f.profile.children.iter().map(|p| p.id).collect();
+    assert_eq!(ids, vec![3, 4]);
+    let main_f = &f.profile.children[0];
+    assert_eq!(main_f.alloc, 350);
+    assert_eq!(main_f.children.len(), 1);
+    assert_eq!(main_f.children[0].alloc, 8);
+    assert_eq!(f.profile.inherited(Metric::Alloc), 1058);
+}
@@ -0,0 +1,148 @@
+//! Selecting which cost centres to show in listings. Cost centres that are filtered out, not in the
+//! top N, or under a threshold are summed into an "other" row so that totals still add up.
+
+use super::CostCentre;
+
+use std::collections::HashMap;
+
+use regex::Regex;
+
+#[derive(Debug, Default)]
+pub struct ListingOptions {
+    /// Only show cost centres with modules matching this regex
+    pub module: Option<Regex>,
+    /// Only show cost centres with labels matching this regex
+    pub label: Option<Regex>,
+    /// Only show CAFs (`Some(true)`) or non-CAFs (`Some(false)`)
+    pub is_caf: Option<bool>,
+    /// Only show the N rows with largest absolute values
+    pub top: Option<usize>,
+    /// Hide rows with absolute values smaller than this
+    pub min_abs: u64,
+    /// Hide rows with absolute values smaller than this percentage of the total
+    pub min_percent: f64,
+}
+
+/// Result of `select_rows`
+#[derive(Debug, PartialEq)]
+pub struct Listing {
+    /// Rows to show, in the original order
+    pub rows: Vec<(String, i64)>,
+    /// Sum of the hidden rows
+    pub other: i64,
+    /// Number of hidden rows
+    pub n_other: usize,
+}
+
+impl ListingOptions {
+    pub fn matches(&self, cc: &CostCentre) -> bool {
+        self.module
+            .as_ref()
+            .map(|re| re.is_match(&cc.module))
+            .unwrap_or(true)
+            && self
+                .label
+                .as_ref()
+                .map(|re| re.is_match(&cc.label))
+                .unwrap_or(true)
+            && self
+                .is_caf
+                .map(|is_caf| cc.is_caf == is_caf)
+                .unwrap_or(true)
+    }
+
+    /// Smallest absolute value of rows to show, from `min_abs` and `min_percent` of `total`
+    pub fn min_value(&self, total: u64) -> u64 {
+        let min_percent_abs = ((total as f64) * self.min_percent / 100.0).ceil() as u64;
+        std::cmp::max(self.min_abs, min_percent_abs)
+    }
+
+    /// Selects rows to show. `ccs` maps cost centre names to cost centres, rows without a cost
+    /// centre are not filtered by module, label or CAF-ness. `total` is used for the percentage
+    /// threshold.
+    pub fn select_rows(
+        &self,
+        rows: Vec<(String, i64)>,
+        ccs: &HashMap<String, &CostCentre>,
+        total: u64,
+    ) -> Listing {
+        let min_abs = self.min_value(total);
+
+        let mut shown: Vec<bool> = rows
+            .iter()
+            .map(|(name, value)| {
+                value.unsigned_abs() >= min_abs
+                    && ccs.get(name).map(|cc| self.matches(cc)).unwrap_or(true)
+            })
+            .collect();
+
+        if let Some(top) = self.top {
+            let mut by_size: Vec<usize> = (0..rows.len()).filter(|i| shown[*i]).collect();
+            by_size.sort_by_key(|i| std::cmp::Reverse(rows[*i].1.unsigned_abs()));
+            for i in by_size.into_iter().skip(top) {
+                shown[i] = false;
+            }
+        }
+
+        let mut listing = Listing {
+            rows: Vec::with_capacity(rows.len()),
+            other: 0,
+            n_other: 0,
+        };
+        for (row, shown) in rows.into_iter().zip(shown) {
+            if shown {
+                listing.rows.push(row);
+            } else {
+                listing.other += row.1;
+                listing.n_other += 1;
+            }
+        }
+        listing
+    }
+}
+
+#[test]
+fn select_rows_test() {
+    let f: super::ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let ccs: HashMap<String, &CostCentre> =
+        f.cost_centres.iter().map(|cc| (cc.name(), cc)).collect();
+    let rows = vec![
+        ("Data.Map.insert".to_owned(), 400),
+        ("Main.f".to_owned(), 300),
+        ("Main.main".to_owned(), 200),
+        ("MAIN.MAIN".to_owned(), 100),
+        ("Main.CAF".to_owned(), -50),
+    ];
+
+    let opts = ListingOptions {
+        module: Some(Regex::new("^Main$").unwrap()),
+        top: Some(2),
+        ..Default::default()
+    };
+    assert_eq!(
+        opts.select_rows(rows.clone(), &ccs, 1000),
+        Listing {
+            rows: vec![("Main.f".to_owned(), 300), ("Main.main".to_owned(), 200)],
+            other: 450,
+            n_other: 3,
+        }
+    );
+
+    let opts = ListingOptions {
+        is_caf: Some(false),
+        min_percent: 15.0,
+        ..Default::default()
+    };
+    assert_eq!(
+        opts.select_rows(rows, &ccs, 1000),
+        Listing {
+            rows: vec![
+                ("Data.Map.insert".to_owned(), 400),
+                ("Main.f".to_owned(), 300),
+                ("Main.main".to_owned(), 200)
+            ],
+            other: 50,
+            n_other: 2,
+        }
+    );
+}
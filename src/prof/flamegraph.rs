@@ -0,0 +1,419 @@
+//! Renders cost-centre profiles as self-contained interactive SVG flame graphs (or icicle graphs,
+//! with the root at the top). Frames are coloured by module. When comparing two profiles frames are
+//! sized by the second profile and coloured by the change in their own cost: red for increase, blue
+//! for decrease. Cost-centre stacks that only appear in the first profile are thus not shown.
+//!
+//! The SVG has no external dependencies. Hovering a frame shows its costs at the bottom, clicking
+//! "Search" highlights frames matching a regex.
+
+use super::{CostCentre, Costs, Metric, ProfFile, Profile};
+
+use crate::utils::{delta, percentage};
+use crate::xml::escape;
+
+use std::collections::HashMap;
+use std::io::Write;
+
+pub struct FlameGraphOptions {
+    /// Metric that determines the frame widths
+    pub metric: Metric,
+    /// Draw the root at the top
+    pub icicle: bool,
+    pub title: String,
+}
+
+const IMAGE_WIDTH: f64 = 1200.0;
+const FRAME_HEIGHT: f64 = 16.0;
+const FONT_SIZE: f64 = 12.0;
+const FONT_WIDTH: f64 = 0.59;
+const X_PAD: f64 = 10.0;
+const TOP_PAD: f64 = FONT_SIZE * 3.0;
+const BOTTOM_PAD: f64 = FONT_SIZE * 2.0 + 10.0;
+/// Frames narrower than this (in pixels) are not drawn
+const MIN_WIDTH: f64 = 0.1;
+
+/// A cost-centre stack in one or two profiles.
+#[derive(Debug, Default)]
+struct Frame {
+    name: String,
+    module: String,
+    /// Own costs in the first profile. Only used when comparing.
+    before: Costs,
+    /// Own costs in the second profile, or in the only profile when not comparing.
+    after: Costs,
+    /// Inherited cost of the graph's metric in the second profile. Determines width of the frame.
+    total: u64,
+    children: Vec<Frame>,
+    /// Maps names of children to their indices in `children`
+    child_idx: HashMap<String, usize>,
+}
+
+impl Frame {
+    fn child(&mut self, cc: &CostCentre) -> &mut Frame {
+        let name = cc.name();
+        let idx = match self.child_idx.get(&name) {
+            Some(idx) => *idx,
+            None => {
+                let idx = self.children.len();
+                self.child_idx.insert(name.clone(), idx);
+                self.children.push(Frame {
+                    name,
+                    module: cc.module.clone(),
+                    ..Default::default()
+                });
+                idx
+            }
+        };
+        &mut self.children[idx]
+    }
+
+    fn add_profile(&mut self, p: &Profile, cc_map: &HashMap<u64, &CostCentre>, after: bool) {
+        let frame = self.child(cc_map.get(&p.id).unwrap());
+        if after {
+            frame.after.add(Costs::own(p));
+        } else {
+            frame.before.add(Costs::own(p));
+        }
+        for child in &p.children {
+            frame.add_profile(child, cc_map, after);
+        }
+    }
+
+    fn compute_totals(&mut self, metric: Metric) -> u64 {
+        let mut total = self.after.metric(metric);
+        for child in &mut self.children {
+            total += child.compute_totals(metric);
+        }
+        self.total = total;
+        total
+    }
+
+    fn max_depth(&self, min_total: f64) -> usize {
+        self.children
+            .iter()
+            .filter(|child| child.total as f64 >= min_total)
+            .map(|child| child.max_depth(min_total) + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Largest absolute change in own cost in this frame and its children
+    fn max_delta(&self, metric: Metric) -> u64 {
+        let delta = delta(self.before.metric(metric), self.after.metric(metric)).unsigned_abs();
+        self.children
+            .iter()
+            .map(|child| child.max_delta(metric))
+            .fold(delta, std::cmp::max)
+    }
+}
+
+/// Renders a flame graph of `after`. When `before` is available frames are coloured by the change
+/// in their costs.
+pub fn write_flamegraph<W: Write>(
+    before: Option<&ProfFile>,
+    after: &ProfFile,
+    opts: &FlameGraphOptions,
+    w: &mut W,
+) {
+    let mut root = Frame {
+        name: "all".to_owned(),
+        ..Default::default()
+    };
+    if let Some(before) = before {
+        root.add_profile(&before.profile, &before.cost_centre_map(), false);
+    }
+    root.add_profile(&after.profile, &after.cost_centre_map(), true);
+    root.compute_totals(opts.metric);
+
+    let scale = if root.total == 0 {
+        0.0
+    } else {
+        (IMAGE_WIDTH - 2.0 * X_PAD) / (root.total as f64)
+    };
+    let min_total = if scale == 0.0 {
+        f64::INFINITY
+    } else {
+        MIN_WIDTH / scale
+    };
+    let depth = root.max_depth(min_total) + 1;
+    let image_height = TOP_PAD + (depth as f64) * FRAME_HEIGHT + BOTTOM_PAD;
+
+    let renderer = Renderer {
+        opts,
+        diff: before.is_some(),
+        scale,
+        min_total,
+        image_height,
+        root_total: root.total,
+        max_delta: root.max_delta(opts.metric),
+    };
+
+    writeln!(
+        w,
+        r#"<?xml version="1.0" standalone="no"?>
+<svg version="1.1" width="{width}" height="{height}" onload="init(evt)" viewBox="0 0 {width} {height}" xmlns="http://www.w3.org/2000/svg">
+<style type="text/css">
+  text {{ font-family: monospace; font-size: {font}px; fill: rgb(0,0,0); }}
+  .frame:hover {{ stroke: black; stroke-width: 0.5; cursor: pointer; }}
+  #search {{ opacity: 0.5; cursor: pointer; }}
+  #search:hover {{ opacity: 1; }}
+</style>
+<script type="text/ecmascript"><![CDATA[{script}]]></script>
+<rect x="0" y="0" width="{width}" height="{height}" fill="rgb(245,245,245)"/>
+<text x="{title_x}" y="{title_y}" text-anchor="middle" style="font-size: {title_font}px">{title}</text>
+<text id="search" x="{search_x}" y="{title_y}" text-anchor="end" onclick="search_prompt()">Search</text>
+<text id="matched" x="{search_x}" y="{details_y}" text-anchor="end"> </text>
+<text id="details" x="{x_pad}" y="{details_y}"> </text>"#,
+        width = IMAGE_WIDTH,
+        height = image_height,
+        font = FONT_SIZE,
+        script = SCRIPT,
+        title_x = IMAGE_WIDTH / 2.0,
+        title_y = FONT_SIZE * 2.0,
+        title_font = FONT_SIZE + 5.0,
+        title = escape(&opts.title),
+        search_x = IMAGE_WIDTH - X_PAD,
+        x_pad = X_PAD,
+        details_y = image_height - FONT_SIZE,
+    )
+    .unwrap();
+
+    renderer.render(&root, X_PAD, 0, w);
+
+    writeln!(w, "</svg>").unwrap();
+}
+
+struct Renderer<'a> {
+    opts: &'a FlameGraphOptions,
+    diff: bool,
+    scale: f64,
+    min_total: f64,
+    image_height: f64,
+    root_total: u64,
+    max_delta: u64,
+}
+
+impl<'a> Renderer<'a> {
+    fn render<W: Write>(&self, frame: &Frame, x: f64, depth: usize, w: &mut W) {
+        let width = (frame.total as f64) * self.scale;
+        let y = if self.opts.icicle {
+            TOP_PAD + (depth as f64) * FRAME_HEIGHT
+        } else {
+            self.image_height - BOTTOM_PAD - ((depth + 1) as f64) * FRAME_HEIGHT
+        };
+
+        let info = escape(&self.frame_info(frame));
+
+        writeln!(
+            w,
+            r#"<g class="frame" data-name="{name}" onmouseover="s(this)" onmouseout="c()"><title>{info}</title><rect x="{x:.1}" y="{y:.1}" width="{width:.1}" height="{height:.1}" rx="2" ry="2" fill="{fill}"/><text x="{text_x:.1}" y="{text_y:.1}">{text}</text></g>"#,
+            name = escape(&frame.name),
+            info = info,
+            x = x,
+            y = y,
+            width = width,
+            height = FRAME_HEIGHT - 1.0,
+            fill = self.frame_colour(frame),
+            text_x = x + 3.0,
+            text_y = y + FRAME_HEIGHT - 4.5,
+            text = escape(&fit_text(&frame.name, width)),
+        )
+        .unwrap();
+
+        let mut child_x = x;
+        for child in &frame.children {
+            if child.total as f64 >= self.min_total {
+                self.render(child, child_x, depth + 1, w);
+            }
+            child_x += (child.total as f64) * self.scale;
+        }
+    }
+
+    fn frame_info(&self, frame: &Frame) -> String {
+        let metric = self.opts.metric;
+        let percentage = percentage(frame.total, self.root_total);
+        if self.diff {
+            format!(
+                "{}: {} {} ({:.2}%), own entries {} -> {}, alloc {} -> {}, ticks {} -> {}",
+                frame.name,
+                metric,
+                frame.total,
+                percentage,
+                frame.before.entries,
+                frame.after.entries,
+                frame.before.alloc,
+                frame.after.alloc,
+                frame.before.ticks,
+                frame.after.ticks,
+            )
+        } else {
+            format!(
+                "{}: {} {} ({:.2}%), own entries {}, alloc {}, ticks {}",
+                frame.name,
+                metric,
+                frame.total,
+                percentage,
+                frame.after.entries,
+                frame.after.alloc,
+                frame.after.ticks,
+            )
+        }
+    }
+
+    fn frame_colour(&self, frame: &Frame) -> String {
+        if self.diff {
+            let delta = delta(
+                frame.before.metric(self.opts.metric),
+                frame.after.metric(self.opts.metric),
+            );
+            if delta == 0 || self.max_delta == 0 {
+                return "rgb(250,250,250)".to_owned();
+            }
+            let c = 220 - ((delta.unsigned_abs() as f64) / (self.max_delta as f64) * 190.0) as u64;
+            if delta > 0 {
+                format!("rgb(255,{},{})", c, c)
+            } else {
+                format!("rgb({},{},255)", c, c)
+            }
+        } else {
+            // Same module, same colour
+            let hash = fnv1a(frame.module.as_bytes());
+            let v1 = (hash & 0xFF) as f64 / 255.0;
+            let v2 = ((hash >> 8) & 0xFF) as f64 / 255.0;
+            let v3 = ((hash >> 16) & 0xFF) as f64 / 255.0;
+            format!(
+                "rgb({},{},{})",
+                205 + (50.0 * v1) as u64,
+                (230.0 * v2) as u64,
+                (55.0 * v3) as u64
+            )
+        }
+    }
+}
+
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100_0000_01b3);
+    }
+    hash
+}
+
+/// Truncates `text` to fit in a frame of the given width
+fn fit_text(text: &str, width: f64) -> String {
+    let max_chars = ((width - 6.0) / (FONT_SIZE * FONT_WIDTH)) as usize;
+    let n_chars = text.chars().count();
+    if n_chars <= max_chars {
+        text.to_owned()
+    } else if max_chars < 3 {
+        String::new()
+    } else {
+        let mut ret: String = text.chars().take(max_chars - 2).collect();
+        ret.push_str("..");
+        ret
+    }
+}
+
+static SCRIPT: &str = r#"
+var details, matched, searching;
+function init(evt) {
+    details = document.getElementById("details").firstChild;
+    matched = document.getElementById("matched").firstChild;
+}
+function s(g) { details.nodeValue = g.getElementsByTagName("title")[0].textContent; }
+function c() { details.nodeValue = " "; }
+function search_prompt() {
+    if (searching) {
+        reset_search();
+        return;
+    }
+    var term = prompt("Search for (regex):", "");
+    if (term != null && term != "") {
+        search(term);
+    }
+}
+function reset_search() {
+    var frames = document.getElementsByClassName("frame");
+    for (var i = 0; i < frames.length; i++) {
+        var rect = frames[i].getElementsByTagName("rect")[0];
+        if (rect.hasAttribute("data-fill")) {
+            rect.setAttribute("fill", rect.getAttribute("data-fill"));
+            rect.removeAttribute("data-fill");
+        }
+    }
+    matched.nodeValue = " ";
+    document.getElementById("search").firstChild.nodeValue = "Search";
+    searching = false;
+}
+function search(term) {
+    var re = new RegExp(term);
+    var frames = document.getElementsByClassName("frame");
+    var intervals = [];
+    var total = 0;
+    for (var i = 0; i < frames.length; i++) {
+        var rect = frames[i].getElementsByTagName("rect")[0];
+        var x = parseFloat(rect.getAttribute("x"));
+        var w = parseFloat(rect.getAttribute("width"));
+        total = Math.max(total, w);
+        if (re.test(frames[i].getAttribute("data-name"))) {
+            rect.setAttribute("data-fill", rect.getAttribute("fill"));
+            rect.setAttribute("fill", "rgb(230,0,230)");
+            intervals.push([x, x + w]);
+        }
+    }
+    // Nested frames are covered by their parents, merge the intervals to avoid counting them
+    // twice
+    intervals.sort(function(a, b) { return a[0] - b[0]; });
+    var covered = 0, end = -1;
+    for (var i = 0; i < intervals.length; i++) {
+        var start = Math.max(intervals[i][0], end);
+        if (intervals[i][1] > start) {
+            covered += intervals[i][1] - start;
+            end = intervals[i][1];
+        }
+    }
+    matched.nodeValue = "Matched: " + (total == 0 ? 0 : covered / total * 100).toFixed(2) + "%";
+    document.getElementById("search").firstChild.nodeValue = "Reset search";
+    searching = true;
+}
+"#;
+
+#[test]
+fn flamegraph_test() {
+    let f: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let opts = FlameGraphOptions {
+        metric: Metric::Alloc,
+        icicle: false,
+        title: "<test>".to_owned(),
+    };
+
+    let mut out = vec![];
+    write_flamegraph(None, &f, &opts, &mut out);
+    let svg = String::from_utf8(out).unwrap();
+
+    assert!(svg.contains("&lt;test&gt;"));
+    // all, MAIN.MAIN, Main.main, Main.f, Data.Map.insert. Main.CAF has no allocation.
+    assert_eq!(svg.matches(r#"<g class="frame""#).count(), 5);
+    assert!(svg.contains(
+        "<title>Main.main: alloc 900 (90.00%), own entries 1, alloc 200, ticks 20</title>"
+    ));
+
+    let mut out = vec![];
+    write_flamegraph(Some(&f), &f, &opts, &mut out);
+    let svg = String::from_utf8(out).unwrap();
+    assert!(svg.contains("own entries 1 -&gt; 1, alloc 200 -&gt; 200, ticks 20 -&gt; 20"));
+
+    // No allocation at all
+    fn clear_alloc(profile: &mut super::Profile) {
+        profile.alloc = 0;
+        profile.children.iter_mut().for_each(clear_alloc);
+    }
+    let mut f = f;
+    clear_alloc(&mut f.profile);
+    let mut out = vec![];
+    write_flamegraph(None, &f, &opts, &mut out);
+    let svg = String::from_utf8(out).unwrap();
+    assert!(svg.contains("(0.00%)"));
+    assert!(!svg.contains("NaN"));
+}
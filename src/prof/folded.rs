@@ -0,0 +1,107 @@
+//! Brendan Gregg's folded stack format, as consumed by `flamegraph.pl` and friends. Each line is a
+//! `;`-separated stack followed by a weight:
+//!
+//! ```text
+//! MAIN.MAIN;Main.main;Main.f 300
+//! ```
+//!
+//! The differential format (as generated by `difffolded.pl`) has two weights per line: one for the
+//! "before" profile and one for the "after" profile.
+
+use super::{CostCentre, Metric, ProfFile, Profile};
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+/// Maps cost-centre stacks to their costs. Stacks with zero cost are not included.
+pub fn fold_stacks(f: &ProfFile, metric: Metric) -> BTreeMap<String, u64> {
+    let cc_map = f.cost_centre_map();
+    let mut stacks = BTreeMap::new();
+    let mut stack = String::new();
+    fold_profile(&f.profile, metric, &cc_map, &mut stack, &mut stacks);
+    stacks
+}
+
+fn fold_profile(
+    p: &Profile,
+    metric: Metric,
+    cc_map: &HashMap<u64, &CostCentre>,
+    stack: &mut String,
+    stacks: &mut BTreeMap<String, u64>,
+) {
+    let stack_len = stack.len();
+    if !stack.is_empty() {
+        stack.push(';');
+    }
+    let cc = cc_map.get(&p.id).unwrap();
+    stack.push_str(&cc.module);
+    stack.push('.');
+    stack.push_str(&cc.label);
+
+    let cost = p.metric(metric);
+    if cost != 0 {
+        *stacks.entry(stack.clone()).or_insert(0) += cost;
+    }
+
+    for child in &p.children {
+        fold_profile(child, metric, cc_map, stack, stacks);
+    }
+
+    stack.truncate(stack_len);
+}
+
+pub fn write_folded<W: Write>(stacks: &BTreeMap<String, u64>, w: &mut W) {
+    for (stack, cost) in stacks {
+        writeln!(w, "{} {}", stack, cost).unwrap();
+    }
+}
+
+pub fn write_diff_folded<W: Write>(
+    stacks1: &BTreeMap<String, u64>,
+    stacks2: &BTreeMap<String, u64>,
+    w: &mut W,
+) {
+    let mut all_stacks: BTreeMap<&str, (u64, u64)> = BTreeMap::new();
+    for (stack, cost) in stacks1 {
+        all_stacks.entry(stack).or_insert((0, 0)).0 = *cost;
+    }
+    for (stack, cost) in stacks2 {
+        all_stacks.entry(stack).or_insert((0, 0)).1 = *cost;
+    }
+
+    for (stack, (cost1, cost2)) in all_stacks {
+        writeln!(w, "{} {} {}", stack, cost1, cost2).unwrap();
+    }
+}
+
+#[test]
+fn folded_test() {
+    let f: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+
+    let mut out = vec![];
+    write_folded(&fold_stacks(&f, Metric::Alloc), &mut out);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "MAIN.MAIN 100\n\
+         MAIN.MAIN;Main.main 200\n\
+         MAIN.MAIN;Main.main;Data.Map.insert 400\n\
+         MAIN.MAIN;Main.main;Main.f 300\n"
+    );
+}
+
+#[test]
+fn diff_folded_test() {
+    let mut stacks1 = BTreeMap::new();
+    stacks1.insert("A;B".to_owned(), 10);
+    stacks1.insert("A;C".to_owned(), 20);
+    let mut stacks2 = BTreeMap::new();
+    stacks2.insert("A;B".to_owned(), 15);
+    stacks2.insert("A;D".to_owned(), 5);
+
+    let mut out = vec![];
+    write_diff_folded(&stacks1, &stacks2, &mut out);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "A;B 10 15\nA;C 20 0\nA;D 0 5\n"
+    );
+}
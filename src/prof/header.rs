@@ -0,0 +1,181 @@
+//! Profile metadata: program, arguments, RTS flags and totals
+
+use super::ProfFile;
+use crate::utils::change;
+
+use std::io::Write;
+
+pub fn write_header<W: Write>(f: &ProfFile, w: &mut W) {
+    writeln!(w, "program:              {}", f.program).unwrap();
+    writeln!(w, "arguments:            {}", f.arguments.join(" ")).unwrap();
+    writeln!(w, "rts_arguments:        {}", f.rts_arguments.join(" ")).unwrap();
+    writeln!(w, "end_time:             {}", f.end_time).unwrap();
+    writeln!(w, "initial_capabilities: {}", f.initial_capabilities).unwrap();
+    writeln!(w, "tick_interval:        {} us", f.tick_interval).unwrap();
+    writeln!(w, "total_time:           {:.2} s", f.total_time).unwrap();
+    writeln!(w, "total_ticks:          {}", f.total_ticks).unwrap();
+    writeln!(w, "total_alloc:          {} bytes", f.total_alloc).unwrap();
+}
+
+pub fn write_header_diff<W: Write>(f1: &ProfFile, f2: &ProfFile, w: &mut W) {
+    let same_or_both = |s1: String, s2: String| {
+        if s1 == s2 {
+            s1
+        } else {
+            format!("{} | {}", s1, s2)
+        }
+    };
+
+    writeln!(
+        w,
+        "program:              {}",
+        same_or_both(f1.program.clone(), f2.program.clone())
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "arguments:            {}",
+        same_or_both(f1.arguments.join(" "), f2.arguments.join(" "))
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "rts_arguments:        {}",
+        same_or_both(f1.rts_arguments.join(" "), f2.rts_arguments.join(" "))
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "end_time:             {}",
+        same_or_both(f1.end_time.clone(), f2.end_time.clone())
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "initial_capabilities: {}",
+        same_or_both(
+            f1.initial_capabilities.to_string(),
+            f2.initial_capabilities.to_string()
+        )
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "tick_interval:        {} us",
+        same_or_both(f1.tick_interval.to_string(), f2.tick_interval.to_string())
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "total_time:           {:.2} -> {:.2} s ({})",
+        f1.total_time,
+        f2.total_time,
+        change(f1.total_time, f2.total_time)
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "total_ticks:          {}",
+        diff_str(f1.total_ticks, f2.total_ticks)
+    )
+    .unwrap();
+    writeln!(
+        w,
+        "total_alloc:          {} bytes",
+        diff_str(f1.total_alloc, f2.total_alloc)
+    )
+    .unwrap();
+
+    let warnings = header_warnings(f1, f2);
+    if !warnings.is_empty() {
+        writeln!(w).unwrap();
+        for warning in warnings {
+            writeln!(w, "WARNING: {}", warning).unwrap();
+        }
+    }
+}
+
+fn diff_str(v1: u64, v2: u64) -> String {
+    let diff = (v2 as i64) - (v1 as i64);
+    format!(
+        "{} -> {} ({:+}, {})",
+        v1,
+        v2,
+        diff,
+        change(v1 as f64, v2 as f64)
+    )
+}
+
+/// Differences in the headers that make comparing the profiles questionable
+pub fn header_warnings(f1: &ProfFile, f2: &ProfFile) -> Vec<String> {
+    let mut warnings = vec![];
+
+    if f1.program != f2.program {
+        warnings.push(format!(
+            "Profiles are of different programs: {} and {}",
+            f1.program, f2.program
+        ));
+    }
+    if f1.arguments != f2.arguments {
+        warnings.push(format!(
+            "Programs were run with different arguments: `{}` and `{}`",
+            f1.arguments.join(" "),
+            f2.arguments.join(" ")
+        ));
+    }
+    if f1.rts_arguments != f2.rts_arguments {
+        warnings.push(format!(
+            "Programs were run with different RTS arguments: `{}` and `{}`",
+            f1.rts_arguments.join(" "),
+            f2.rts_arguments.join(" ")
+        ));
+    }
+    if f1.tick_interval != f2.tick_interval {
+        warnings.push(format!(
+            "Tick intervals are different: {} us and {} us, tick counts are not comparable",
+            f1.tick_interval, f2.tick_interval
+        ));
+    }
+    if f1.initial_capabilities != f2.initial_capabilities {
+        warnings.push(format!(
+            "Programs were run with different number of capabilities: {} and {}",
+            f1.initial_capabilities, f2.initial_capabilities
+        ));
+    }
+
+    warnings
+}
+
+#[test]
+fn header_warnings_test() {
+    let f1: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let mut f2: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    assert!(header_warnings(&f1, &f2).is_empty());
+
+    f2.tick_interval = 10000;
+    f2.rts_arguments.push("-N4".to_owned());
+    let warnings = header_warnings(&f1, &f2);
+    assert_eq!(warnings.len(), 2);
+    assert_eq!(
+        warnings[0],
+        "Programs were run with different RTS arguments: `-pj` and `-pj -N4`"
+    );
+
+    let mut out = vec![];
+    write_header_diff(&f1, &f2, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("tick_interval:        1000 | 10000 us\n"));
+    assert!(out.contains("total_alloc:          1000 -> 1000 (+0, +0.00%) bytes\n"));
+
+    // Zero baselines
+    let mut f1 = f1;
+    f1.total_time = 0.0;
+    f1.total_ticks = 0;
+    f2.total_time = 0.0;
+    let mut out = vec![];
+    write_header_diff(&f1, &f2, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("total_time:           0.00 -> 0.00 s (+0.00%)\n"));
+    assert!(out.contains("total_ticks:          0 -> 100 (+100, new)\n"));
+    assert!(!out.contains("NaN") && !out.contains("inf"));
+}
@@ -0,0 +1,495 @@
+//! Renders a profile, or a comparison of two profiles, as a single static HTML page for sharing.
+//! The page has no external assets and contains the header, the biggest cost centres (or the
+//! biggest changes when comparing), a sortable table of cost centres, and an expandable
+//! cost-centre tree. When comparing, increases are shown in red and decreases in green.
+
+use super::{
+    cost_map, header_warnings, metric_total, CostCentre, Costs, Metric, ProfFile, Profile,
+};
+use crate::utils::{change, delta, percentage};
+use crate::xml::escape;
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+
+/// Number of rows in the top cost centres/biggest movers tables
+const N_MOVERS: usize = 10;
+
+const METRICS: [Metric; 3] = [Metric::Entries, Metric::Alloc, Metric::Ticks];
+
+/// A cost-centre stack in one or two profiles
+#[derive(Debug, Default)]
+struct Node {
+    /// Inherited costs in the first profile. Only used when comparing.
+    before: Costs,
+    /// Inherited costs in the second profile, or in the only profile when not comparing.
+    after: Costs,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    /// Adds a profile tree to the stack
+    fn add_profile(&mut self, p: &Profile, cc_map: &HashMap<u64, &CostCentre>, after: bool) {
+        let costs = Costs {
+            entries: p.entries,
+            alloc: p.inherited(Metric::Alloc),
+            ticks: p.inherited(Metric::Ticks),
+        };
+        if after {
+            self.after.add(costs);
+        } else {
+            self.before.add(costs);
+        }
+        for child in &p.children {
+            let name = cc_map.get(&child.id).unwrap().name();
+            self.children
+                .entry(name)
+                .or_default()
+                .add_profile(child, cc_map, after);
+        }
+    }
+}
+
+/// A row of the flat table: own costs of a cost centre, summed over stacks
+struct Row<'a> {
+    name: String,
+    cc: &'a CostCentre,
+    before: Costs,
+    after: Costs,
+}
+
+/// Renders a report of `after`. When `before` is available the report shows changes from `before`
+/// to `after`.
+pub fn write_html_report<W: Write>(
+    before: Option<&ProfFile>,
+    after: &ProfFile,
+    title: &str,
+    w: &mut W,
+) {
+    let diff = before.is_some();
+
+    writeln!(
+        w,
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>{title}</title>
+<style>{style}</style>
+<script>{script}</script>
+</head>
+<body>
+<h1>{title}</h1>"#,
+        title = escape(title),
+        style = STYLE,
+        script = SCRIPT,
+    )
+    .unwrap();
+
+    write_header_table(before, after, w);
+
+    let rows = flat_rows(before, after);
+    writeln!(
+        w,
+        "<h2>{}</h2>",
+        if diff {
+            "Biggest movers"
+        } else {
+            "Top cost centres"
+        }
+    )
+    .unwrap();
+    for metric in &[Metric::Alloc, Metric::Ticks] {
+        write_movers(&rows, *metric, diff, after, w);
+    }
+
+    writeln!(w, "<h2>Cost centres</h2>").unwrap();
+    writeln!(
+        w,
+        "<p>Own costs, summed over stacks. Click a column to sort.</p>"
+    )
+    .unwrap();
+    write_flat_table(&rows, diff, after, w);
+
+    writeln!(w, "<h2>Cost-centre tree</h2>").unwrap();
+    writeln!(w, "<p>Inherited costs. Click a cost centre to expand.</p>").unwrap();
+    // Profiles are added under their roots. Roots of the two profiles should be the same, if not
+    // we show both under a synthetic root.
+    let mut top = Node::default();
+    for (f, is_after) in before
+        .iter()
+        .map(|f| (*f, false))
+        .chain(Some((after, true)))
+    {
+        let cc_map = f.cost_centre_map();
+        top.children
+            .entry(cc_map.get(&f.profile.id).unwrap().name())
+            .or_default()
+            .add_profile(&f.profile, &cc_map, is_after);
+    }
+    let mut costs = (Costs::default(), Costs::default());
+    for child in top.children.values() {
+        costs.0.add(child.before);
+        costs.1.add(child.after);
+    }
+    top.before = costs.0;
+    top.after = costs.1;
+    let (root_name, root) = if top.children.len() == 1 {
+        let (name, root) = top.children.iter().next().unwrap();
+        (name.as_str(), root)
+    } else {
+        ("all", &top)
+    };
+    writeln!(w, r#"<ul class="tree">"#).unwrap();
+    write_tree(root_name, root, diff, after, true, w);
+    writeln!(w, "</ul>").unwrap();
+
+    writeln!(w, "</body>\n</html>").unwrap();
+}
+
+fn write_header_table<W: Write>(before: Option<&ProfFile>, after: &ProfFile, w: &mut W) {
+    let fields = |f: &ProfFile| -> Vec<(&'static str, String)> {
+        vec![
+            ("program", f.program.clone()),
+            ("arguments", f.arguments.join(" ")),
+            ("rts_arguments", f.rts_arguments.join(" ")),
+            ("end_time", f.end_time.clone()),
+            ("initial_capabilities", f.initial_capabilities.to_string()),
+            ("tick_interval", format!("{} us", f.tick_interval)),
+            ("total_time", format!("{:.2} s", f.total_time)),
+            ("total_ticks", f.total_ticks.to_string()),
+            ("total_alloc", format!("{} bytes", f.total_alloc)),
+        ]
+    };
+
+    writeln!(w, r#"<table class="header">"#).unwrap();
+    match before {
+        None => {
+            for (field, value) in fields(after) {
+                writeln!(w, "<tr><th>{}</th><td>{}</td></tr>", field, escape(&value)).unwrap();
+            }
+        }
+        Some(before) => {
+            writeln!(w, "<tr><th></th><th>before</th><th>after</th></tr>").unwrap();
+            for ((field, value1), (_, value2)) in fields(before).into_iter().zip(fields(after)) {
+                let class = if value1 == value2 {
+                    ""
+                } else {
+                    " class=\"changed\""
+                };
+                writeln!(
+                    w,
+                    "<tr{}><th>{}</th><td>{}</td><td>{}</td></tr>",
+                    class,
+                    field,
+                    escape(&value1),
+                    escape(&value2)
+                )
+                .unwrap();
+            }
+        }
+    }
+    writeln!(w, "</table>").unwrap();
+
+    if let Some(before) = before {
+        let warnings = header_warnings(before, after);
+        if !warnings.is_empty() {
+            writeln!(w, r#"<ul class="warnings">"#).unwrap();
+            for warning in warnings {
+                writeln!(w, "<li>WARNING: {}</li>", escape(&warning)).unwrap();
+            }
+            writeln!(w, "</ul>").unwrap();
+        }
+    }
+}
+
+fn flat_rows<'a>(before: Option<&'a ProfFile>, after: &'a ProfFile) -> Vec<Row<'a>> {
+    let mut rows: BTreeMap<String, Row<'a>> = BTreeMap::new();
+    let mut add = |f: &'a ProfFile, after: bool| {
+        let ccs = f.cost_centres_by_name();
+        for metric in &METRICS {
+            for (name, cost) in cost_map(f, *metric) {
+                let row = rows.entry(name.clone()).or_insert_with(|| Row {
+                    name: name.clone(),
+                    cc: ccs.get(&name).unwrap(),
+                    before: Costs::default(),
+                    after: Costs::default(),
+                });
+                let costs = if after {
+                    &mut row.after
+                } else {
+                    &mut row.before
+                };
+                match metric {
+                    Metric::Entries => costs.entries = cost,
+                    Metric::Alloc => costs.alloc = cost,
+                    Metric::Ticks => costs.ticks = cost,
+                }
+            }
+        }
+    };
+    if let Some(before) = before {
+        add(before, false);
+    }
+    add(after, true);
+    rows.into_values().collect()
+}
+
+/// A table cell with a value to sort by
+fn cell(value: impl std::fmt::Display, text: &str, class: &str) -> String {
+    if class.is_empty() {
+        format!(r#"<td data-v="{}">{}</td>"#, value, text)
+    } else {
+        format!(r#"<td data-v="{}" class="{}">{}</td>"#, value, class, text)
+    }
+}
+
+/// A table cell showing a change. Increases are red, decreases green.
+fn diff_cell(before: u64, after: u64) -> String {
+    let d = delta(before, after);
+    let text = format!("{:+} ({})", d, change(before as f64, after as f64));
+    cell(d, &text, diff_class(before, after))
+}
+
+fn write_movers<W: Write>(rows: &[Row], metric: Metric, diff: bool, after: &ProfFile, w: &mut W) {
+    let mut rows: Vec<&Row> = rows.iter().collect();
+    if diff {
+        rows.retain(|row| row.before.metric(metric) != row.after.metric(metric));
+        rows.sort_by_key(|row| {
+            std::cmp::Reverse(delta(row.before.metric(metric), row.after.metric(metric)).abs())
+        });
+    } else {
+        rows.retain(|row| row.after.metric(metric) != 0);
+        rows.sort_by_key(|row| std::cmp::Reverse(row.after.metric(metric)));
+    }
+    rows.truncate(N_MOVERS);
+
+    writeln!(w, "<h3>{}</h3>", metric).unwrap();
+    if rows.is_empty() {
+        writeln!(w, "<p>(none)</p>").unwrap();
+        return;
+    }
+
+    writeln!(w, r#"<table class="sortable">"#).unwrap();
+    if diff {
+        writeln!(
+            w,
+            "<tr><th>cost centre</th><th>before</th><th>after</th><th>diff</th></tr>"
+        )
+        .unwrap();
+    } else {
+        writeln!(
+            w,
+            "<tr><th>cost centre</th><th>{}</th><th>%</th></tr>",
+            metric
+        )
+        .unwrap();
+    }
+    let total = metric_total(after, metric);
+    for row in rows {
+        let (before, after) = (row.before.metric(metric), row.after.metric(metric));
+        write!(w, "<tr>{}", cell(escape(&row.name), &escape(&row.name), "")).unwrap();
+        if diff {
+            write!(
+                w,
+                "{}{}{}",
+                cell(before, &before.to_string(), ""),
+                cell(after, &after.to_string(), ""),
+                diff_cell(before, after)
+            )
+            .unwrap();
+        } else {
+            let p = percentage(after, total);
+            write!(
+                w,
+                "{}{}",
+                cell(after, &after.to_string(), ""),
+                cell(p, &format!("{:.2}", p), "")
+            )
+            .unwrap();
+        }
+        writeln!(w, "</tr>").unwrap();
+    }
+    writeln!(w, "</table>").unwrap();
+}
+
+fn write_flat_table<W: Write>(rows: &[Row], diff: bool, after: &ProfFile, w: &mut W) {
+    writeln!(w, r#"<table class="sortable">"#).unwrap();
+    write!(w, "<tr><th>cost centre</th><th>module</th><th>src</th>").unwrap();
+    for metric in &METRICS {
+        if diff {
+            write!(
+                w,
+                "<th>{m} before</th><th>{m} after</th><th>{m} diff</th>",
+                m = metric
+            )
+            .unwrap();
+        } else {
+            write!(w, "<th>{m}</th><th>{m} %</th>", m = metric).unwrap();
+        }
+    }
+    writeln!(w, "</tr>").unwrap();
+
+    for row in rows {
+        write!(
+            w,
+            "<tr>{}{}{}",
+            cell(escape(&row.name), &escape(&row.cc.label), ""),
+            cell(escape(&row.cc.module), &escape(&row.cc.module), ""),
+            cell(escape(&row.cc.src_loc), &escape(&row.cc.src_loc), ""),
+        )
+        .unwrap();
+        for metric in &METRICS {
+            let (before, after_cost) = (row.before.metric(*metric), row.after.metric(*metric));
+            if diff {
+                write!(
+                    w,
+                    "{}{}{}",
+                    cell(before, &before.to_string(), ""),
+                    cell(after_cost, &after_cost.to_string(), ""),
+                    diff_cell(before, after_cost)
+                )
+                .unwrap();
+            } else {
+                let p = percentage(after_cost, metric_total(after, *metric));
+                write!(
+                    w,
+                    "{}{}",
+                    cell(after_cost, &after_cost.to_string(), ""),
+                    cell(p, &format!("{:.2}", p), "")
+                )
+                .unwrap();
+            }
+        }
+        writeln!(w, "</tr>").unwrap();
+    }
+    writeln!(w, "</table>").unwrap();
+}
+
+fn write_tree<W: Write>(
+    name: &str,
+    node: &Node,
+    diff: bool,
+    after: &ProfFile,
+    open: bool,
+    w: &mut W,
+) {
+    let summary = if diff {
+        format!(
+            "<b>{}</b>: entries {} -> {}, alloc {} -> {} <span class=\"{}\">({:+})</span>, \
+             ticks {} -> {} <span class=\"{}\">({:+})</span>",
+            escape(name),
+            node.before.entries,
+            node.after.entries,
+            node.before.alloc,
+            node.after.alloc,
+            diff_class(node.before.alloc, node.after.alloc),
+            delta(node.before.alloc, node.after.alloc),
+            node.before.ticks,
+            node.after.ticks,
+            diff_class(node.before.ticks, node.after.ticks),
+            delta(node.before.ticks, node.after.ticks),
+        )
+    } else {
+        format!(
+            "<b>{}</b>: entries {}, alloc {} ({:.2}%), ticks {} ({:.2}%)",
+            escape(name),
+            node.after.entries,
+            node.after.alloc,
+            percentage(node.after.alloc, after.total_alloc),
+            node.after.ticks,
+            percentage(node.after.ticks, after.total_ticks),
+        )
+    };
+
+    if node.children.is_empty() {
+        writeln!(w, "<li>{}</li>", summary).unwrap();
+        return;
+    }
+
+    writeln!(
+        w,
+        "<li><details{}><summary>{}</summary><ul>",
+        if open { " open" } else { "" },
+        summary
+    )
+    .unwrap();
+    let mut children: Vec<(&String, &Node)> = node.children.iter().collect();
+    children.sort_by_key(|(_, child)| {
+        std::cmp::Reverse(std::cmp::max(child.before.alloc, child.after.alloc))
+    });
+    for (child_name, child) in children {
+        write_tree(child_name, child, diff, after, false, w);
+    }
+    writeln!(w, "</ul></details></li>").unwrap();
+}
+
+fn diff_class(before: u64, after: u64) -> &'static str {
+    match after.cmp(&before) {
+        std::cmp::Ordering::Greater => "inc",
+        std::cmp::Ordering::Less => "dec",
+        std::cmp::Ordering::Equal => "",
+    }
+}
+
+static STYLE: &str = r#"
+body { font-family: sans-serif; font-size: 14px; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 2px 6px; text-align: left; }
+table.sortable th { cursor: pointer; background: #eee; }
+table.sortable td { font-family: monospace; }
+tr.changed td { background: #ffe; }
+.inc { color: #c00; }
+.dec { color: #080; }
+.warnings { color: #c00; }
+ul.tree, ul.tree ul { list-style: none; padding-left: 1.5em; font-family: monospace; }
+summary { cursor: pointer; }
+"#;
+
+static SCRIPT: &str = r#"
+function sortTable(th) {
+    var table = th.closest("table");
+    var col = Array.prototype.indexOf.call(th.parentNode.children, th);
+    var rows = Array.prototype.slice.call(table.rows, 1);
+    var desc = th.getAttribute("data-desc") != "true";
+    th.setAttribute("data-desc", desc);
+    rows.sort(function(a, b) {
+        var x = a.cells[col].getAttribute("data-v"), y = b.cells[col].getAttribute("data-v");
+        var nx = parseFloat(x), ny = parseFloat(y);
+        var cmp = (isNaN(nx) || isNaN(ny)) ? x.localeCompare(y) : nx - ny;
+        return desc ? -cmp : cmp;
+    });
+    for (var i = 0; i < rows.length; i++) {
+        table.tBodies[0].appendChild(rows[i]);
+    }
+}
+document.addEventListener("DOMContentLoaded", function() {
+    var ths = document.querySelectorAll("table.sortable th");
+    for (var i = 0; i < ths.length; i++) {
+        ths[i].addEventListener("click", function() { sortTable(this); });
+    }
+});
+"#;
+
+#[test]
+fn html_report_test() {
+    let f1: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let mut f2: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    f2.profile.children[0].children[0].alloc = 200;
+    f2.profile.children[0].children[1].alloc = 500;
+
+    let mut out = vec![];
+    write_html_report(None, &f1, "<test>", &mut out);
+    let html = String::from_utf8(out).unwrap();
+    assert!(html.contains("<h1>&lt;test&gt;</h1>"));
+    assert!(html.contains("<h2>Top cost centres</h2>"));
+    assert!(html.contains("<b>Main.main</b>: entries 1, alloc 900 (90.00%), ticks 90 (90.00%)"));
+
+    let mut out = vec![];
+    write_html_report(Some(&f1), &f2, "test", &mut out);
+    let html = String::from_utf8(out).unwrap();
+    assert!(html.contains("<h2>Biggest movers</h2>"));
+    assert!(html.contains(r#"<td data-v="100" class="inc">+100 (+25.00%)</td>"#));
+    assert!(html.contains(r#"<td data-v="-100" class="dec">-100 (-33.33%)</td>"#));
+    assert!(html.contains("alloc 900 -> 900 <span class=\"\">(+0)</span>"));
+}
@@ -0,0 +1,404 @@
+//! Matching cost centres of two profiles of different builds.
+//!
+//! Names of automatically generated cost centres change between builds: GHC uniques in labels
+//! (`CAF:lvl12_r1Xz`), renumbered bindings (`lvl12` becomes `lvl13`), and so on. Matching only by
+//! `module.label` reports these as removed/added pairs. After matching names exactly we try, in
+//! order:
+//!
+//! 1. Match labels after stripping GHC uniques, pairing by closest source location.
+//! 2. Match labels after also stripping numbers, pairing by closest source location.
+//! 3. Pair remaining CAFs in the same module by closest cost.
+//!
+//! Pairing by source location only pairs cost centres in the same file whose lines are at most
+//! `max_line_shift` apart. Cost centres without a line (e.g. CAFs of the `<entire-module>`) are
+//! left to step 3.
+
+use super::CostCentre;
+
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+use regex::Regex;
+
+lazy_static! {
+    static ref UNIQUE_RE: Regex = Regex::new(&format!("_{}$", UNIQUE)).unwrap();
+    static ref BARE_UNIQUE_RE: Regex = Regex::new(&format!("^{}$", UNIQUE)).unwrap();
+    static ref NUMBER_RE: Regex = Regex::new(r"\d+").unwrap();
+    static ref SRC_LOC_RE: Regex = Regex::new(r"^(?P<file>.*?):\(?(?P<line>\d+)[,:]").unwrap();
+}
+
+/// GHC uniques in names: `r` or `s` followed by a base-62 number with a digit, so that ordinary
+/// suffixes like `_result` or `_step` are not taken as uniques
+const UNIQUE: &str = "[rs][0-9A-Za-z]*[0-9][0-9A-Za-z]*";
+
+/// Strips a GHC unique suffix, e.g. `lvl1_r1aB` becomes `lvl1`
+pub(crate) fn strip_unique(name: &str) -> Cow<'_, str> {
+    UNIQUE_RE.replace(name, "")
+}
+
+/// Whether the word is a GHC unique without a name, e.g. `r1aB`
+pub(crate) fn is_unique(word: &str) -> bool {
+    BARE_UNIQUE_RE.is_match(word)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MatchKind {
+    /// Same `module.label`
+    Exact,
+    /// Same label after stripping uniques
+    Unique,
+    /// Same label after stripping uniques and numbers, paired by source location
+    SrcLoc,
+    /// CAFs in the same module, paired by cost
+    CafCost,
+}
+
+impl fmt::Display for MatchKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MatchKind::Exact => "exact",
+            MatchKind::Unique => "unique",
+            MatchKind::SrcLoc => "src_loc",
+            MatchKind::CafCost => "CAF cost",
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct CcMatch<'a> {
+    pub before: &'a CostCentre,
+    pub after: &'a CostCentre,
+    pub kind: MatchKind,
+}
+
+#[derive(Debug)]
+pub struct Matching<'a> {
+    pub matches: Vec<CcMatch<'a>>,
+    /// Cost centres only in the first profile
+    pub only_before: Vec<&'a CostCentre>,
+    /// Cost centres only in the second profile
+    pub only_after: Vec<&'a CostCentre>,
+}
+
+/// Matches cost centres of two profiles. Costs are used to pair CAFs. Cost centres should have
+/// distinct names (`module.label`). Renamed cost centres are only paired by source location when
+/// their lines are at most `max_line_shift` apart.
+pub fn match_cost_centres<'a>(
+    ccs1: &[(&'a CostCentre, u64)],
+    ccs2: &[(&'a CostCentre, u64)],
+    max_line_shift: u64,
+) -> Matching<'a> {
+    let mut matches = vec![];
+
+    let names2: HashMap<String, usize> = ccs2
+        .iter()
+        .enumerate()
+        .map(|(idx, (cc, _))| (cc.name(), idx))
+        .collect();
+
+    let mut left1: Vec<usize> = vec![];
+    let mut matched2 = vec![false; ccs2.len()];
+    for (idx1, (cc1, _)) in ccs1.iter().enumerate() {
+        match names2.get(&cc1.name()) {
+            None => left1.push(idx1),
+            Some(idx2) => {
+                matched2[*idx2] = true;
+                matches.push(CcMatch {
+                    before: cc1,
+                    after: ccs2[*idx2].0,
+                    kind: MatchKind::Exact,
+                });
+            }
+        }
+    }
+    let mut left2: Vec<usize> = (0..ccs2.len()).filter(|idx| !matched2[*idx]).collect();
+
+    let without_unique =
+        |cc: &CostCentre| (cc.module.clone(), strip_unique(&cc.label).into_owned());
+    match_groups(
+        ccs1,
+        ccs2,
+        &mut left1,
+        &mut left2,
+        without_unique,
+        MatchKind::Unique,
+        max_line_shift,
+        &mut matches,
+    );
+
+    let strip_numbers = |cc: &CostCentre| {
+        (
+            cc.module.clone(),
+            NUMBER_RE
+                .replace_all(&strip_unique(&cc.label), "")
+                .into_owned(),
+        )
+    };
+    match_groups(
+        ccs1,
+        ccs2,
+        &mut left1,
+        &mut left2,
+        strip_numbers,
+        MatchKind::SrcLoc,
+        max_line_shift,
+        &mut matches,
+    );
+
+    match_cafs(ccs1, ccs2, &mut left1, &mut left2, &mut matches);
+
+    Matching {
+        matches,
+        only_before: left1.into_iter().map(|idx| ccs1[idx].0).collect(),
+        only_after: left2.into_iter().map(|idx| ccs2[idx].0).collect(),
+    }
+}
+
+/// Groups unmatched cost centres by `key`, pairs cost centres in the same group by closest source
+/// location. Cost centres in different files, without lines, or more than `max_line_shift` lines
+/// apart are not paired.
+#[allow(clippy::too_many_arguments)]
+fn match_groups<'a, F: Fn(&CostCentre) -> (String, String)>(
+    ccs1: &[(&'a CostCentre, u64)],
+    ccs2: &[(&'a CostCentre, u64)],
+    left1: &mut Vec<usize>,
+    left2: &mut Vec<usize>,
+    key: F,
+    kind: MatchKind,
+    max_line_shift: u64,
+    matches: &mut Vec<CcMatch<'a>>,
+) {
+    let mut groups: BTreeMap<(String, String), (Vec<usize>, Vec<usize>)> = BTreeMap::new();
+    for idx in left1.iter() {
+        groups.entry(key(ccs1[*idx].0)).or_default().0.push(*idx);
+    }
+    for idx in left2.iter() {
+        groups.entry(key(ccs2[*idx].0)).or_default().1.push(*idx);
+    }
+
+    for (_, (idxs1, idxs2)) in groups {
+        let mut candidates: Vec<(u64, usize, usize)> = vec![];
+        for idx1 in &idxs1 {
+            for idx2 in &idxs2 {
+                match line_shift(&ccs1[*idx1].0.src_loc, &ccs2[*idx2].0.src_loc) {
+                    Some(shift) if shift <= max_line_shift => {
+                        candidates.push((shift, *idx1, *idx2))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        pair_greedy(candidates, ccs1, ccs2, left1, left2, kind, matches);
+    }
+}
+
+/// Pairs CAFs in the same module by closest cost.
+fn match_cafs<'a>(
+    ccs1: &[(&'a CostCentre, u64)],
+    ccs2: &[(&'a CostCentre, u64)],
+    left1: &mut Vec<usize>,
+    left2: &mut Vec<usize>,
+    matches: &mut Vec<CcMatch<'a>>,
+) {
+    let mut candidates: Vec<(u64, usize, usize)> = vec![];
+    for idx1 in left1.iter() {
+        let (cc1, cost1) = ccs1[*idx1];
+        if !cc1.is_caf {
+            continue;
+        }
+        for idx2 in left2.iter() {
+            let (cc2, cost2) = ccs2[*idx2];
+            if cc2.is_caf && cc1.module == cc2.module {
+                candidates.push((cost_distance(cost1, cost2), *idx1, *idx2));
+            }
+        }
+    }
+    pair_greedy(
+        candidates,
+        ccs1,
+        ccs2,
+        left1,
+        left2,
+        MatchKind::CafCost,
+        matches,
+    );
+}
+
+/// Pairs candidates with smallest distances first.
+fn pair_greedy<'a>(
+    mut candidates: Vec<(u64, usize, usize)>,
+    ccs1: &[(&'a CostCentre, u64)],
+    ccs2: &[(&'a CostCentre, u64)],
+    left1: &mut Vec<usize>,
+    left2: &mut Vec<usize>,
+    kind: MatchKind,
+    matches: &mut Vec<CcMatch<'a>>,
+) {
+    let mut free1: HashSet<usize> = left1.iter().copied().collect();
+    let mut free2: HashSet<usize> = left2.iter().copied().collect();
+
+    candidates.sort();
+    for (_, idx1, idx2) in candidates {
+        if free1.contains(&idx1) && free2.contains(&idx2) {
+            free1.remove(&idx1);
+            free2.remove(&idx2);
+            matches.push(CcMatch {
+                before: ccs1[idx1].0,
+                after: ccs2[idx2].0,
+                kind,
+            });
+        }
+    }
+
+    left1.retain(|idx| free1.contains(idx));
+    left2.retain(|idx| free2.contains(idx));
+}
+
+/// Splits a source location like `Main.hs:3:1-20` or `Main.hs:(3,1)-(5,20)` into file and line.
+fn parse_src_loc(src_loc: &str) -> (&str, Option<u64>) {
+    match SRC_LOC_RE.captures(src_loc) {
+        None => (src_loc, None),
+        Some(captures) => (
+            captures.name("file").unwrap().as_str(),
+            captures["line"].parse().ok(),
+        ),
+    }
+}
+
+/// Number of lines between two source locations, `None` if they are in different files or a line
+/// is not known
+fn line_shift(src_loc1: &str, src_loc2: &str) -> Option<u64> {
+    let (file1, line1) = parse_src_loc(src_loc1);
+    let (file2, line2) = parse_src_loc(src_loc2);
+    if file1 != file2 {
+        return None;
+    }
+    Some(line1?.abs_diff(line2?))
+}
+
+/// Distance between two costs, in permille of the larger one
+fn cost_distance(cost1: u64, cost2: u64) -> u64 {
+    let max = std::cmp::max(cost1, cost2);
+    if max == 0 {
+        return 0;
+    }
+    let min = std::cmp::min(cost1, cost2);
+    (max - min) * 1000 / max
+}
+
+#[test]
+fn match_test() {
+    let cc = |id, label: &str, src_loc: &str, is_caf| CostCentre {
+        id,
+        label: label.to_owned(),
+        module: "Main".to_owned(),
+        src_loc: src_loc.to_owned(),
+        is_caf,
+    };
+
+    let ccs1 = [
+        cc(1, "main", "Main.hs:3:1-20", false),
+        cc(2, "CAF:lvl1_r1aB", "Main.hs:10:1", true),
+        cc(3, "go", "Main.hs:20:5-10", false),
+        cc(4, "go_r2", "Main.hs:40:5-10", false),
+        cc(5, "CAF:x_rAb", "<entire-module>", true),
+        cc(6, "removed", "Main.hs:100:1", false),
+    ];
+    let ccs2 = [
+        cc(1, "main", "Main.hs:3:1-20", false),
+        cc(2, "CAF:lvl1_r9xY", "Main.hs:12:1", true),
+        cc(3, "go_s3", "Main.hs:(43,5)-(45,10)", false),
+        cc(4, "go_s4", "Main.hs:22:5-10", false),
+        cc(5, "CAF:y", "<entire-module>", true),
+        cc(6, "added", "Main.hs:100:1", false),
+    ];
+    let costs1 = [10, 10, 10, 10, 100, 10];
+    let costs2 = [10, 10, 10, 10, 90, 10];
+
+    let ccs1: Vec<(&CostCentre, u64)> = ccs1.iter().zip(costs1).collect();
+    let ccs2: Vec<(&CostCentre, u64)> = ccs2.iter().zip(costs2).collect();
+
+    let matching = match_cost_centres(&ccs1, &ccs2, 20);
+    let mut matches: Vec<(&str, &str, MatchKind)> = matching
+        .matches
+        .iter()
+        .map(|m| (m.before.label.as_str(), m.after.label.as_str(), m.kind))
+        .collect();
+    matches.sort();
+    assert_eq!(
+        matches,
+        vec![
+            ("CAF:lvl1_r1aB", "CAF:lvl1_r9xY", MatchKind::Unique),
+            ("CAF:x_rAb", "CAF:y", MatchKind::CafCost),
+            ("go", "go_s4", MatchKind::Unique),
+            ("go_r2", "go_s3", MatchKind::Unique),
+            ("main", "main", MatchKind::Exact),
+        ]
+    );
+    assert_eq!(matching.only_before[0].label, "removed");
+    assert_eq!(matching.only_after[0].label, "added");
+}
+
+#[test]
+fn strip_unique_test() {
+    assert_eq!(strip_unique("CAF:lvl1_r1aB"), "CAF:lvl1");
+    assert_eq!(strip_unique("go_s3"), "go");
+    assert_eq!(strip_unique("foo_result"), "foo_result");
+    assert_eq!(strip_unique("go_step"), "go_step");
+    assert!(is_unique("r1aB"));
+    assert!(!is_unique("rest"));
+    assert!(!is_unique("r1_s2"));
+}
+
+#[test]
+fn match_limits_test() {
+    let cc = |id, label: &str, src_loc: &str, is_caf| CostCentre {
+        id,
+        label: label.to_owned(),
+        module: "Main".to_owned(),
+        src_loc: src_loc.to_owned(),
+        is_caf,
+    };
+
+    // CAFs without lines have the same label after stripping numbers, but are paired by cost.
+    // `go1` and `go2` are too far apart to be paired.
+    let ccs1 = [
+        cc(1, "CAF:lvl12", "<entire-module>", true),
+        cc(2, "CAF:lvl13", "<entire-module>", true),
+        cc(3, "go1", "Main.hs:10:1", false),
+    ];
+    let ccs2 = [
+        cc(1, "CAF:lvl14", "<entire-module>", true),
+        cc(2, "CAF:lvl15", "<entire-module>", true),
+        cc(3, "go2", "Main.hs:200:1", false),
+    ];
+    let costs1 = [100, 500, 10];
+    let costs2 = [510, 95, 10];
+
+    let ccs1: Vec<(&CostCentre, u64)> = ccs1.iter().zip(costs1).collect();
+    let ccs2: Vec<(&CostCentre, u64)> = ccs2.iter().zip(costs2).collect();
+
+    let matching = match_cost_centres(&ccs1, &ccs2, 20);
+    let mut matches: Vec<(&str, &str, MatchKind)> = matching
+        .matches
+        .iter()
+        .map(|m| (m.before.label.as_str(), m.after.label.as_str(), m.kind))
+        .collect();
+    matches.sort();
+    assert_eq!(
+        matches,
+        vec![
+            ("CAF:lvl12", "CAF:lvl15", MatchKind::CafCost),
+            ("CAF:lvl13", "CAF:lvl14", MatchKind::CafCost),
+        ]
+    );
+    assert_eq!(matching.only_before[0].label, "go1");
+    assert_eq!(matching.only_after[0].label, "go2");
+
+    let matching = match_cost_centres(&ccs1, &ccs2, 200);
+    assert!(matching
+        .matches
+        .iter()
+        .any(|m| m.before.label == "go1" && m.kind == MatchKind::SrcLoc));
+}
@@ -0,0 +1,216 @@
+//! Merging profiles of multiple runs of a program. Tick counts in a single profile are noisy, so we
+//! merge cost-centre trees of several runs by stack and average (or sum) the costs.
+
+use super::{CostCentre, Metric, ProfFile, Profile};
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    Average,
+    Sum,
+}
+
+/// A node in the merged tree
+#[derive(Default)]
+struct Node {
+    entries: u64,
+    alloc: u64,
+    ticks: u64,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn add(&mut self, p: &Profile, cc_map: &HashMap<u64, &CostCentre>) {
+        self.entries += p.entries;
+        self.alloc += p.alloc;
+        self.ticks += p.ticks;
+        for child in &p.children {
+            let name = cc_map.get(&child.id).unwrap().name();
+            self.children.entry(name).or_default().add(child, cc_map);
+        }
+    }
+
+    fn into_profile(self, id: u64, ids: &HashMap<String, u64>, mode: MergeMode, n: u64) -> Profile {
+        let merge = |cost: u64| match mode {
+            MergeMode::Sum => cost,
+            // Round to nearest
+            MergeMode::Average => (cost + n / 2) / n,
+        };
+        Profile {
+            id,
+            entries: merge(self.entries),
+            alloc: merge(self.alloc),
+            ticks: merge(self.ticks),
+            children: self
+                .children
+                .into_iter()
+                .map(|(name, child)| child.into_profile(*ids.get(&name).unwrap(), ids, mode, n))
+                .collect(),
+        }
+    }
+}
+
+/// Merges profiles by cost-centre stacks. Cost centres are identified by name (`module.label`) and
+/// get new ids in the merged profile. Header fields other than the totals are taken from the first
+/// profile. Fails when the profiles have different root cost centres.
+pub fn merge_profiles(files: Vec<ProfFile>, mode: MergeMode) -> Result<ProfFile, String> {
+    assert!(!files.is_empty());
+    if files.len() == 1 {
+        return Ok(files.into_iter().next().unwrap());
+    }
+
+    let n = files.len() as u64;
+
+    // Assign new ids to cost centres
+    let mut ids: HashMap<String, u64> = HashMap::new();
+    let mut cost_centres: Vec<CostCentre> = vec![];
+    for f in &files {
+        for cc in &f.cost_centres {
+            if let Entry::Vacant(entry) = ids.entry(cc.name()) {
+                let id = (cost_centres.len() + 1) as u64;
+                entry.insert(id);
+                cost_centres.push(CostCentre { id, ..cc.clone() });
+            }
+        }
+    }
+
+    let root_name = {
+        let f = &files[0];
+        f.cost_centre_map().get(&f.profile.id).unwrap().name()
+    };
+    let mut root = Node::default();
+    let mut total_time = 0.0;
+    let mut total_ticks = 0;
+    let mut total_alloc = 0;
+    for f in &files {
+        let cc_map = f.cost_centre_map();
+        let name = cc_map.get(&f.profile.id).unwrap().name();
+        if name != root_name {
+            return Err(format!(
+                "Profiles have different roots: {} and {}",
+                root_name, name
+            ));
+        }
+        root.add(&f.profile, &cc_map);
+        total_time += f.total_time;
+        total_ticks += f.total_ticks;
+        total_alloc += f.total_alloc;
+    }
+
+    let (total_time, total_ticks, total_alloc) = match mode {
+        MergeMode::Sum => (total_time, total_ticks, total_alloc),
+        MergeMode::Average => (
+            total_time / (n as f64),
+            (total_ticks + n / 2) / n,
+            (total_alloc + n / 2) / n,
+        ),
+    };
+
+    let profile = root.into_profile(*ids.get(&root_name).unwrap(), &ids, mode, n);
+
+    let mut files = files.into_iter();
+    let first = files.next().unwrap();
+    Ok(ProfFile {
+        total_time,
+        total_ticks,
+        total_alloc,
+        cost_centres,
+        profile,
+        ..first
+    })
+}
+
+/// Mean and sample standard deviation of a cost over runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostStats {
+    pub mean: f64,
+    pub stddev: f64,
+    /// Number of runs
+    pub n: usize,
+}
+
+impl CostStats {
+    pub fn new(samples: &[u64]) -> CostStats {
+        let n = samples.len();
+        let mean = samples.iter().sum::<u64>() as f64 / (n as f64);
+        let stddev = if n < 2 {
+            0.0
+        } else {
+            let sum_sq: f64 = samples
+                .iter()
+                .map(|sample| (*sample as f64 - mean).powi(2))
+                .sum();
+            (sum_sq / ((n - 1) as f64)).sqrt()
+        };
+        CostStats { mean, stddev, n }
+    }
+
+    /// Standard error of the difference between the means of two sets of runs
+    pub fn noise(&self, other: &CostStats) -> f64 {
+        (self.stddev.powi(2) / (self.n as f64) + other.stddev.powi(2) / (other.n as f64)).sqrt()
+    }
+}
+
+/// Own costs of cost centres (summed over stacks) in each run, as mean and standard deviation.
+/// Cost centres missing in a run have zero cost in that run.
+pub fn cost_centre_stats(files: &[ProfFile], metric: Metric) -> HashMap<String, CostStats> {
+    let mut samples: HashMap<String, Vec<u64>> = HashMap::new();
+    for (run, f) in files.iter().enumerate() {
+        let cc_map = f.cost_centre_map();
+        add_samples(&f.profile, &cc_map, metric, run, files.len(), &mut samples);
+    }
+    samples
+        .into_iter()
+        .map(|(name, samples)| (name, CostStats::new(&samples)))
+        .collect()
+}
+
+fn add_samples(
+    p: &Profile,
+    cc_map: &HashMap<u64, &CostCentre>,
+    metric: Metric,
+    run: usize,
+    n_runs: usize,
+    samples: &mut HashMap<String, Vec<u64>>,
+) {
+    let name = cc_map.get(&p.id).unwrap().name();
+    samples.entry(name).or_insert_with(|| vec![0; n_runs])[run] += p.metric(metric);
+    for child in &p.children {
+        add_samples(child, cc_map, metric, run, n_runs, samples);
+    }
+}
+
+#[test]
+fn merge_test() {
+    let f1: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let mut f2: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    f2.total_alloc = 2000;
+    f2.profile.children[0].children[0].alloc = 500;
+    f2.profile.children.pop();
+
+    let stats = cost_centre_stats(&[f1, f2], Metric::Alloc);
+    assert_eq!(stats["Main.f"].mean, 400.0);
+    assert!((stats["Main.f"].stddev - 141.42).abs() < 0.01);
+    assert_eq!(stats["Main.main"].stddev, 0.0);
+
+    let f1: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let mut f2: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    f2.total_alloc = 2000;
+    f2.profile.children[0].children[0].alloc = 500;
+    let merged = merge_profiles(vec![f1, f2], MergeMode::Average).unwrap();
+    assert_eq!(merged.total_alloc, 1500);
+    assert_eq!(merged.profile.inherited(Metric::Alloc), 1100);
+    let cc_map = merged.cost_centre_map();
+    let main = &merged.profile.children[0];
+    assert_eq!(cc_map.get(&main.id).unwrap().name(), "Main.CAF");
+
+    let f1: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let mut f2: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    f2.profile.id = 2;
+    assert_eq!(
+        merge_profiles(vec![f1, f2], MergeMode::Sum).err().unwrap(),
+        "Profiles have different roots: MAIN.MAIN and Main.main"
+    );
+}
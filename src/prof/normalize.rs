@@ -0,0 +1,188 @@
+//! Comparing cost centres by their share of the total cost. When the workload size differs between
+//! two runs absolute differences are meaningless, but changes in shares still show which parts of
+//! the program got relatively more or less expensive.
+
+use super::{cost_map, Metric, ProfFile};
+use crate::utils::percentage;
+
+use std::io::Write;
+
+#[derive(Debug, PartialEq)]
+pub struct ShareDiff {
+    pub name: String,
+    pub before: u64,
+    pub after: u64,
+    /// Share of the total in the first profile, in percent
+    pub share_before: f64,
+    /// Share of the total in the second profile, in percent
+    pub share_after: f64,
+}
+
+impl ShareDiff {
+    /// Change in share, in percentage points
+    pub fn share_diff(&self) -> f64 {
+        self.share_after - self.share_before
+    }
+}
+
+/// Total cost of a metric. Allocation and tick totals are taken from the header, entries are
+/// summed over the cost-centre tree.
+pub fn metric_total(f: &ProfFile, metric: Metric) -> u64 {
+    match metric {
+        Metric::Entries => f.profile.inherited(Metric::Entries),
+        Metric::Alloc => f.total_alloc,
+        Metric::Ticks => f.total_ticks,
+    }
+}
+
+/// Changes in shares of cost centres, biggest (absolute) change in share first. Cost centres with
+/// no cost in either profile are not included.
+pub fn share_diffs(f1: &ProfFile, f2: &ProfFile, metric: Metric) -> Vec<ShareDiff> {
+    let total1 = metric_total(f1, metric);
+    let total2 = metric_total(f2, metric);
+
+    let costs1 = cost_map(f1, metric);
+    let mut costs2 = cost_map(f2, metric);
+
+    let mut diffs = vec![];
+    for (name, before) in costs1 {
+        let after = costs2.remove(&name).unwrap_or(0);
+        diffs.push(ShareDiff {
+            name,
+            before,
+            after,
+            share_before: percentage(before, total1),
+            share_after: percentage(after, total2),
+        });
+    }
+    for (name, after) in costs2 {
+        diffs.push(ShareDiff {
+            name,
+            before: 0,
+            after,
+            share_before: 0.0,
+            share_after: percentage(after, total2),
+        });
+    }
+
+    diffs.retain(|diff| diff.before != 0 || diff.after != 0);
+    diffs.sort_by(|d1, d2| {
+        d2.share_diff()
+            .abs()
+            .partial_cmp(&d1.share_diff().abs())
+            .unwrap()
+            .then_with(|| d1.name.cmp(&d2.name))
+    });
+    diffs
+}
+
+pub fn write_share_diffs<W: Write>(
+    f1: &ProfFile,
+    f2: &ProfFile,
+    metric: Metric,
+    diffs: &[ShareDiff],
+    other: &[ShareDiff],
+    w: &mut W,
+) {
+    let write_row = |w: &mut W, name: &str, diff: &ShareDiff| {
+        writeln!(
+            w,
+            "{}: {:.2}% -> {:.2}% ({:+.2} pp), {} {} -> {} ({:+})",
+            name,
+            diff.share_before,
+            diff.share_after,
+            diff.share_diff(),
+            metric,
+            diff.before,
+            diff.after,
+            (diff.after as i64) - (diff.before as i64),
+        )
+        .unwrap();
+    };
+    for diff in diffs {
+        write_row(w, &diff.name, diff);
+    }
+    if !other.is_empty() {
+        // Sum of the rows that are not shown
+        let sum = other.iter().fold(
+            ShareDiff {
+                name: String::new(),
+                before: 0,
+                after: 0,
+                share_before: 0.0,
+                share_after: 0.0,
+            },
+            |sum, diff| ShareDiff {
+                before: sum.before + diff.before,
+                after: sum.after + diff.after,
+                share_before: sum.share_before + diff.share_before,
+                share_after: sum.share_after + diff.share_after,
+                ..sum
+            },
+        );
+        write_row(w, &format!("OTHER ({} cost centres)", other.len()), &sum);
+    }
+
+    let total1 = metric_total(f1, metric);
+    let total2 = metric_total(f2, metric);
+    // Ratio of the totals. From zero the ratio is "1.00x" when both are zero, "new" otherwise.
+    let ratio = if total1 == 0 {
+        if total2 == 0 {
+            "1.00x".to_owned()
+        } else {
+            "new".to_owned()
+        }
+    } else {
+        format!("{:.2}x", (total2 as f64) / (total1 as f64))
+    };
+    writeln!(w).unwrap();
+    writeln!(
+        w,
+        "TOTAL: {} -> {} ({:+}, {})",
+        total1,
+        total2,
+        (total2 as i64) - (total1 as i64),
+        ratio
+    )
+    .unwrap();
+}
+
+#[test]
+fn share_diffs_test() {
+    let f1: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    let mut f2: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    // Twice the work, but `Main.f` allocates 4x
+    f2.total_alloc = 2600;
+    f2.profile.alloc = 200;
+    f2.profile.children[0].alloc = 400;
+    f2.profile.children[0].children[0].alloc = 1200;
+    f2.profile.children[0].children[1].alloc = 800;
+
+    let diffs = share_diffs(&f1, &f2, Metric::Alloc);
+    assert_eq!(diffs.len(), 4);
+    assert_eq!(diffs[0].name, "Main.f");
+    assert!((diffs[0].share_diff() - 16.15).abs() < 0.01);
+    assert_eq!(diffs[0].before, 300);
+    assert_eq!(diffs[0].after, 1200);
+    assert!(diffs[1..].iter().all(|diff| diff.share_diff() < 0.0));
+    // Biggest movers first, increases and decreases alike
+    assert!(diffs
+        .windows(2)
+        .all(|w| w[0].share_diff().abs() >= w[1].share_diff().abs()));
+
+    // Zero totals in the first profile
+    let mut f0: ProfFile = serde_json::from_str(super::TEST_PROFILE).unwrap();
+    f0.total_ticks = 0;
+    let mut out = vec![];
+    write_share_diffs(&f0, &f1, Metric::Ticks, &[], &[], &mut out);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\nTOTAL: 0 -> 100 (+100, new)\n"
+    );
+    let mut out = vec![];
+    write_share_diffs(&f0, &f0, Metric::Ticks, &[], &[], &mut out);
+    assert_eq!(
+        String::from_utf8(out).unwrap(),
+        "\nTOTAL: 0 -> 0 (+0, 1.00x)\n"
+    );
+}
@@ -0,0 +1,567 @@
+//! Types and parser for ticky-ticky profiles (`+RTS -r`), and comparison of two profiles
+//!
+//! A ticky report has a global counters section followed by a table of per-closure counters:
+//!
+//! ```text
+//! ENTERS: 1234 of which 1000 (81.0%) direct to the entry code
+//! ...
+//! ALLOC_HEAP_ctr                       3
+//! ALLOC_HEAP_tot                     112
+//!
+//! **************************************************
+//!
+//!     Entries      Alloc    Alloc'd  Non-void Arguments      STG Name
+//! --------------------------------------------------------------------------------
+//!          10        480          0   2 Li                  Main.$wgo{v r1ab} (fun)
+//!           1          0          0   0                     Main.main1{v r2cd} (fun)
+//! **************************************************
+//! ```
+//!
+//! Only `NAME value` lines of the counters section are parsed, the summary lines at the top are
+//! computed from those.
+//!
+//! Names of closures change between builds because of GHC uniques (`{v r1ab}`, `sat_s2Xf`), so
+//! closures are compared by `TickyClosure::key`, which strips these. Closures with the same key
+//! are added up in comparisons.
+//!
+//! Newer GHCs can also write ticky counters to the eventlog (`+RTS -lT`), see
+//! `eventlog::read_ticky`. These have per-closure samples, but no global counters.
+
+use crate::prof::{is_unique, strip_unique};
+use crate::utils::change;
+use crate::z_decode::z_decode;
+
+use std::collections::HashMap;
+use std::fmt;
+use std::io::Write;
+use std::str::FromStr;
+
+use regex::Regex;
+
+lazy_static! {
+    static ref COUNTER_RE: Regex = Regex::new(r"^\s*([A-Za-z_][A-Za-z0-9_]*)\s+(\d+)\s*$").unwrap();
+    static ref ANNOT_RE: Regex = Regex::new(r"\{[^}]*\}").unwrap();
+    static ref SYMBOL_RE: Regex = Regex::new(r"^[A-Za-z0-9_]+$").unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickyFile {
+    /// Global counters, in the order they appear in the report
+    pub counters: Vec<(String, u64)>,
+    /// Per-closure counters, in the order they appear in the report
+    pub closures: Vec<TickyClosure>,
+    /// Times of samples in nanoseconds, for profiles read from eventlogs
+    pub sample_times: Vec<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickyClosure {
+    /// Name as in the `STG Name` column, e.g. `Main.$wgo{v r1ab} (fun)`
+    pub name: String,
+    pub entries: u64,
+    /// Bytes allocated by the closure
+    pub alloc: u64,
+    /// Bytes allocated for the closure
+    pub allocd: u64,
+    /// Number of non-void arguments
+    pub arity: u32,
+    /// Kinds of the arguments, e.g. `Li` for a lifted and an unlifted argument
+    pub kinds: String,
+    /// Samples in which the closure was entered or allocated, for profiles read from eventlogs
+    pub samples: Vec<TickySample>,
+}
+
+/// Counts of a closure since the previous sample
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickySample {
+    /// In nanoseconds
+    pub time: u64,
+    pub entries: u64,
+    pub alloc: u64,
+    pub allocd: u64,
+}
+
+impl TickyFile {
+    pub fn counter(&self, name: &str) -> Option<u64> {
+        self.counters
+            .iter()
+            .find(|(counter, _)| counter == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+impl TickyClosure {
+    /// Name for matching closures of different builds: z-decoded, without `{...}` annotations
+    /// and GHC uniques
+    pub fn key(&self) -> String {
+        closure_key(&self.name)
+    }
+
+    pub fn get(&self, metric: TickyMetric) -> u64 {
+        match metric {
+            TickyMetric::Entries => self.entries,
+            TickyMetric::Alloc => self.alloc,
+            TickyMetric::Allocd => self.allocd,
+        }
+    }
+}
+
+impl TickySample {
+    pub fn get(&self, metric: TickyMetric) -> u64 {
+        match metric {
+            TickyMetric::Entries => self.entries,
+            TickyMetric::Alloc => self.alloc,
+            TickyMetric::Allocd => self.allocd,
+        }
+    }
+}
+
+fn closure_key(name: &str) -> String {
+    let name = ANNOT_RE.replace_all(name, "");
+    let mut words: Vec<String> = vec![];
+    let mut after_in = false;
+    for word in name.split_whitespace() {
+        // Parent closures given as a bare unique: `sat_s1 (Main) (thk) in r1ab`
+        if after_in && is_unique(word) {
+            words.pop();
+            after_in = false;
+            continue;
+        }
+        after_in = word == "in";
+        let word = if words.is_empty() && SYMBOL_RE.is_match(word) {
+            z_decode(word).unwrap_or_else(|| word.to_owned())
+        } else {
+            word.to_owned()
+        };
+        words.push(strip_unique(&word).into_owned());
+    }
+    words.join(" ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TickyMetric {
+    Entries,
+    Alloc,
+    Allocd,
+}
+
+impl FromStr for TickyMetric {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TickyMetric, String> {
+        match s {
+            "entries" => Ok(TickyMetric::Entries),
+            "alloc" => Ok(TickyMetric::Alloc),
+            "allocd" => Ok(TickyMetric::Allocd),
+            _ => Err(format!("Unknown metric: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for TickyMetric {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            TickyMetric::Entries => "entries",
+            TickyMetric::Alloc => "alloc",
+            TickyMetric::Allocd => "allocd",
+        })
+    }
+}
+
+/// Parses a ticky report. Errors include the line number.
+pub fn parse_ticky(s: &str) -> Result<TickyFile, String> {
+    let mut ticky = TickyFile {
+        counters: vec![],
+        closures: vec![],
+        sample_times: vec![],
+    };
+
+    // Column of `STG Name` in the table header, when in the table
+    let mut name_col: Option<usize> = None;
+
+    for (line_idx, line) in s.lines().enumerate() {
+        let err = |msg: String| format!("line {}: {}", line_idx + 1, msg);
+        match name_col {
+            None => {
+                if line.contains("Entries") && line.contains("STG Name") {
+                    name_col = line.find("STG Name");
+                } else if let Some(captures) = COUNTER_RE.captures(line) {
+                    let value = captures[2]
+                        .parse::<u64>()
+                        .map_err(|e| err(format!("{}: {}", &captures[2], e)))?;
+                    ticky.counters.push((captures[1].to_owned(), value));
+                }
+            }
+            Some(col) => {
+                let line = line.trim_end();
+                if line.starts_with('-') || line.is_empty() {
+                    continue;
+                }
+                if line.starts_with('*') {
+                    break;
+                }
+                ticky.closures.push(parse_closure(line, col).map_err(err)?);
+            }
+        }
+    }
+
+    Ok(ticky)
+}
+
+/// Byte offsets of the words in a line
+fn word_starts(line: &str) -> Vec<usize> {
+    let mut starts = vec![];
+    let mut prev_space = true;
+    for (idx, c) in line.char_indices() {
+        if !c.is_whitespace() && prev_space {
+            starts.push(idx);
+        }
+        prev_space = c.is_whitespace();
+    }
+    starts
+}
+
+fn parse_closure(line: &str, name_col: usize) -> Result<TickyClosure, String> {
+    let words = word_starts(line);
+    if words.len() < 5 {
+        return Err(format!("Not enough columns in: {}", line));
+    }
+    let number = |idx: usize, what: &str| -> Result<u64, String> {
+        let word = line[words[idx]..].split_whitespace().next().unwrap();
+        word.parse::<u64>()
+            .map_err(|e| format!("Unable to parse {}: {}: {}", what, word, e))
+    };
+    let entries = number(0, "entries")?;
+    let alloc = number(1, "alloc")?;
+    let allocd = number(2, "alloc'd")?;
+    let arity = number(3, "arity")? as u32;
+
+    // Argument kinds are optional. The name starts at the `STG Name` column, unless long
+    // argument kinds shifted it.
+    let name_start = if words[4..].contains(&name_col) {
+        name_col
+    } else if arity != 0 && words.len() > 5 {
+        words[5]
+    } else {
+        words[4]
+    };
+
+    Ok(TickyClosure {
+        name: line[name_start..].trim().to_owned(),
+        entries,
+        alloc,
+        allocd,
+        arity,
+        kinds: line[words[4]..name_start].trim().to_owned(),
+        samples: vec![],
+    })
+}
+
+pub fn parse_ticky_file(path: &str) -> TickyFile {
+    let contents = std::fs::read_to_string(path).unwrap();
+    match parse_ticky(&contents) {
+        Ok(ticky) => ticky,
+        Err(err) => {
+            eprintln!("Unable to parse {}: {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Writes the `n` closures with the largest values of the metric
+pub fn write_ticky<W: Write>(ticky: &TickyFile, metric: TickyMetric, n: usize, w: &mut W) {
+    let total: u64 = ticky.closures.iter().map(|c| c.get(metric)).sum();
+    writeln!(w, "total {}: {}", metric, total).unwrap();
+    writeln!(w).unwrap();
+
+    let mut closures: Vec<&TickyClosure> = ticky.closures.iter().collect();
+    closures.sort_by_key(|c| std::cmp::Reverse(c.get(metric)));
+    writeln!(w, "entries\talloc\talloc'd\targs\tname").unwrap();
+    for c in closures.into_iter().take(n) {
+        writeln!(
+            w,
+            "{}\t{}\t{}\t{} {}\t{}",
+            c.entries, c.alloc, c.allocd, c.arity, c.kinds, c.name
+        )
+        .unwrap();
+    }
+}
+
+/// Writes the metric of the `n` closures with the largest totals in each sample, one line per
+/// sample. Only profiles read from eventlogs have samples.
+pub fn write_ticky_series<W: Write>(ticky: &TickyFile, metric: TickyMetric, n: usize, w: &mut W) {
+    let mut closures: Vec<&TickyClosure> = ticky.closures.iter().collect();
+    closures.sort_by_key(|c| std::cmp::Reverse(c.get(metric)));
+    closures.truncate(n);
+
+    write!(w, "time (s)").unwrap();
+    for c in &closures {
+        write!(w, "\t{}", c.name).unwrap();
+    }
+    writeln!(w).unwrap();
+
+    // Index of the next sample of each closure
+    let mut next = vec![0; closures.len()];
+    for &time in &ticky.sample_times {
+        write!(w, "{:.6}", (time as f64) / 1_000_000_000.0).unwrap();
+        for (c, next) in closures.iter().zip(next.iter_mut()) {
+            let value = match c.samples.get(*next) {
+                Some(sample) if sample.time == time => {
+                    *next += 1;
+                    sample.get(metric)
+                }
+                _ => 0,
+            };
+            write!(w, "\t{}", value).unwrap();
+        }
+        writeln!(w).unwrap();
+    }
+}
+
+/// Change in the counters of closures with the same key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TickyDiff {
+    pub key: String,
+    /// Entries, alloc and alloc'd before
+    pub before: [u64; 3],
+    /// Entries, alloc and alloc'd after
+    pub after: [u64; 3],
+}
+
+impl TickyDiff {
+    fn index(metric: TickyMetric) -> usize {
+        match metric {
+            TickyMetric::Entries => 0,
+            TickyMetric::Alloc => 1,
+            TickyMetric::Allocd => 2,
+        }
+    }
+
+    pub fn diff(&self, metric: TickyMetric) -> i64 {
+        let idx = TickyDiff::index(metric);
+        (self.after[idx] as i64) - (self.before[idx] as i64)
+    }
+}
+
+fn closure_totals(ticky: &TickyFile) -> HashMap<String, [u64; 3]> {
+    let mut totals: HashMap<String, [u64; 3]> = HashMap::new();
+    for c in &ticky.closures {
+        let total = totals.entry(c.key()).or_default();
+        total[0] += c.entries;
+        total[1] += c.alloc;
+        total[2] += c.allocd;
+    }
+    totals
+}
+
+/// Matches closures of two profiles by key and returns the ones whose counters changed, biggest
+/// (absolute) change in the metric first
+pub fn ticky_diffs(t1: &TickyFile, t2: &TickyFile, metric: TickyMetric) -> Vec<TickyDiff> {
+    let totals1 = closure_totals(t1);
+    let mut totals2 = closure_totals(t2);
+
+    let mut diffs: Vec<TickyDiff> = vec![];
+    for (key, before) in totals1 {
+        let after = totals2.remove(&key).unwrap_or_default();
+        if before != after {
+            diffs.push(TickyDiff { key, before, after });
+        }
+    }
+    for (key, after) in totals2 {
+        if after != [0; 3] {
+            diffs.push(TickyDiff {
+                key,
+                before: [0; 3],
+                after,
+            });
+        }
+    }
+
+    diffs.sort_by(|d1, d2| {
+        d2.diff(metric)
+            .abs()
+            .cmp(&d1.diff(metric).abs())
+            .then_with(|| d1.key.cmp(&d2.key))
+    });
+    diffs
+}
+
+/// Shows changed global counters, and the `n` closures with the biggest changes in the metric
+pub fn write_ticky_diff<W: Write>(
+    t1: &TickyFile,
+    t2: &TickyFile,
+    metric: TickyMetric,
+    n: usize,
+    w: &mut W,
+) {
+    let total = |t: &TickyFile| -> u64 { t.closures.iter().map(|c| c.get(metric)).sum() };
+    let (total1, total2) = (total(t1), total(t2));
+    writeln!(
+        w,
+        "total {}: {} -> {} ({:+}, {})",
+        metric,
+        total1,
+        total2,
+        (total2 as i64) - (total1 as i64),
+        change(total1 as f64, total2 as f64)
+    )
+    .unwrap();
+
+    // Counters of the first report, then counters only in the second report
+    let counters = t1
+        .counters
+        .iter()
+        .map(|(name, value1)| (name, *value1, t2.counter(name).unwrap_or(0)))
+        .chain(
+            t2.counters
+                .iter()
+                .filter(|(name, _)| t1.counter(name).is_none())
+                .map(|(name, value2)| (name, 0, *value2)),
+        );
+    let mut counters_shown = false;
+    for (name, value1, value2) in counters {
+        if value1 != value2 {
+            if !counters_shown {
+                writeln!(w).unwrap();
+                counters_shown = true;
+            }
+            writeln!(
+                w,
+                "{}: {} -> {} ({})",
+                name,
+                value1,
+                value2,
+                change(value1 as f64, value2 as f64)
+            )
+            .unwrap();
+        }
+    }
+    writeln!(w).unwrap();
+
+    writeln!(w, "before\tafter\tdiff\tchange\tname").unwrap();
+    let idx = TickyDiff::index(metric);
+    for diff in ticky_diffs(t1, t2, metric).into_iter().take(n) {
+        if diff.diff(metric) == 0 {
+            break;
+        }
+        writeln!(
+            w,
+            "{}\t{}\t{:+}\t{}\t{}",
+            diff.before[idx],
+            diff.after[idx],
+            diff.diff(metric),
+            change(diff.before[idx] as f64, diff.after[idx] as f64),
+            diff.key
+        )
+        .unwrap();
+    }
+}
+
+#[cfg(test)]
+const TEST_TICKY: &str = "\
+'./Main +RTS -rMain.ticky' ticky-ticky statistics
+
+ENTERS: 11 of which 11 (100.0%) direct to the entry code
+\t\t  [the rest indirected via Node's info ptr]
+       1 (  9.1%) thunks
+      10 ( 90.9%) function values
+
+ALLOC_HEAP_ctr                       3
+ALLOC_HEAP_tot                     112
+ENT_VIA_NODE_ctr                     0
+
+The following table is explained by https://gitlab.haskell.org/ghc/ghc/wikis/debugging/ticky-ticky
+All allocation numbers are in bytes.
+
+**************************************************
+
+    Entries      Alloc    Alloc'd  Non-void Arguments      STG Name
+--------------------------------------------------------------------------------
+         10        480          0   2 Li                  Main.$wgo{v r1ab} (fun)
+          1          0         32   0                     sat_s2Xf{v} (Main) (thk) in r1ab
+          1         16          0   0                     Main_zdwloop
+**************************************************
+";
+
+#[test]
+fn parse_test() {
+    let ticky = parse_ticky(TEST_TICKY).unwrap();
+    assert_eq!(
+        ticky.counters,
+        vec![
+            ("ALLOC_HEAP_ctr".to_owned(), 3),
+            ("ALLOC_HEAP_tot".to_owned(), 112),
+            ("ENT_VIA_NODE_ctr".to_owned(), 0),
+        ]
+    );
+    assert_eq!(ticky.counter("ALLOC_HEAP_tot"), Some(112));
+    assert_eq!(ticky.closures.len(), 3);
+    assert_eq!(
+        ticky.closures[0],
+        TickyClosure {
+            name: "Main.$wgo{v r1ab} (fun)".to_owned(),
+            entries: 10,
+            alloc: 480,
+            allocd: 0,
+            arity: 2,
+            kinds: "Li".to_owned(),
+            samples: vec![],
+        }
+    );
+    assert_eq!(ticky.closures[1].kinds, "");
+    assert_eq!(ticky.closures[1].allocd, 32);
+    assert_eq!(ticky.closures[1].name, "sat_s2Xf{v} (Main) (thk) in r1ab");
+
+    assert!(parse_ticky(&TEST_TICKY.replace("480", "4x0")).is_err());
+}
+
+#[test]
+fn key_test() {
+    assert_eq!(closure_key("Main.$wgo{v r1ab} (fun)"), "Main.$wgo (fun)");
+    assert_eq!(
+        closure_key("sat_s2Xf{v} (Main) (thk) in r1ab"),
+        "sat (Main) (thk)"
+    );
+    assert_eq!(
+        closure_key("sat_s2Xf{v} (Main) (thk) in Main.go"),
+        "sat (Main) (thk) in Main.go"
+    );
+    assert_eq!(closure_key("Main_zdwloop"), "Main_$wloop");
+    assert_eq!(
+        closure_key("Main.parse_result{v r3} (fun)"),
+        "Main.parse_result (fun)"
+    );
+}
+
+#[test]
+fn diff_test() {
+    let t1 = parse_ticky(TEST_TICKY).unwrap();
+    let t2 = parse_ticky(
+        &TEST_TICKY
+            .replace("Main.$wgo{v r1ab}", "Main.$wgo{v r9zz}")
+            .replace("480", " 80")
+            .replace("sat_s2Xf", "sat_s3Yg"),
+    )
+    .unwrap();
+    let diffs = ticky_diffs(&t1, &t2, TickyMetric::Alloc);
+    assert_eq!(
+        diffs,
+        vec![TickyDiff {
+            key: "Main.$wgo (fun)".to_owned(),
+            before: [10, 480, 0],
+            after: [10, 80, 0],
+        }]
+    );
+    assert_eq!(diffs[0].diff(TickyMetric::Alloc), -400);
+
+    // Global counters only in the second report are shown too
+    let t2 = parse_ticky(&TEST_TICKY.replace(
+        "ENT_VIA_NODE_ctr                     0",
+        "ENT_VIA_NODE_ctr                     0\nALLOC_FUN_ctr                        2",
+    ))
+    .unwrap();
+    let mut out = vec![];
+    write_ticky_diff(&t1, &t2, TickyMetric::Alloc, 10, &mut out);
+    let out = String::from_utf8(out).unwrap();
+    assert!(out.contains("\nALLOC_FUN_ctr: 0 -> 2 (new)\n"));
+}
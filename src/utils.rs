@@ -0,0 +1,43 @@
+//! Formatting helpers shared by the reports
+
+/// `cost` as a percentage of `total`, zero if `total` is zero
+pub fn percentage(cost: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        (cost as f64) / (total as f64) * 100.0
+    }
+}
+
+/// Signed difference from `before` to `after`
+pub fn delta(before: u64, after: u64) -> i64 {
+    (after as i64) - (before as i64)
+}
+
+/// Change from `before` to `after` in percent, `None` if `before` is zero
+pub fn percent_change(before: f64, after: f64) -> Option<f64> {
+    if before == 0.0 {
+        None
+    } else {
+        Some((after - before) / before * 100.0)
+    }
+}
+
+/// Change from `before` to `after` in percent, for reports. Change from zero is "+0.00%" when
+/// `after` is also zero, "new" otherwise.
+pub fn change(before: f64, after: f64) -> String {
+    match percent_change(before, after) {
+        Some(change) => format!("{:+.2}%", change),
+        None if after == 0.0 => "+0.00%".to_owned(),
+        None => "new".to_owned(),
+    }
+}
+
+#[test]
+fn change_test() {
+    assert_eq!(change(200.0, 250.0), "+25.00%");
+    assert_eq!(change(200.0, 0.0), "-100.00%");
+    assert_eq!(change(0.0, 0.0), "+0.00%");
+    assert_eq!(change(0.0, 10.0), "new");
+    assert_eq!(percent_change(0.0, 10.0), None);
+}
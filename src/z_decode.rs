@@ -1,5 +1,13 @@
 use std::convert::TryFrom;
 
+use regex::Regex;
+
+lazy_static! {
+    static ref SYMBOL_RE: Regex =
+        Regex::new(r"^[A-Za-z0-9_]+_(con_info|info|closure|entry)$").unwrap();
+    static ref SYMBOL_SUFFIX_RE: Regex = Regex::new(r"_(con_info|info|closure|entry)$").unwrap();
+}
+
 pub fn z_decode(s: &str) -> Option<String> {
     let mut ret = String::with_capacity(s.len());
     let mut chars = s.chars();
@@ -202,6 +210,30 @@ pub fn z_decode(s: &str) -> Option<String> {
     Some(ret)
 }
 
+/// Z-decodes names that are symbols, like `ghczmprim_GHCziTypes_ZC_con_info`, dropping the
+/// `_info`, `_closure` etc. suffix. Other names are returned as they are.
+pub(crate) fn decode_symbol(name: &str) -> String {
+    if SYMBOL_RE.is_match(name) {
+        let name = SYMBOL_SUFFIX_RE.replace(name, "");
+        z_decode(&name).unwrap_or_else(|| name.into_owned())
+    } else {
+        name.to_owned()
+    }
+}
+
+#[test]
+fn decode_symbol_test() {
+    assert_eq!(
+        decode_symbol("ghczmprim_GHCziTypes_ZC_con_info"),
+        "ghc-prim_GHC.Types_:"
+    );
+    assert_eq!(decode_symbol("Main_zdwgo_closure"), "Main_$wgo");
+    assert_eq!(
+        decode_symbol("Data.Map.Internal.Bin"),
+        "Data.Map.Internal.Bin"
+    );
+}
+
 #[test]
 fn decode_test() {
     assert_eq!(z_decode("ZL"), Some("(".to_string()));